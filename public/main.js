@@ -8,6 +8,9 @@ const I18nTable = JSON.parse(`
     "edit-download": "Download",
     "edit-has-audio": "Has audio",
     "edit-assign-audio": "Assign audio",
+    "edit-trim-start": "Cut before here",
+    "edit-trim-end": "Cut after here",
+    "edit-trim-reset": "Keep all audio",
     "main-description": "Easily turn your presentation pdf into a narrated video.",
     "main-drag-drop": "Drag&Drop a .pdf file",
     "main-go": " Go "
@@ -20,6 +23,9 @@ const I18nTable = JSON.parse(`
     "edit-download": "Download",
     "edit-has-audio": "Hat Audio",
     "edit-assign-audio": "Audio zuweisen",
+    "edit-trim-start": "Davor abschneiden",
+    "edit-trim-end": "Danach abschneiden",
+    "edit-trim-reset": "Ganzes Audio behalten",
     "main-description": "Mach ein Video aus einer pdf Präsentation.",
     "main-drag-drop": "Eine .pdf Datei hierher ziehen",
     "main-go": " Start "
@@ -64,13 +70,17 @@ const Global = {
     try {
       const response = await request;
       if (response.status >= 300) {
-        throw '';
+        throw await apiError(response);
       }
 
       const data = await response.json();
       this.project = data;
     } catch (e) {
       console.log(e);
+      // Not having a project yet is expected, everything else is worth telling.
+      if (e.code && e.code != 'no_such_project') {
+        window.alert(e.detail ? e.message + '\n\n' + e.detail : e.message);
+      }
       throw e;
     }
 
@@ -119,6 +129,42 @@ const Global = {
       }));
     };
 
+    /* The trim points are stored with the project, the audio file stays untouched. */
+    const trimTo = async function(start, end) {
+      if (isNaN(projectForHandler.selectedPageIdx)) {
+        console.log('no page selected');
+        return;
+      }
+
+      const params = new URLSearchParams();
+      if (start !== null) {
+        params.set('start', start);
+      }
+      if (end !== null) {
+        params.set('end', end);
+      }
+
+      const trimUrl = '/project/page/' + projectForHandler.selectedPageIdx + '/trim?' + params;
+      await projectForHandler.loadFromRequest(fetch(trimUrl, { method: 'post' }));
+    };
+
+    const currentTrim = function() {
+      const el = projectForHandler.project.pages[projectForHandler.selectedPageIdx];
+      return (el && el.trim) ? el.trim : { start: 0, end: null };
+    };
+
+    this.mainEl.querySelector('#trimStart').onclick = async function() {
+      await trimTo(audio.currentTime, currentTrim().end);
+    };
+
+    this.mainEl.querySelector('#trimEnd').onclick = async function() {
+      await trimTo(currentTrim().start, audio.currentTime);
+    };
+
+    this.mainEl.querySelector('#trimReset').onclick = async function() {
+      await trimTo(null, null);
+    };
+
     this.project.pages.forEach((el, idx) => {
       const listItem = this.templatePage.content.querySelector('.page-list-item').cloneNode(true);
       const preview = listItem.querySelector('.page-preview');
@@ -129,13 +175,27 @@ const Global = {
         projectForHandler.updateSelectedPageState();
       };
 
-      preview.querySelector('img').src = el.img_url;
+      preview.querySelector('img').src = el.thumb_url + '?w=320';
+      if (el.error) {
+        listItem.classList.add('page-failed');
+        listItem.title = el.error;
+      }
       pageList.appendChild(listItem);
       if (el.audio_url) {
         audioIndicator.classList.add('page-audio-yes');
       } else {
         audioIndicator.classList.add('page-audio-no');
       }
+      // Barely any speech, or speaking until the very end as if cut off.
+      if (el.speech && !el.error) {
+        if (el.speech.spoken < 0.5) {
+          listItem.classList.add('page-audio-suspect');
+          listItem.title = 'The audio is probably empty';
+        } else if (el.speech.trailing < 0.1) {
+          listItem.classList.add('page-audio-suspect');
+          listItem.title = 'The audio is probably cut off';
+        }
+      }
     });
 
     const create = this.mainEl.querySelector('#createVideo');
@@ -209,6 +269,19 @@ const Global = {
   }
 };
 
+/* Turn the json body of a failed request into an exception. */
+async function apiError(response) {
+  try {
+    const body = await response.json();
+    const error = new Error(body.message);
+    error.code = body.code;
+    error.detail = body.detail;
+    return error;
+  } catch (e) {
+    return new Error('Request failed with status ' + response.status);
+  }
+}
+
 window.onload = function() {
   const global = Global.init();
   if (window.location.pathname == '/') {
@@ -0,0 +1,461 @@
+//! The routes of the web server, and their description as an OpenAPI document.
+//!
+//! The frontend works with the project of its session. Scripts instead use the routes of `API`
+//! which name the project explicitly and authorize with the token handed out on creation. Only
+//! the latter are documented, the frontend may change along with the server.
+use serde_json::{json, Map, Value};
+use tide::http::Method;
+
+/// A handler of the web server.
+#[derive(Clone, Copy, Debug)]
+pub enum Endpoint {
+    Index,
+    Static,
+    Create,
+    ApiCreate,
+    Introspect,
+    Asset,
+    Render,
+    SetAudio,
+    AudioBatch,
+    Recording,
+    Trim,
+    Thumbnail,
+    Share,
+    Shared,
+    OpenApi,
+    Health,
+    Ready,
+}
+
+pub struct Route {
+    pub method: Method,
+    /// The path, in the syntax of `tide`.
+    pub path: &'static str,
+    pub endpoint: Endpoint,
+    pub doc: Doc,
+}
+
+/// What the OpenAPI document says about a route.
+pub struct Doc {
+    pub summary: &'static str,
+    /// The content type of the request body, if any.
+    pub body: Option<&'static str>,
+    /// The status of a successful response.
+    pub status: u16,
+    /// The schema of a successful json response, or `None` for a file download.
+    pub schema: Option<&'static str>,
+    /// If the project token is required.
+    pub token: bool,
+}
+
+/// Routes serving the frontend with the project of the session.
+pub const SESSION: &[Route] = &[
+    Route::new(Method::Get, "/", Endpoint::Index),
+    // FIXME: restore the session state to that id.
+    Route::new(Method::Get, "/project/edit/:id", Endpoint::Index),
+    Route::new(Method::Put, "/project/new", Endpoint::Create),
+    Route::new(Method::Get, "/project/get", Endpoint::Introspect),
+    Route::new(Method::Get, "/project/asset/:asset", Endpoint::Asset),
+    Route::new(Method::Post, "/project/render", Endpoint::Render),
+    Route::new(Method::Post, "/project/share", Endpoint::Share),
+    Route::new(Method::Put, "/project/page/:num", Endpoint::SetAudio),
+    Route::new(Method::Post, "/project/audio-batch", Endpoint::AudioBatch),
+    Route::new(Method::Post, "/project/recording", Endpoint::Recording),
+    Route::new(Method::Post, "/project/page/:num/trim", Endpoint::Trim),
+    Route::new(Method::Get, "/project/page/:num/thumb", Endpoint::Thumbnail),
+    Route::new(Method::Get, "/static/*", Endpoint::Static),
+];
+
+/// Routes for clients without a session.
+pub const API: &[Route] = &[
+    Route {
+        method: Method::Put,
+        path: "/api/project/new",
+        endpoint: Endpoint::ApiCreate,
+        doc: Doc {
+            summary: "Create a project from a pdf, one slide per page.",
+            body: Some("application/pdf"),
+            status: 201,
+            schema: Some("Created"),
+            token: false,
+        },
+    },
+    Route {
+        method: Method::Get,
+        path: "/api/project/:project",
+        endpoint: Endpoint::Introspect,
+        doc: Doc {
+            summary: "Get the state of a project.",
+            body: None,
+            status: 201,
+            schema: Some("Project"),
+            token: true,
+        },
+    },
+    Route {
+        method: Method::Get,
+        path: "/api/project/:project/asset/:asset",
+        endpoint: Endpoint::Asset,
+        doc: Doc {
+            summary: "Download a file of the project, as linked from its state.",
+            body: None,
+            status: 200,
+            schema: None,
+            token: true,
+        },
+    },
+    Route {
+        method: Method::Post,
+        path: "/api/project/:project/render",
+        endpoint: Endpoint::Render,
+        doc: Doc {
+            summary: "Render the video, linked as `output` in the resulting state. While waiting \
+                for other renders, the state of the project has the position in `queued`.",
+            body: None,
+            status: 201,
+            schema: Some("Project"),
+            token: true,
+        },
+    },
+    Route {
+        method: Method::Put,
+        path: "/api/project/:project/page/:num",
+        endpoint: Endpoint::SetAudio,
+        doc: Doc {
+            summary: "Set the narration of a page, in any format ffmpeg understands.",
+            body: Some("application/octet-stream"),
+            status: 201,
+            schema: Some("Project"),
+            token: true,
+        },
+    },
+    Route {
+        method: Method::Post,
+        path: "/api/project/:project/audio-batch",
+        endpoint: Endpoint::AudioBatch,
+        doc: Doc {
+            summary: "Set the narration of many pages from a zip of files such as `slide-01.wav`.",
+            body: Some("application/zip"),
+            status: 201,
+            schema: Some("Project"),
+            token: true,
+        },
+    },
+    Route {
+        method: Method::Post,
+        path: "/api/project/:project/recording",
+        endpoint: Endpoint::Recording,
+        doc: Doc {
+            summary: "Split one narration of all enabled slides at the longest pauses, or at `markers`.",
+            body: Some("application/octet-stream"),
+            status: 201,
+            schema: Some("Project"),
+            token: true,
+        },
+    },
+    Route {
+        method: Method::Post,
+        path: "/api/project/:project/page/:num/trim",
+        endpoint: Endpoint::Trim,
+        doc: Doc {
+            summary: "Play only the narration of a page between `start` and `end`, or all of it without both.",
+            body: None,
+            status: 201,
+            schema: Some("Project"),
+            token: true,
+        },
+    },
+    Route {
+        method: Method::Get,
+        path: "/api/project/:project/page/:num/thumb",
+        endpoint: Endpoint::Thumbnail,
+        doc: Doc {
+            summary: "Download a jpeg preview of a page, `w` pixels wide.",
+            body: None,
+            status: 200,
+            schema: None,
+            token: true,
+        },
+    },
+    Route {
+        method: Method::Post,
+        path: "/api/project/:project/share",
+        endpoint: Endpoint::Share,
+        doc: Doc {
+            summary: "Create a read-only link to the rendered video, valid for `hours`.",
+            body: None,
+            status: 201,
+            schema: Some("ShareLink"),
+            token: true,
+        },
+    },
+    Route {
+        method: Method::Get,
+        path: "/share/:link",
+        endpoint: Endpoint::Shared,
+        doc: Doc {
+            summary: "Download the video of a share link.",
+            body: None,
+            status: 200,
+            schema: None,
+            token: false,
+        },
+    },
+    Route {
+        method: Method::Get,
+        path: "/healthz",
+        endpoint: Endpoint::Health,
+        doc: Doc {
+            summary: "Versions of the tools, free disk space and the number of running and queued jobs.",
+            body: None,
+            status: 200,
+            schema: Some("Health"),
+            token: false,
+        },
+    },
+    Route {
+        method: Method::Get,
+        path: "/readyz",
+        endpoint: Endpoint::Ready,
+        doc: Doc {
+            summary: "As `/healthz` but fails with 503 while new projects can not be accepted.",
+            body: None,
+            status: 200,
+            schema: Some("Health"),
+            token: false,
+        },
+    },
+    Route {
+        method: Method::Get,
+        path: "/api/openapi.json",
+        endpoint: Endpoint::OpenApi,
+        doc: Doc {
+            summary: "This document.",
+            body: None,
+            status: 200,
+            schema: None,
+            token: false,
+        },
+    },
+];
+
+impl Route {
+    /// An undocumented route.
+    const fn new(method: Method, path: &'static str, endpoint: Endpoint) -> Self {
+        Route {
+            method,
+            path,
+            endpoint,
+            doc: Doc {
+                summary: "",
+                body: None,
+                status: 200,
+                schema: None,
+                token: false,
+            },
+        }
+    }
+
+    /// Convert `:name` segments to `{name}`.
+    fn openapi_path(&self) -> String {
+        self.path
+            .split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(name) => format!("{{{}}}", name),
+                None => segment.to_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        let mut parameters: Vec<_> = self.path
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix(':'))
+            .map(|name| json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": { "type": "string" },
+            }))
+            .collect();
+
+        let query = match self.endpoint {
+            Endpoint::Share => vec![("hours", json!({ "type": "integer", "minimum": 0 }))],
+            Endpoint::Thumbnail => vec![("w", json!({ "type": "integer", "minimum": 0 }))],
+            Endpoint::Recording => vec![("markers", json!({
+                "type": "string",
+                "description": "Where each following slide begins, such as `75,2:05.5`",
+            }))],
+            Endpoint::Trim => vec![
+                ("start", json!({ "type": "number", "minimum": 0, "description": "Seconds" })),
+                ("end", json!({ "type": "number", "description": "Seconds from the start of the file" })),
+            ],
+            _ => vec![],
+        };
+
+        for (name, schema) in query {
+            parameters.push(json!({
+                "name": name,
+                "in": "query",
+                "required": false,
+                "schema": schema,
+            }));
+        }
+
+        parameters
+    }
+
+    fn operation(&self) -> Value {
+        let doc = &self.doc;
+        let success = match doc.schema {
+            Some(schema) => json!({
+                "description": "Success",
+                "content": {
+                    "application/json": {
+                        "schema": { "$ref": format!("#/components/schemas/{}", schema) },
+                    },
+                },
+            }),
+            None => json!({ "description": "The file" }),
+        };
+
+        let mut operation = json!({
+            "summary": doc.summary,
+            "parameters": self.parameters(),
+            "responses": {
+                (doc.status.to_string()): success,
+                "default": {
+                    "description": "Failure",
+                    "content": {
+                        "application/json": {
+                            "schema": { "$ref": "#/components/schemas/Error" },
+                        },
+                    },
+                },
+            },
+        });
+
+        if let Some(mime) = doc.body {
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": { (mime): {} },
+            });
+        }
+
+        if doc.token {
+            operation["security"] = json!([{ "projectToken": [] }, { "bearerToken": [] }]);
+        }
+
+        operation
+    }
+}
+
+/// The OpenAPI document of all routes in `API`.
+pub fn openapi() -> Value {
+    let mut paths = Map::new();
+    for route in API {
+        let item = paths
+            .entry(route.openapi_path())
+            .or_insert_with(|| json!({}));
+        let method = route.method.to_string().to_lowercase();
+        item[method.as_str()] = route.operation();
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "vid-from-pdf",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "securitySchemes": {
+                "projectToken": { "type": "apiKey", "in": "header", "name": "X-Project-Token" },
+                "bearerToken": { "type": "http", "scheme": "bearer" },
+            },
+            "schemas": {
+                "Page": {
+                    "type": "object",
+                    "properties": {
+                        "img_url": { "type": "string", "nullable": true },
+                        "thumb_url": { "type": "string" },
+                        "audio_url": { "type": "string", "nullable": true },
+                        "error": { "type": "string", "nullable": true },
+                        "speech": {
+                            "nullable": true,
+                            "allOf": [{ "$ref": "#/components/schemas/Speech" }],
+                        },
+                        "trim": {
+                            "nullable": true,
+                            "allOf": [{ "$ref": "#/components/schemas/Trim" }],
+                        },
+                    },
+                },
+                "Trim": {
+                    "type": "object",
+                    "description": "The seconds of the narration that are played",
+                    "properties": {
+                        "start": { "type": "number" },
+                        "end": { "type": "number", "nullable": true },
+                    },
+                },
+                "Speech": {
+                    "type": "object",
+                    "description": "Seconds of silence around the narration and of speaking",
+                    "properties": {
+                        "leading": { "type": "number" },
+                        "trailing": { "type": "number" },
+                        "spoken": { "type": "number" },
+                    },
+                },
+                "Project": {
+                    "type": "object",
+                    "properties": {
+                        "identifier": { "type": "string" },
+                        "pages": { "type": "array", "items": { "$ref": "#/components/schemas/Page" } },
+                        "failed": { "type": "array", "items": { "type": "integer" } },
+                        "output": { "type": "string", "nullable": true },
+                        "queued": { "type": "integer", "nullable": true },
+                    },
+                },
+                "Created": {
+                    "allOf": [
+                        { "$ref": "#/components/schemas/Project" },
+                        {
+                            "type": "object",
+                            "properties": { "token": { "type": "string" } },
+                        },
+                    ],
+                },
+                "ShareLink": {
+                    "type": "object",
+                    "properties": {
+                        "url": { "type": "string" },
+                        "expires": { "type": "integer", "description": "Seconds since the epoch" },
+                    },
+                },
+                "Health": {
+                    "type": "object",
+                    "properties": {
+                        "ffmpeg": { "type": "string" },
+                        "magick": { "type": "string", "nullable": true },
+                        "pdf": { "type": "string" },
+                        "free_space": { "type": "integer", "nullable": true },
+                        "active_jobs": { "type": "integer" },
+                        "queued_jobs": { "type": "integer" },
+                    },
+                },
+                "Error": {
+                    "type": "object",
+                    "properties": {
+                        "code": { "type": "string" },
+                        "message": { "type": "string" },
+                        "detail": { "type": "string", "nullable": true },
+                    },
+                },
+            },
+        },
+    })
+}
@@ -1,13 +1,17 @@
 //! Thred-safe abstraction for a whole app.
 //!
 //! The goal is that it's easy to bind this to any web server implementation.
-use std::sync::{Arc, atomic::AtomicU64, atomic::Ordering};
+use std::{fmt, sync::{Arc, atomic::AtomicU64, atomic::Ordering}};
 use tempfile::TempDir;
 
+use crate::auth::Users;
 use crate::explode::ExplodePdf;
 use crate::ffmpeg::Ffmpeg;
+use crate::health::Jobs;
+use crate::queue::Queue;
 use crate::sink::SyncSink;
 use crate::resources::Resources;
+use crate::upload::Upload;
 
 pub struct App {
     pub ffmpeg: Ffmpeg,
@@ -16,6 +20,20 @@ pub struct App {
     pub sink: SyncSink,
     pub explode: Arc<dyn ExplodePdf>,
     pub limits: Limits,
+    /// How slides are placed in the video.
+    pub frame: Frame,
+    /// How long slides are shown, unless their project says otherwise.
+    pub slide_seconds: SlideSeconds,
+    /// How the video ends.
+    pub ending: Ending,
+    /// Stable key for signing session cookies, if sessions should be persisted.
+    pub session_key: Option<Vec<u8>>,
+    /// Users of the web server, if it requires authentication.
+    pub users: Option<Users>,
+    /// Explode and render jobs currently running.
+    pub jobs: Jobs,
+    /// Where videos are uploaded once rendered, if anywhere.
+    pub upload: Option<Upload>,
 }
 
 /// Application wide limits.
@@ -25,12 +43,116 @@ pub struct App {
 /// modified through a shared, sync reference.
 pub struct Limits {
     pub meta_size: AtomicU64,
+    /// Maximum size of a single uploaded file.
+    pub upload_size: AtomicU64,
+    /// Maximum size of an uploaded pdf, lower than `upload_size` as they are parsed right away.
+    pub pdf_size: AtomicU64,
+    /// Maximum number of pages of a project's pdf.
+    pub page_count: AtomicU64,
+    /// Maximum length of a rendered video, in seconds.
+    pub video_duration: AtomicU64,
+    /// Renders running at once, others wait.
+    pub renders: Queue,
+    /// Pdfs exploded at once, others wait.
+    pub explodes: Queue,
+    /// Size of the converted pages kept for converting the same pdf again, `0` to keep none.
+    pub cache_size: AtomicU64,
+}
+
+/// How slides are placed into the frames of the video, all of the same size.
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    /// Pixels kept free on each side of the slide.
+    pub padding: u32,
+    /// The color around the slide, where its aspect ratio differs from the video, as `0xRRGGBB`.
+    pub background: u32,
+    /// The shape of the video, chosen by the slides of each project if `None`.
+    pub geometry: Option<Geometry>,
+    /// Turn pages in portrait among landscape ones, instead of fitting them upright.
+    pub rotate: bool,
+}
+
+/// How long slides are shown whose audio is short or missing, so that they do not flash by.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SlideSeconds {
+    /// Slides with shorter audio are shown this long, with silence after the audio.
+    pub min: Option<f32>,
+    /// Slides without audio are shown this long.
+    pub default: Option<f32>,
+}
+
+/// How the video ends after the audio of the last slide, instead of cutting off.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Ending {
+    /// Seconds the last slide is shown for longer, in silence.
+    pub hold: f32,
+    /// Seconds of fading out the audio, at the very end. The last slide stays as it is.
+    pub fade: f32,
+}
+
+/// The supported shapes of the video.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Geometry {
+    /// 16:9, in 1920x1080.
+    Wide,
+    /// 4:3, in 1440x1080.
+    Standard,
+}
+
+/// What to redo when the pdf of the project changes on disk.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Watch {
+    /// Convert the pages again, keeping the narration of unchanged slides.
+    Pages,
+    /// Also generate the video again.
+    Render,
+}
+
+/// A project that can not be handled within the configured limits.
+#[derive(Debug)]
+pub enum LimitExceeded {
+    PageCount {
+        count: u64,
+        limit: u64,
+    },
+    VideoDuration {
+        seconds: u64,
+        limit: u64,
+    },
+    /// An uploaded pdf is larger than is parsed.
+    PdfSize {
+        limit: u64,
+    },
+    /// The content of an upload, after unpacking, is too large.
+    UploadSize {
+        limit: u64,
+    },
 }
 
 impl Limits {
     pub fn meta_size(&self) -> u64 {
         self.meta_size.load(Ordering::Relaxed)
     }
+
+    pub fn upload_size(&self) -> u64 {
+        self.upload_size.load(Ordering::Relaxed)
+    }
+
+    pub fn pdf_size(&self) -> u64 {
+        self.pdf_size.load(Ordering::Relaxed)
+    }
+
+    pub fn page_count(&self) -> u64 {
+        self.page_count.load(Ordering::Relaxed)
+    }
+
+    pub fn video_duration(&self) -> u64 {
+        self.video_duration.load(Ordering::Relaxed)
+    }
+
+    pub fn cache_size(&self) -> u64 {
+        self.cache_size.load(Ordering::Relaxed)
+    }
 }
 
 impl App {
@@ -41,7 +163,136 @@ impl App {
             magick: res.magick,
             sink: res.dir_as_sink.into(),
             explode: res.explode.into(),
-            limits: Limits::default(),
+            limits: res.limits,
+            frame: res.frame,
+            slide_seconds: res.slide_seconds,
+            ending: res.ending,
+            session_key: res.session_key,
+            users: res.users,
+            jobs: Jobs::default(),
+            upload: res.upload,
+        }
+    }
+}
+
+impl Frame {
+    /// The size of the video, the wide one until the geometry is chosen.
+    pub fn size(&self) -> (u32, u32) {
+        self.geometry.unwrap_or(Geometry::Wide).size()
+    }
+
+    /// The largest size of a slide, within the padding.
+    pub fn inner(&self) -> (u32, u32) {
+        let (width, height) = self.size();
+        (width - 2 * self.padding, height - 2 * self.padding)
+    }
+
+    /// If the padding leaves room for the slide in all geometries.
+    pub fn is_valid_padding(padding: u32) -> bool {
+        padding < 1080 / 2
+    }
+
+    /// The frame of a project whose slides have this aspect ratio, if known.
+    pub fn for_slides(self, aspect: Option<f32>) -> Self {
+        let detected = aspect.map(Geometry::closest);
+        Frame {
+            geometry: self.geometry.or(detected),
+            ..self
+        }
+    }
+
+    /// Parse a color such as `#1e1e1e`.
+    pub fn parse_color(value: &str) -> Option<u32> {
+        let hex = value.strip_prefix('#').unwrap_or(value);
+        if hex.len() != 6 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return None;
+        }
+        u32::from_str_radix(hex, 16).ok()
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Frame {
+            padding: 0,
+            background: 0x000000,
+            geometry: None,
+            rotate: false,
+        }
+    }
+}
+
+impl Ending {
+    pub fn is_valid(seconds: f32) -> bool {
+        seconds >= 0.0 && seconds.is_finite()
+    }
+}
+
+impl SlideSeconds {
+    pub fn is_valid(seconds: f32) -> bool {
+        seconds > 0.0 && seconds.is_finite()
+    }
+
+    /// Each setting, or the other one if not set.
+    pub fn or(self, other: Self) -> Self {
+        SlideSeconds {
+            min: self.min.or(other.min),
+            default: self.default.or(other.default),
+        }
+    }
+}
+
+impl Geometry {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "16:9" => Some(Geometry::Wide),
+            "4:3" => Some(Geometry::Standard),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Geometry::Wide => "16:9",
+            Geometry::Standard => "4:3",
+        }
+    }
+
+    pub fn size(self) -> (u32, u32) {
+        match self {
+            Geometry::Wide => (1920, 1080),
+            Geometry::Standard => (1440, 1080),
+        }
+    }
+
+    /// The geometry closest to slides of an aspect ratio, width by height.
+    pub fn closest(aspect: f32) -> Self {
+        if Geometry::Wide.mismatch(aspect) < Geometry::Standard.mismatch(aspect) {
+            Geometry::Wide
+        } else {
+            Geometry::Standard
+        }
+    }
+
+    /// If slides of an aspect ratio fill the video, except for small bars.
+    pub fn fits(self, aspect: f32) -> bool {
+        self.mismatch(aspect) < 0.05
+    }
+
+    /// How far the aspect ratio is off, relative to ours and in either direction.
+    fn mismatch(self, aspect: f32) -> f32 {
+        let (width, height) = self.size();
+        let ratio = aspect / (width as f32 / height as f32);
+        ratio.max(1.0 / ratio) - 1.0
+    }
+}
+
+impl Watch {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "pages" => Some(Watch::Pages),
+            "render" => Some(Watch::Render),
+            _ => None,
         }
     }
 }
@@ -50,10 +301,61 @@ impl Default for Limits {
     fn default() -> Self {
         Limits {
             meta_size: AtomicU64::new(2_000_000),
+            upload_size: AtomicU64::new(256_000_000),
+            pdf_size: AtomicU64::new(100_000_000),
+            page_count: AtomicU64::new(500),
+            video_duration: AtomicU64::new(4*60*60),
+            renders: Queue::new(1),
+            explodes: Queue::new(2),
+            cache_size: AtomicU64::new(1_000_000_000),
         }
     }
 }
 
+impl Clone for Limits {
+    fn clone(&self) -> Self {
+        Limits {
+            meta_size: AtomicU64::new(self.meta_size()),
+            upload_size: AtomicU64::new(self.upload_size()),
+            pdf_size: AtomicU64::new(self.pdf_size()),
+            page_count: AtomicU64::new(self.page_count()),
+            video_duration: AtomicU64::new(self.video_duration()),
+            renders: Queue::new(self.renders.capacity()),
+            explodes: Queue::new(self.explodes.capacity()),
+            cache_size: AtomicU64::new(self.cache_size()),
+        }
+    }
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitExceeded::PageCount { count, limit } => write!(
+                f,
+                "The pdf has {} pages but at most {} are supported.",
+                count, limit,
+            ),
+            LimitExceeded::VideoDuration { seconds, limit } => write!(
+                f,
+                "The video would be {} seconds long but at most {} seconds are supported.",
+                seconds, limit,
+            ),
+            LimitExceeded::PdfSize { limit } => write!(
+                f,
+                "The pdf is larger than the limit of {} bytes.",
+                limit,
+            ),
+            LimitExceeded::UploadSize { limit } => write!(
+                f,
+                "The unpacked upload is larger than the limit of {} bytes.",
+                limit,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
 const _: () = {
     fn only_if_send_and_sync<T: Send + Sync>() {}
     let _ = only_if_send_and_sync::<App>;
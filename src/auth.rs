@@ -0,0 +1,90 @@
+//! Optional authentication for sharing one instance between several users.
+use std::{collections::HashMap, fs, io, path::Path};
+
+use crate::FatalError;
+use crate::sink::Sink;
+
+/// Users allowed to access the web server, with their passwords.
+pub struct Users {
+    passwords: HashMap<String, String>,
+}
+
+/// The authenticated user of a request.
+#[derive(Clone)]
+pub struct User {
+    pub name: String,
+}
+
+impl Users {
+    /// Read a file of `name:password` lines.
+    ///
+    /// Empty lines and lines starting with `#` are ignored. Names are used as directory names and
+    /// must consist of alphanumeric characters, `-`, `_`, `.` and `@` only.
+    pub fn from_file(path: &Path) -> Result<Self, io::Error> {
+        let content = fs::read_to_string(path)?;
+        let mut passwords = HashMap::new();
+
+        for (nr, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let bad_line = |reason: &str| io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path.display(), nr + 1, reason),
+            );
+
+            let pos = line.find(':').ok_or_else(|| bad_line("expected `name:password`"))?;
+            let (name, password) = (&line[..pos], &line[pos+1..]);
+
+            if !User::is_valid_name(name) {
+                return Err(bad_line("invalid user name"));
+            }
+
+            if password.is_empty() {
+                return Err(bad_line("empty password"));
+            }
+
+            passwords.insert(name.to_owned(), password.to_owned());
+        }
+
+        Ok(Users { passwords })
+    }
+
+    /// Check the value of a basic `Authorization` header.
+    pub fn authenticate(&self, authorization: &str) -> Option<User> {
+        let encoded = authorization.strip_prefix("Basic ")?;
+        let decoded = base64::decode(encoded.trim()).ok()?;
+        let decoded = String::from_utf8(decoded).ok()?;
+        let pos = decoded.find(':')?;
+        let (name, password) = (&decoded[..pos], &decoded[pos+1..]);
+
+        let expected = self.passwords.get(name)?;
+        if constant_time_eq(expected, password) {
+            Some(User { name: name.to_owned() })
+        } else {
+            None
+        }
+    }
+}
+
+impl User {
+    fn is_valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && !name.starts_with('.')
+            && name.chars().all(|ch| ch.is_ascii_alphanumeric() || "-_.@".contains(ch))
+    }
+
+    /// The part of the sink in which the projects of this user live.
+    pub fn namespace(&self, sink: &Sink) -> Result<Sink, FatalError> {
+        let path = sink.work_dir().join(".users").join(&self.name);
+        fs::create_dir_all(&path)?;
+        sink.nested(path)
+    }
+}
+
+/// Compare secrets without leaking the length of a common prefix through timing.
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,115 @@
+//! Converted pages kept across projects, so that the same pdf is not converted twice.
+//!
+//! Entries are named by the hash of everything that went into them: the pdf, the page, the size
+//! it was converted to and the backend that did it. Projects get a hard link to an entry, so
+//! evicting it does not touch any project.
+use std::{fs, io, path::Path, path::PathBuf};
+use sha2::{Digest as _, Sha256};
+
+use crate::sink::Sink;
+
+#[derive(Clone)]
+pub struct RenderCache {
+    path: PathBuf,
+    /// The size in bytes above which the oldest entries are removed.
+    limit: u64,
+}
+
+impl RenderCache {
+    /// Use a directory as cache, creating it if necessary.
+    pub fn new(path: PathBuf, limit: u64) -> Result<Self, io::Error> {
+        fs::create_dir_all(&path)?;
+        Ok(RenderCache { path, limit })
+    }
+
+    /// The hash of a pdf, to build the keys of its pages.
+    pub fn digest(pdf: &Path) -> Result<String, io::Error> {
+        let mut hasher = Sha256::new();
+        io::copy(&mut fs::File::open(pdf)?, &mut hasher)?;
+        Ok(hex(&hasher.finalize()))
+    }
+
+    /// The hash of a key, such as one built from other digests.
+    pub fn digest_of(key: &[u8]) -> String {
+        hex(&Sha256::digest(key))
+    }
+
+    /// Get a page converted earlier into the directory of the sink, with the same extension.
+    pub fn get(&self, key: &str, sink: &mut Sink) -> Option<PathBuf> {
+        let name = Self::name(key);
+        let entry = Self::EXTENSIONS
+            .iter()
+            .map(|ext| self.path.join(&name).with_extension(ext))
+            .find(|entry| entry.is_file())?;
+
+        let mut unique = sink.unique_path().ok()?;
+        unique.path.set_extension(entry.extension().unwrap_or_default());
+        let linked = fs::hard_link(&entry, &unique.path)
+            .or_else(|_| fs::copy(&entry, &unique.path).map(drop));
+        match linked {
+            Ok(()) => Some(unique.path),
+            Err(err) => {
+                tracing::debug!("Can't use cached page: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Keep a converted page.
+    pub fn put(&self, key: &str, page: &Path) -> Result<(), io::Error> {
+        let mut entry = self.path.join(Self::name(key));
+        if let Some(ext) = page.extension() {
+            entry.set_extension(ext);
+        }
+
+        match fs::hard_link(page, &entry) {
+            Ok(()) => Ok(()),
+            // The same page, converted concurrently.
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+            Err(_) => fs::copy(page, &entry).map(drop),
+        }
+    }
+
+    /// Remove the oldest entries until the cache is within its limit.
+    ///
+    /// Explodes running at once evict concurrently, entries removed by another are skipped.
+    pub fn evict(&self) -> Result<(), io::Error> {
+        let gone = |err: &io::Error| err.kind() == io::ErrorKind::NotFound;
+        let mut entries = vec![];
+        let mut total = 0;
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            let meta = match entry.metadata() {
+                Err(err) if gone(&err) => continue,
+                meta => meta?,
+            };
+            total += meta.len();
+            entries.push((meta.modified()?, meta.len(), entry.path()));
+        }
+
+        entries.sort();
+        for (_, len, path) in entries {
+            if total <= self.limit {
+                break;
+            }
+            match fs::remove_file(path) {
+                Err(err) if !gone(&err) => return Err(err),
+                _ => total -= len,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The names of entries are hashes of the key, which may contain any characters.
+    fn name(key: &str) -> String {
+        Self::digest_of(key.as_bytes())
+    }
+
+    /// The extensions of entries, svg pages have none.
+    const EXTENSIONS: &'static [&'static str] = &["", "png", "ppm"];
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
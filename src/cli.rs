@@ -1,26 +1,43 @@
-use std::{fs, io, mem, path::Path, path::PathBuf, process};
-use tokio::runtime;
+use std::{fs, io, mem, path::Path, path::PathBuf, process, sync::Arc, sync::mpsc, thread};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::{runtime, time};
 use tokio::stream::StreamExt;
 use crossterm::{
     ErrorKind,
     terminal::{disable_raw_mode, enable_raw_mode},
     event::{Event, EventStream, KeyCode, KeyEvent, KeyModifiers},
 };
+use image::RgbImage;
 use tui::{Terminal, layout, widgets};
-use tui::backend::CrosstermBackend;
+use tui::backend::{Backend, CrosstermBackend};
 
-use crate::FatalError;
-use crate::app::App;
-use crate::project::{Audio, Project, Slide, Visual};
+use crate::{ErrorClass, FatalError};
+use crate::app::{App, Watch};
+use crate::conditional::fmt_http_date;
+use crate::ffmpeg::Cue;
+use crate::preview::{Graphics, HalfBlocks};
+use crate::progress::{Progress, Stage};
+use crate::resources::require_tool;
+use crate::project::{Audio, Overlay, Position, Project, Slide, Summary, Visual};
 use crate::sink::FileSource;
 
-pub fn tui(app: App) -> Result<(), FatalError> {
+/// Run the terminal interface, `web` is the address of a web server working on the same `App`.
+pub fn tui(
+    app: Arc<App>,
+    output: &Path,
+    graphics: Graphics,
+    web: Option<&str>,
+    watch: Option<Watch>,
+    cue: Option<Cue>,
+) -> Result<(), FatalError> {
     let stdout = io::stdout();
     let backend = CrosstermBackend::new(stdout);
     let terminal = Terminal::new(backend)?;
 
-    let rt = runtime::Builder::new_current_thread().build()?;
-    rt.block_on(drive_tui(terminal, &app))?;
+    // The app is also shared with the thread doing the work of a `Job`.
+    let rt = runtime::Builder::new_current_thread().enable_time().build()?;
+    rt.block_on(drive_tui(terminal, &app, output, graphics, web, watch, cue))?;
 
     Ok(())
 }
@@ -29,27 +46,156 @@ pub fn tui(app: App) -> Result<(), FatalError> {
 struct Tui {
     select: Option<(FileSelect, SelectTarget)>,
     project: Option<Project>,
-    status: Option<String>,
+    /// Messages for the user, the latest in the status line.
+    status: StatusLog,
     outfile: Option<PathBuf>,
     slide_idx: usize,
+    /// The selection among the visible part of the slide list.
+    slide_state: widgets::ListState,
+    /// The number of slides visible at once, for paging.
+    slide_page: usize,
+    /// The slide number typed after `g`.
+    jump: Option<String>,
+    edit: Option<SlideEdit>,
+    resume: Option<ResumePicker>,
+    /// Showing the reference of all keys.
+    help: bool,
+    job: Option<Job>,
+    preview: Option<SlidePreview>,
+    playback: Option<Playback>,
+    /// Played before the audio of a slide, to practice starting the narration.
+    cue: Option<Cue>,
+    /// The web server works on the same projects, and may change ours.
+    shared: bool,
+    /// When the meta data of the project was last found to be changed.
+    seen: Option<SystemTime>,
+    watch: Option<Watch>,
+    /// The modification time and size of the pdf the project was made from, at the last look.
+    pdf_seen: Option<(Option<SystemTime>, Option<u64>)>,
+    /// When the pdf was last found to be changed, until it is converted again.
+    pdf_changed: Option<Instant>,
+}
+
+/// The projects kept from earlier runs, to continue one of them.
+struct ResumePicker {
+    projects: Vec<Summary>,
+    state: widgets::ListState,
+}
+
+/// Editing the settings of one slide, each change is stored at once.
+struct SlideEdit {
+    idx: usize,
+    field: EditField,
+    /// The duration as typed, which may not yet be a number.
+    duration: String,
+    /// The offset of the audio in milliseconds as typed, such as a lone `-`.
+    offset: String,
+    /// The tempo as typed.
+    tempo: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum EditField {
+    Duration,
+    Offset,
+    Tempo,
+    Enabled,
+    Subtitle,
+    Overlay,
+}
+
+/// Audio playing in the background, stopped when dropped.
+struct Playback {
+    child: process::Child,
+}
+
+/// The selected slide, shown next to the list of slides.
+struct SlidePreview {
+    graphics: Graphics,
+    /// The slide and its image, `None` if it could not be rendered.
+    image: Option<(usize, Option<RgbImage>)>,
+    /// Where the image goes, when it is written with a graphics protocol after drawing.
+    area: Option<layout::Rect>,
+    /// What was written with the graphics protocol.
+    shown: Option<(usize, layout::Rect)>,
+}
+
+/// What happened recently, such as errors and the stages of work, to read up on later.
+#[derive(Default)]
+struct StatusLog {
+    /// The oldest entries are dropped beyond `StatusLog::CAPACITY`.
+    entries: VecDeque<LogEntry>,
+    /// If the latest entry is still shown in the status line.
+    current: bool,
+    /// Showing the pane with all entries.
+    open: bool,
+    /// How many entries the pane is scrolled back from the latest.
+    scroll: usize,
+}
+
+struct LogEntry {
+    at: SystemTime,
+    message: String,
+    /// How often the same message came in a row.
+    repeated: usize,
+}
+
+/// Work on the project running on another thread, which hands the project back when done.
+struct Job {
+    kind: JobKind,
+    progress: Arc<Progress>,
+    /// The last stage of the progress that was logged.
+    stage: Stage,
+    done: mpsc::Receiver<(Project, Result<(), FatalError>)>,
+}
+
+#[derive(Clone, Copy)]
+enum JobKind {
+    Explode,
+    Render,
+    /// Converting a changed pdf again.
+    Reload(Watch),
 }
 
 struct FileSelect {
     path: PathBuf,
     idx: usize,
+    /// Extensions of the files offered, all files if empty. Directories are always offered.
+    extensions: &'static [&'static str],
+    /// Typed to narrow down the files.
+    filter: String,
     // TODO: redundant. Use sprint-dir or walk-dir here.
-    files: Vec<PathBuf>,
+    entries: Vec<FileEntry>,
+    /// Entries still arriving from the thread reading the directory.
+    listing: Option<mpsc::Receiver<io::Result<FileEntry>>>,
+    /// The entries matching the filter, best matches first.
+    files: Vec<FileEntry>,
     state: widgets::ListState,
 }
 
+#[derive(Clone)]
+struct FileEntry {
+    path: PathBuf,
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
 enum SelectTarget {
     AudioOf(usize),
     Project,
+    /// Where to save the video, with the file name typed so far.
+    Output { name: String },
 }
 
 async fn drive_tui(
-    mut term: Terminal<impl tui::backend::Backend>,
-    app: &App,
+    mut term: Terminal<impl Backend + io::Write>,
+    app: &Arc<App>,
+    output: &Path,
+    graphics: Graphics,
+    web: Option<&str>,
+    watch: Option<Watch>,
+    cue: Option<Cue>,
 )
     -> Result<(), FatalError>
 {
@@ -69,18 +215,67 @@ async fn drive_tui(
     let _canary = DisableRawMode::new();
     let mut events = EventStream::new();
     let mut tui = Tui::default();
-    tui.status = Some("Press `enter` to select pdf for a new project.".into());
+    if graphics != Graphics::None {
+        tui.preview = Some(SlidePreview {
+            graphics,
+            image: None,
+            area: None,
+            shown: None,
+        });
+    }
+    tui.watch = watch;
+    tui.cue = cue;
+    if let Some(addr) = web {
+        tui.shared = true;
+        tui.status.push(format!("Serving the web interface on http://{}", addr));
+    }
+    tui.status.push("Press `enter` to select pdf for a new project.");
+
+    let projects = Project::list(app, &app.sink.as_sink())?;
+    if !projects.is_empty() {
+        let mut state = widgets::ListState::default();
+        state.select(Some(0));
+        tui.resume = Some(ResumePicker { projects, state });
+        tui.status.push("Press `enter` to continue a project, `n` to start a new one.");
+    }
 
     term.clear()?;
-    term.draw(|frame| tui.draw(frame))?;
+    tui.redraw(&mut term)?;
 
     loop {
-        let next = match events.next().await {
+        let next = if tui.is_busy() {
+            // Wake up regularly to redraw the progress.
+            match time::timeout(Tui::PROGRESS_TICK, events.next()).await {
+                Ok(event) => event,
+                Err(_) => {
+                    tui.poll(app, output)?;
+                    tui.update_preview(app);
+                    tui.redraw(&mut term)?;
+                    continue;
+                }
+            }
+        } else {
+            events.next().await
+        };
+
+        let next = match next {
             // TODO: maybe some deliberation on some error types?
             Some(event) => event.map_err(convert_err)?,
             None => break,
         };
 
+        if tui.close_help(&next)
+            || tui.pick_resume(&next, app)?
+            || tui.type_name(&next)
+            || tui.type_filter(&next)
+            || tui.type_jump(&next)
+            || tui.type_edit(&next, app)?
+            || tui.status.use_keys(&next)
+        {
+            tui.redraw(&mut term)?;
+            continue;
+        }
+
         match next {
             Event::Key(KeyEvent {
                 code: KeyCode::Char('q'),
@@ -94,6 +289,8 @@ async fn drive_tui(
                 code: KeyCode::Char('d'),
                 modifiers: KeyModifiers::CONTROL,
             }) => break,
+            // The project is with the job, nothing else to do until it is back.
+            _ if tui.job.is_some() => {}
             Event::Key(KeyEvent {
                 code: KeyCode::Up,
                 modifiers: KeyModifiers::NONE,
@@ -125,6 +322,60 @@ async fn drive_tui(
                     }
                 }
             }
+            Event::Key(KeyEvent {
+                code: KeyCode::PageUp,
+                modifiers: KeyModifiers::NONE,
+            }) => {
+                if tui.select.is_none() {
+                    tui.go_to_slide(tui.slide_idx.saturating_sub(tui.slide_page));
+                }
+            }
+            Event::Key(KeyEvent {
+                code: KeyCode::PageDown,
+                modifiers: KeyModifiers::NONE,
+            }) => {
+                if tui.select.is_none() {
+                    tui.go_to_slide(tui.slide_idx.saturating_add(tui.slide_page));
+                }
+            }
+            Event::Key(KeyEvent {
+                code: KeyCode::Home,
+                modifiers: KeyModifiers::NONE,
+            }) => {
+                if tui.select.is_none() {
+                    tui.go_to_slide(0);
+                }
+            }
+            Event::Key(KeyEvent {
+                code: KeyCode::End,
+                modifiers: KeyModifiers::NONE,
+            }) => {
+                if tui.select.is_none() {
+                    tui.go_to_slide(usize::MAX);
+                }
+            }
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('?'),
+                ..
+            }) => {
+                tui.help = true;
+            }
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('e'),
+                modifiers: KeyModifiers::NONE,
+            }) => {
+                if tui.select.is_none() {
+                    tui.start_edit();
+                }
+            }
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('g'),
+                modifiers: KeyModifiers::NONE,
+            }) => {
+                if tui.select.is_none() && tui.project.is_some() {
+                    tui.jump = Some(String::new());
+                }
+            }
             Event::Key(KeyEvent {
                 code: KeyCode::Enter,
                 modifiers: KeyModifiers::NONE,
@@ -134,31 +385,59 @@ async fn drive_tui(
                         tui.select_project(app, select)?
                     }
                     Some((select, SelectTarget::AudioOf(idx))) => {
-                        tui.select_slide_audio(select, idx)?;
+                        tui.select_slide_audio(select, idx, app)?;
+                    }
+                    Some((select, SelectTarget::Output { name })) => {
+                        tui.select_output(select, name);
                     }
                     None => {
                         if let Some(ref project) = tui.project {
                             if tui.slide_idx < project.meta.slides.len() {
-                                tui.select = Some((tui.start_select()?, SelectTarget::AudioOf(tui.slide_idx)));
+                                tui.select = Some(tui.start_select(SelectTarget::AudioOf(tui.slide_idx)));
                                 tui.slide_idx += 1;
                             }
                         } else {
                             if tui.select.is_none() {
-                                tui.select = Some((tui.start_select()?, SelectTarget::Project));
+                                tui.select = Some(tui.start_select(SelectTarget::Project));
                             }
                         }
                     }
                 }
             }
+            Event::Key(KeyEvent {
+                code: KeyCode::Esc,
+                modifiers: KeyModifiers::NONE,
+            }) => {
+                if tui.select.take().is_some() {
+                    tui.status.push("Selection cancelled");
+                }
+            }
             Event::Key(KeyEvent {
                 code: KeyCode::Char('s'),
                 modifiers: KeyModifiers::NONE,
             }) => {
-                if let Some(ref outfile) = tui.outfile {
-                    fs::copy(outfile, "/tmp/output.mp4")?;
-                    tui.status = Some("Written existing video to /tmp/output.mp4".into());
-                } else {
-                    tui.compute_video(&mut term, app)?;
+                if tui.select.is_none() {
+                    if tui.outfile.is_some() {
+                        tui.start_save(output);
+                    } else {
+                        tui.compute_video(app);
+                    }
+                }
+            }
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('d'),
+                modifiers: KeyModifiers::NONE,
+            }) => {
+                if tui.select.is_none() {
+                    tui.next_filter(app)?;
+                }
+            }
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('n'),
+                modifiers: KeyModifiers::NONE,
+            }) => {
+                if tui.select.is_none() {
+                    tui.toggle_slide_numbers(app)?;
                 }
             }
             Event::Key(KeyEvent {
@@ -170,15 +449,43 @@ async fn drive_tui(
                     tui.preview_slide()?;
                 }
             }
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('p'),
+                modifiers: KeyModifiers::NONE,
+            }) => {
+                if tui.select.is_none() {
+                    tui.toggle_playback(app);
+                }
+            }
             _ => {}
         }
 
-        term.draw(|frame| tui.draw(frame))?;
+        tui.poll(app, output)?;
+        tui.update_preview(app);
+        tui.redraw(&mut term)?;
     }
 
     Ok(())
 }
 
+/// A line for the log, with the hint that helps the most.
+fn describe_err(err: &FatalError) -> String {
+    // The whole complaint of ffmpeg, its last line rarely says enough.
+    if let (FatalError::Ffmpeg(ffmpeg), ErrorClass::User) = (err, err.class()) {
+        return format!("{}\n{}", err, ffmpeg.stderr_tail);
+    }
+
+    let detail = err.detail().and_then(|detail| detail.lines().last().map(str::to_owned));
+    match (err.class(), detail) {
+        (ErrorClass::Bug, _) => format!("{:?}", err),
+        (_, None) => err.to_string(),
+        (ErrorClass::User, Some(detail)) => format!("{} {}", err, detail),
+        (ErrorClass::Environment, Some(detail)) => {
+            format!("{} Check the installation and free space: {}", err, detail)
+        }
+    }
+}
+
 fn convert_err(ct: ErrorKind) -> FatalError {
     match ct {
         ErrorKind::IoError(io) => io.into(),
@@ -187,42 +494,135 @@ fn convert_err(ct: ErrorKind) -> FatalError {
 }
 
 impl Tui {
-    fn draw(&mut self, frame: &mut tui::Frame<'_, impl tui::backend::Backend>) {
-        let size = frame.size();
-        frame.render_widget(widgets::Clear, size);
+    /// Draw, then write the preview if the terminal draws it with its graphics protocol.
+    fn redraw(&mut self, term: &mut Terminal<impl Backend + io::Write>) -> Result<(), FatalError> {
+        term.draw(|frame| self.draw(frame))?;
+
+        let (graphics, shown, wanted) = match self.preview {
+            Some(ref preview) => (preview.graphics, preview.shown, preview.wanted()),
+            None => return Ok(()),
+        };
+
+        if shown == wanted {
+            return Ok(());
+        }
+
+        if shown.is_some() {
+            // Only changed cells are drawn, the rest of the old image would remain.
+            graphics.clear(term.backend_mut())?;
+            term.clear()?;
+            term.draw(|frame| self.draw(frame))?;
+        }
+
+        if let Some(ref mut preview) = self.preview {
+            if let (Some((_, area)), Some((_, Some(image)))) = (wanted, &preview.image) {
+                graphics.show(image, area, term.backend_mut())?;
+            }
+            preview.shown = wanted;
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut tui::Frame<'_, impl Backend>) {
+        let full = frame.size();
+        frame.render_widget(widgets::Clear, full);
+        // The last two lines are for the hints and the status.
+        let mut size = layout::Rect { height: full.height.saturating_sub(2), ..full };
+
+        if self.status.open {
+            let height = (size.height / 3).max(3).min(size.height);
+            size.height -= height;
+            let rect = layout::Rect { y: size.y + size.height, height, ..size };
+            self.status.draw(frame, rect);
+        }
+
+        if let Some(ref mut preview) = self.preview {
+            preview.area = None;
+        }
 
         if let Some(ref project) = self.project {
+            let mut size = size;
+            if let Some(ref mut preview) = self.preview {
+                let columns = layout::Layout::default()
+                    .direction(layout::Direction::Horizontal)
+                    .constraints([
+                        layout::Constraint::Percentage(60),
+                        layout::Constraint::Percentage(40),
+                    ].as_ref())
+                    .split(size);
+                size = columns[0];
+                let overlaid = self.select.is_some()
+                    || self.job.is_some()
+                    || self.edit.is_some()
+                    || self.help;
+                preview.draw(frame, columns[1], overlaid);
+            }
+
             let block = widgets::Block::default()
                 .title(format!("Project with {} slides", project.meta.slides.len()))
                 .borders(widgets::Borders::ALL);
             frame.render_widget(block, size);
 
-            let mut inner = size.inner(&layout::Margin { horizontal: 1, vertical: 1 });
-            for (idx, slide) in project.meta.slides.iter().enumerate() {
-                let item_rect = layout::Rect { height: 2, ..inner };
-                let par = widgets::Paragraph::new(format!(
+            // Only the visible slides are listed, such that the selected one stays centered.
+            let inner = size.inner(&layout::Margin { horizontal: 1, vertical: 1 });
+            let slides = &project.meta.slides;
+            let visible = usize::from(inner.height / 2).max(1);
+            let first = self.slide_idx
+                .saturating_sub(visible / 2)
+                .min(slides.len().saturating_sub(visible));
+            let last = slides.len().min(first + visible);
+            self.slide_page = visible;
+
+            let selected = self.slide_idx;
+            let items = slides[first..last].iter().enumerate().map(|(idx, slide)| {
+                let idx = first + idx;
+                widgets::ListItem::new(format!(
                         "{}Video: {}\n\
-                         {}Audio: {}",
+                         {}Audio: {}{}",
                          " ",
-                         match &slide.visual {
-                             crate::project::Visual::Slide { src, .. } => src.display(),
+                         match (&slide.visual, &slide.error) {
+                             (_, Some(error)) => format!("Page could not be converted: {}", error),
+                             (crate::project::Visual::Slide { src, .. }, None) => src.display().to_string(),
                          },
-                         if idx == self.slide_idx { "*" } else { " " },
+                         if idx == selected { "*" } else { " " },
                          match &slide.audio {
                              Audio::Silent => String::from("Frame is silent"),
                              Audio::Skip => String::from("Frame is skipped, select audio to enable"),
                              Audio::File { src } => src.display().to_string(),
-                         }
-                    ));
-                frame.render_widget(par, item_rect);
-                inner.y = inner.y.saturating_add(2);
-                inner.height = inner.height.saturating_sub(2);
-            }
+                         },
+                         match (slide.enabled, slide.duration) {
+                             (false, _) => String::from(" (disabled)"),
+                             (true, Some(duration)) => format!(" ({}s)", duration),
+                             (true, None) => String::new(),
+                         },
+                    ))
+            }).collect::<Vec<_>>();
+
+            self.slide_state.select(if (first..last).contains(&self.slide_idx) {
+                Some(self.slide_idx - first)
+            } else {
+                None
+            });
+
+            let list = widgets::List::new(items)
+                .highlight_style(tui::style::Style::default().add_modifier(tui::style::Modifier::BOLD));
+            frame.render_stateful_widget(list, inner, &mut self.slide_state);
         }
 
         if let Some((ref mut select, ref kind)) = self.select {
             let block_rect = size.inner(&layout::Margin { horizontal: 5, vertical: 5 });
-            let rect = block_rect.inner(&layout::Margin { horizontal: 1, vertical: 1 });
+            let mut rect = block_rect.inner(&layout::Margin { horizontal: 1, vertical: 1 });
+
+            // The file name entry takes the last line of the dialog.
+            let entry = match kind {
+                SelectTarget::Output { name } if rect.height > 1 => {
+                    rect.height -= 1;
+                    let entry_rect = layout::Rect { y: rect.y + rect.height, height: 1, ..rect };
+                    Some((format!("File name: {}_", name), entry_rect))
+                }
+                _ => None,
+            };
 
             let items = select.files.len();
             let first = if select.idx < items {
@@ -238,8 +638,7 @@ impl Tui {
 
             let list = select.files[first..last]
                 .iter()
-                .map(|os| os.to_str().unwrap_or("???? Unreadable file name"))
-                .map(|item| widgets::ListItem::new(item))
+                .map(|entry| widgets::ListItem::new(entry.describe()))
                 .collect::<Vec<_>>();
 
             select.state.select(if (first..last).contains(&select.idx) {
@@ -248,179 +647,976 @@ impl Tui {
                 None
             });
 
+            let mut title = match *kind {
+                SelectTarget::Project => format!("Select a pdf: {}", select.path.display()),
+                SelectTarget::AudioOf(idx) => format!("Select audio for slide {}", idx),
+                SelectTarget::Output { .. } => format!("Save video in: {}", select.path.display()),
+            };
+            if !select.filter.is_empty() {
+                title.push_str(&format!(" (matching `{}`)", select.filter));
+            }
+            if select.listing.is_some() {
+                title.push_str(" (listing...)");
+            }
+
             let block = widgets::Block::default()
-                .title(match *kind {
-                    SelectTarget::Project => format!("Select a pdf: {}", select.path.display()),
-                    SelectTarget::AudioOf(idx) => format!("Select audio for slide {}", idx),
-                })
+                .title(title)
                 .borders(widgets::Borders::ALL);
             frame.render_widget(block, block_rect);
             let list = widgets::List::new(list).highlight_symbol("*");
             frame.render_widget(widgets::Clear, rect);
             frame.render_stateful_widget(list, rect, &mut select.state);
+
+            if let Some((entry, entry_rect)) = entry {
+                frame.render_widget(widgets::Paragraph::new(entry), entry_rect);
+            }
+        }
+
+        if let (Some(edit), Some(project)) = (&self.edit, &self.project) {
+            edit.draw(frame, project);
+        }
+
+        if let Some(ref mut resume) = self.resume {
+            resume.draw(frame);
         }
 
-        if let Some(ref status) = self.status {
+        if full.height >= 2 {
+            let rect = layout::Rect { y: full.height - 2, height: 1, ..full };
+            let hints = widgets::Paragraph::new(self.hints())
+                .style(tui::style::Style::default().add_modifier(tui::style::Modifier::REVERSED));
+            frame.render_widget(hints, rect);
+        }
+
+        let jump = self.jump.as_ref().map(|num| format!("Go to slide: {}_", num));
+        if let Some(status) = jump.as_deref().or_else(|| self.status.current()) {
             let rect = layout::Rect {
                 x: 0,
-                y: size.height.saturating_sub(1),
+                y: full.height.saturating_sub(1),
                 height: 1,
-                width: size.width,
+                width: full.width,
             };
 
-            frame.render_widget(widgets::Paragraph::new(status.as_str()), rect);
+            frame.render_widget(widgets::Paragraph::new(status), rect);
         }
-    }
-
-    fn start_select(&self) -> Result<FileSelect, io::Error> {
-        Ok(FileSelect {
-            path: Path::new(".").to_owned(),
-            idx: usize::MAX,
-            files: FileSelect::read_dir(Path::new("."))?,
-            state: widgets::ListState::default(),
-        })
-    }
 
-    fn select_project(&mut self, app: &App, select: FileSelect) -> Result<(), FatalError> {
-        let selected_file = match self.resolve_file_selection(select, SelectTarget::Project) {
-            Some(file) => file,
-            None => return Ok(()),
-        };
+        if let Some(ref job) = self.job {
+            let progress = job.progress.snapshot();
+            let label = if progress.stage == Stage::Queued {
+                format!("{}, position {} in the queue", progress.stage.describe(), progress.total)
+            } else if progress.total > 0 {
+                format!("{}: {} of {}", progress.stage.describe(), progress.done, progress.total)
+            } else {
+                progress.stage.describe().to_owned()
+            };
 
-        let mut sink = app.sink.as_sink();
-        let file = match fs::File::open(selected_file) {
-            Err(io) => {
-                self.status = Some(format!("Failed to open file: {:?}", io));
-                return Ok(())
-            },
-            Ok(file) => file,
-        };
+            let rect = size.inner(&layout::Margin { horizontal: 5, vertical: 1 });
+            let rect = layout::Rect {
+                y: rect.y + rect.height.saturating_sub(3) / 2,
+                height: rect.height.min(3),
+                ..rect
+            };
 
-        let mut file = io::BufReader::new(file);
-        let mut project = Project::new(&mut sink, &mut file)?;
-        project.explode(app)?;
-        self.project = Some(project);
-        self.status = Some("Press `enter` to select next audio, `s` to generate output".into());
+            let gauge = widgets::Gauge::default()
+                .block(widgets::Block::default()
+                    .title(match job.kind {
+                        JobKind::Explode => "Opening the pdf",
+                        JobKind::Render => "Generating the video",
+                        JobKind::Reload(_) => "Converting the changed pdf",
+                    })
+                    .borders(widgets::Borders::ALL))
+                .ratio(progress.ratio())
+                .label(label);
+            frame.render_widget(widgets::Clear, rect);
+            frame.render_widget(gauge, rect);
+        }
 
-        Ok(())
+        if self.help {
+            let text = format!("{}\n\n{}", self.step(), Self::KEYS.join("\n"));
+            let rect = size.inner(&layout::Margin { horizontal: 5, vertical: 2 });
+            let block = widgets::Block::default()
+                .title("Keys, any key to close")
+                .borders(widgets::Borders::ALL);
+            frame.render_widget(widgets::Clear, rect);
+            frame.render_widget(widgets::Paragraph::new(text).block(block), rect);
+        }
     }
 
-    fn select_slide_audio(&mut self, select: FileSelect, idx: usize)
-        -> Result<(), FatalError>
-    {
-        let selected_file = match self.resolve_file_selection(select, SelectTarget::AudioOf(idx)) {
-            Some(file) => file,
-            None => return Ok(()),
-        };
-
-        let project = match self.project {
-            Some(ref mut project) => project,
-            None => {
-                self.status = Some("Selecting an audio file without project does nothing. How did you end up here?".into());
-                return Ok(())
+    /// Where we are in creating a video.
+    fn step(&self) -> &'static str {
+        match &self.project {
+            _ if self.job.is_some() => "Please wait for the work on the project to finish.",
+            None => "Step 1 of 3: choose a pdf, one slide is made of each page.",
+            Some(_) if self.outfile.is_none() => {
+                "Step 2 of 3: choose the narration of each slide, then generate the video."
             }
-        };
+            Some(_) => "Step 3 of 3: save the video, or change slides and generate it again.",
+        }
+    }
 
-        if project.meta.slides.len() <= idx {
-            self.status = Some(format!("Slide index {} is out of range. How did you end up here?", idx));
-            return Ok(())
+    /// The keys that do something right now.
+    fn hints(&self) -> &'static str {
+        if self.help {
+            "any key: close"
+        } else if self.job.is_some() {
+            "l: log  q: quit"
+        } else if self.resume.is_some() {
+            "up/down: choose  enter: continue  n: new project  q: quit"
+        } else if self.edit.is_some() {
+            "up/down: field  space: toggle  enter: close"
+        } else if self.jump.is_some() {
+            "digits: slide number  enter: go  esc: cancel"
+        } else if let Some((_, SelectTarget::Output { .. })) = self.select {
+            "type: file name  up/down: choose  enter: save or open  esc: cancel"
+        } else if self.select.is_some() {
+            "type: filter  up/down: choose  enter: select or open  esc: cancel"
+        } else if self.project.is_some() {
+            "enter: audio  p: play  e: edit  o: open  g: go to  s: save  l: log  ?: help  q: quit"
+        } else {
+            "enter: choose pdf  l: log  ?: help  q: quit"
         }
+    }
 
-        let mut source = match FileSource::new_from_existing(selected_file) {
-            Ok(source) => source,
-            Err(err) => {
-                self.status = Some(format!("Error opening selected audio file: {:?}", err));
-                return Ok(());
-            }
-        };
+    const KEYS: &'static [&'static str] = &[
+        "enter        choose a pdf, or the audio of the selected slide",
+        "up, down     select a slide, or a file",
+        "pgup, pgdn   select a slide a page away",
+        "home, end    select the first or last slide",
+        "g NUM enter  select slide NUM",
+        "e            edit timing, subtitle, overlay text and if the slide is shown",
+        "d            switch the colors of all slides: as is, dark, negated",
+        "n            toggle drawing `Slide i/N` in a corner of each slide of the video",
+        "p            play the audio of the slide, after the `--cue`, again to stop",
+        "o            open the slide in an image viewer",
+        "s            generate the video, then save it",
+        "esc          close a dialog",
+        "l            show earlier messages, [ and ] to scroll",
+        "?            show this help",
+        "q, ctrl-c    quit",
+        "",
+        "When choosing a file, type to filter the names.",
+    ];
 
-        project.import_audio(idx, &mut source)?;
-        self.status = Some("Press `enter` to select next audio, `s` to generate output".into());
+    /// How often the progress of a job is redrawn.
+    const PROGRESS_TICK: Duration = Duration::from_millis(200);
+    /// How long the pdf must stay the same after a change, such that it is completely written.
+    const SOURCE_SETTLE: Duration = Duration::from_secs(1);
 
-        Ok(())
+    fn start_select(&self, kind: SelectTarget) -> (FileSelect, SelectTarget) {
+        (FileSelect::new(Path::new("."), kind.extensions()), kind)
     }
 
-    fn compute_video(
-        &mut self,
-        term: &mut Terminal<impl tui::backend::Backend>,
-        app: &App,
-    ) -> Result<(), FatalError> {
-        self.status = Some("Generating video output, this may take a while.".into());
-        term.draw(|frame| self.draw(frame))?;
-
-        let project = match self.project {
-            Some(ref mut project) => project,
-            None => {
-                self.status = Some("Generating video file without project does nothing. How did you end up here?".into());
-                return Ok(())
-            }
+    /// Open the save dialog, suggesting the configured output path.
+    fn start_save(&mut self, suggested: &Path) {
+        let dir = match suggested.parent() {
+            Some(dir) if dir.is_dir() => dir,
+            _ => Path::new("."),
         };
 
-        if let Err(error) = project.assemble(app) {
-            self.status = Some(format!("Error: {:?}", error));
-        } else {
-            project.store()?;
-        }
+        let name = suggested
+            .file_name()
+            .map_or_else(|| "output.mp4".into(), |name| name.to_string_lossy().into_owned());
 
-        self.outfile = project.meta.output.clone();
-        if let Some(ref path) = self.outfile {
-            self.status = Some(format!("Video generated in `{}`", path.display()));
+        let kind = SelectTarget::Output { name };
+        let mut select = FileSelect::new(dir, kind.extensions());
+        if let Ok(canonical) = dir.canonicalize() {
+            select.path = canonical;
         }
 
-        Ok(())
+        self.select = Some((select, kind));
+        self.status.push("Type a file name and press `enter` to save, `esc` to cancel");
     }
 
-    fn resolve_file_selection(&mut self, mut select: FileSelect, kind: SelectTarget)
-        -> Option<PathBuf>
-    {
-        let selected_file = if let Some(select) = select.take_selected() {
-            select
-        } else {
-            self.status = Some("no file selected".into());
-            return None;
+    /// Close the help on any key.
+    ///
+    /// Returns if the event was consumed.
+    fn close_help(&mut self, event: &Event) -> bool {
+        match event {
+            Event::Key(_) if self.help => {
+                self.help = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Choose a project to continue, if offered.
+    ///
+    /// Returns if the event was consumed, all keys but those for quitting are.
+    fn pick_resume(&mut self, event: &Event, app: &App) -> Result<bool, FatalError> {
+        let resume = match self.resume {
+            Some(ref mut resume) => resume,
+            None => return Ok(false),
         };
 
-        match fs::metadata(&selected_file) {
-            Err(io) => {
-                self.status = Some(format!("Failed to inspect file: {:?}", io));
-                return None;
+        let code = match *event {
+            Event::Key(KeyEvent { code: KeyCode::Char('q'), .. })
+            | Event::Key(KeyEvent { modifiers: KeyModifiers::CONTROL, .. }) => return Ok(false),
+            Event::Key(KeyEvent { code, .. }) => code,
+            _ => return Ok(false),
+        };
+
+        let count = resume.projects.len();
+        let selected = resume.state.selected().unwrap_or(0);
+        match code {
+            KeyCode::Up => resume.state.select(Some((selected + count - 1) % count)),
+            KeyCode::Down => resume.state.select(Some((selected + 1) % count)),
+            KeyCode::Char('n') | KeyCode::Esc => {
+                self.resume = None;
+                self.status.push("Press `enter` to select pdf for a new project.");
             }
-            Ok(meta) if meta.is_dir() => {
-                if let Err(err) = select.pivot(selected_file) {
-                    self.status = Some(format!(
-                        "Can't switch to directory, failed to canonicalize: {}", err
-                    ));
+            KeyCode::Enter => {
+                let project_id = resume.projects[selected].project_id;
+                self.resume = None;
+                match Project::load(app, &app.sink.as_sink(), project_id)? {
+                    Some(project) => {
+                        self.outfile = project.meta.output.clone();
+                        self.project = Some(project);
+                        self.pdf_seen = None;
+                        self.pdf_changed = None;
+                        self.slide_idx = 0;
+                        self.status.push("Press `enter` to select next audio, `p` to play it, `s` to generate output");
+                    }
+                    None => self.status.push("The project is gone"),
                 }
-                self.select = Some((select, kind));
-                return None;
-            }
-            Ok(meta) if !meta.is_file() => {
-                self.status = Some("Neither a file nor a directory".into());
-                return None;
             }
-            Ok(_) => Some(selected_file),
+            _ => {}
         }
+
+        Ok(true)
     }
 
-    fn preview_slide(&mut self)
-        -> Result<(), FatalError>
-    {
-        let project = match &mut self.project {
-            Some(project) => project,
-            None => {
-                self.status = Some("No project to open a slide from".into());
-                return Ok(());
-            },
+    /// Open the settings of the selected slide.
+    fn start_edit(&mut self) {
+        let project = match self.project {
+            Some(ref project) => project,
+            None => return,
         };
 
-        project.thumbnail()?;
+        let slide = match project.meta.slides.get(self.slide_idx) {
+            Some(slide) => slide,
+            None => return,
+        };
+
+        self.edit = Some(SlideEdit {
+            idx: self.slide_idx,
+            field: EditField::Duration,
+            duration: slide.duration.map_or_else(String::new, |duration| duration.to_string()),
+            offset: match slide.sync_offset_ms {
+                0 => String::new(),
+                offset => offset.to_string(),
+            },
+            tempo: slide.tempo.map_or_else(String::new, |tempo| tempo.to_string()),
+        });
+        self.status.push("`up`/`down` to choose, `space` to toggle, `enter` to close");
+    }
+
+    /// Edit the settings of a slide, if they are open.
+    ///
+    /// Returns if the event was consumed, all keys are while editing.
+    fn type_edit(&mut self, event: &Event, app: &App) -> Result<bool, FatalError> {
+        let (edit, project) = match (&mut self.edit, &mut self.project) {
+            (Some(edit), Some(project)) => (edit, project),
+            _ => return Ok(false),
+        };
+
+        let code = match *event {
+            Event::Key(KeyEvent { code, .. }) => code,
+            _ => return Ok(false),
+        };
+
+        let slide = match project.meta.slides.get_mut(edit.idx) {
+            Some(slide) => slide,
+            None => {
+                self.edit = None;
+                return Ok(false);
+            }
+        };
+
+        match (code, edit.field) {
+            (KeyCode::Enter, _) | (KeyCode::Esc, _) => {
+                self.edit = None;
+                self.status.dismiss();
+                return Ok(true);
+            }
+            (KeyCode::Up, _) | (KeyCode::BackTab, _) => edit.field = edit.field.prev(),
+            (KeyCode::Down, _) | (KeyCode::Tab, _) => edit.field = edit.field.next(),
+            (KeyCode::Char(' '), EditField::Enabled) => slide.enabled = !slide.enabled,
+            (KeyCode::Char(ch), EditField::Duration) if ch.is_ascii_digit() || ch == '.' => {
+                edit.duration.push(ch);
+            }
+            (KeyCode::Backspace, EditField::Duration) => { edit.duration.pop(); }
+            (KeyCode::Char(ch), EditField::Offset)
+                if ch.is_ascii_digit() || (ch == '-' && edit.offset.is_empty()) =>
+            {
+                edit.offset.push(ch);
+            }
+            (KeyCode::Backspace, EditField::Offset) => { edit.offset.pop(); }
+            (KeyCode::Char(ch), EditField::Tempo) if ch.is_ascii_digit() || ch == '.' => {
+                edit.tempo.push(ch);
+            }
+            (KeyCode::Backspace, EditField::Tempo) => { edit.tempo.pop(); }
+            (KeyCode::Char(ch), EditField::Subtitle) => {
+                slide.subtitle.get_or_insert_with(String::new).push(ch);
+            }
+            (KeyCode::Backspace, EditField::Subtitle) => {
+                if let Some(subtitle) = &mut slide.subtitle {
+                    subtitle.pop();
+                    if subtitle.is_empty() {
+                        slide.subtitle = None;
+                    }
+                }
+            }
+            (KeyCode::Char(ch), EditField::Overlay) => {
+                slide.overlay
+                    .get_or_insert_with(|| Overlay { text: String::new(), position: Default::default() })
+                    .text
+                    .push(ch);
+            }
+            (KeyCode::Backspace, EditField::Overlay) => {
+                if let Some(overlay) = &mut slide.overlay {
+                    overlay.text.pop();
+                    if overlay.text.is_empty() {
+                        slide.overlay = None;
+                    }
+                }
+            }
+            _ => return Ok(true),
+        }
+
+        // An incomplete number, such as `1.`, keeps the last valid duration.
+        if edit.duration.is_empty() {
+            slide.duration = None;
+        } else if let Ok(duration) = edit.duration.parse::<f32>() {
+            if duration > 0.0 && duration.is_finite() {
+                slide.duration = Some(duration);
+            }
+        }
+
+        if edit.offset.is_empty() {
+            slide.sync_offset_ms = 0;
+        } else if let Ok(offset) = edit.offset.parse() {
+            slide.sync_offset_ms = offset;
+        }
+
+        // Out of range while typing, such as `0.` on the way to `0.8`.
+        if edit.tempo.is_empty() {
+            slide.tempo = None;
+        } else if let Ok(tempo) = edit.tempo.parse() {
+            if Slide::is_valid_tempo(tempo) {
+                slide.tempo = Some(tempo);
+            }
+        }
+
+        self.store_project(app)?;
+        Ok(true)
+    }
+
+    /// Store a change of the project. When the web interface stored first, take its version.
+    ///
+    /// Returns if our change was stored.
+    fn store_project(&mut self, app: &App) -> Result<bool, FatalError> {
+        let project = match self.project {
+            Some(ref mut project) => project,
+            None => return Ok(false),
+        };
+
+        match project.store() {
+            Ok(()) => Ok(true),
+            Err(FatalError::Conflict) => {
+                project.reload(app)?;
+                self.status.push("The project was changed in the web interface meanwhile, change it again");
+                self.fit_to_slides();
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Narrow down the files of the selection by typing, except when a name is typed instead.
+    ///
+    /// Returns if the event was consumed, such that typed letters are not taken as commands.
+    fn type_filter(&mut self, event: &Event) -> bool {
+        let select = match self.select {
+            Some((_, SelectTarget::Output { .. })) | None => return false,
+            Some((ref mut select, _)) => select,
+        };
+
+        match *event {
+            Event::Key(KeyEvent { code: KeyCode::Char(ch), modifiers })
+                if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                select.filter.push(ch);
+            }
+            Event::Key(KeyEvent { code: KeyCode::Backspace, .. }) if !select.filter.is_empty() => {
+                select.filter.pop();
+            }
+            _ => return false,
+        }
+
+        // Select the best match again.
+        select.idx = usize::MAX;
+        select.refilter();
+        true
+    }
+
+    /// Change the colors of the slides to the next filter.
+    fn next_filter(&mut self, app: &App) -> Result<(), FatalError> {
+        let project = match self.project {
+            Some(ref mut project) => project,
+            None => return Ok(()),
+        };
+
+        let filter = project.meta.filter.next();
+        project.meta.filter = filter;
+        if !self.store_project(app)? {
+            return Ok(());
+        }
+        self.status.push(format!("Slides are shown with the filter {}", filter.name()));
+        // Rendered again with the filter.
+        if let Some(ref mut preview) = self.preview {
+            preview.image = None;
+        }
+        Ok(())
+    }
+
+    fn toggle_slide_numbers(&mut self, app: &App) -> Result<(), FatalError> {
+        let project = match self.project {
+            Some(ref mut project) => project,
+            None => return Ok(()),
+        };
+
+        let numbers = match project.meta.slide_numbers {
+            Some(_) => None,
+            None => Some(Position::BottomRight),
+        };
+        project.meta.slide_numbers = numbers;
+        if !self.store_project(app)? {
+            return Ok(());
+        }
+        self.status.push(match numbers {
+            Some(_) => "Slide numbers are drawn in the video",
+            None => "Slide numbers are left out of the video",
+        });
+        Ok(())
+    }
+
+    /// Select a slide, the last one if the index is past the end.
+    fn go_to_slide(&mut self, idx: usize) {
+        if let Some(ref project) = self.project {
+            self.slide_idx = idx.min(project.meta.slides.len().saturating_sub(1));
+        }
+    }
+
+    /// Edit the slide number after `g`, jumping to it on `enter`.
+    ///
+    /// Returns if the event was consumed, all keys are while a number is typed.
+    fn type_jump(&mut self, event: &Event) -> bool {
+        let num = match self.jump {
+            Some(ref mut num) => num,
+            None => return false,
+        };
+
+        let code = match *event {
+            Event::Key(KeyEvent { code, .. }) => code,
+            _ => return false,
+        };
+
+        match code {
+            KeyCode::Char(ch) if ch.is_ascii_digit() => num.push(ch),
+            KeyCode::Backspace => { num.pop(); }
+            KeyCode::Enter => {
+                // Numbered from one, as in the preview.
+                if let Ok(num) = num.parse::<usize>() {
+                    self.go_to_slide(num.saturating_sub(1));
+                }
+                self.jump = None;
+            }
+            _ => self.jump = None,
+        }
+
+        true
+    }
+
+    /// Edit the file name of the save dialog, if it is open.
+    ///
+    /// Returns if the event was consumed, such that typed letters are not taken as commands.
+    fn type_name(&mut self, event: &Event) -> bool {
+        let name = match self.select {
+            Some((_, SelectTarget::Output { ref mut name })) => name,
+            _ => return false,
+        };
+
+        match *event {
+            Event::Key(KeyEvent { code: KeyCode::Char(ch), modifiers })
+                if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                name.push(ch);
+                true
+            }
+            Event::Key(KeyEvent { code: KeyCode::Backspace, .. }) => {
+                name.pop();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn select_output(&mut self, mut select: FileSelect, name: String) {
+        let outfile = match self.outfile {
+            Some(ref outfile) => outfile.clone(),
+            None => {
+                self.status.push("Saving without a video does nothing. How did you end up here?");
+                return;
+            }
+        };
+
+        // A highlighted directory is entered, a highlighted file lends its name.
+        if let Some(selected) = select.take_selected() {
+            let name = if selected.is_dir() {
+                select.pivot(selected);
+                name
+            } else {
+                selected.file_name().map_or(name, |name| name.to_string_lossy().into_owned())
+            };
+            select.idx = usize::MAX;
+            self.select = Some((select, SelectTarget::Output { name }));
+            return;
+        }
+
+        if name.is_empty() || name.contains(std::path::MAIN_SEPARATOR) {
+            self.status.push("Enter a file name, without any directory");
+            self.select = Some((select, SelectTarget::Output { name }));
+            return;
+        }
+
+        let target = select.path.join(&name);
+        match fs::copy(&outfile, &target) {
+            Ok(_) => self.status.push(format!("Written video to {}", target.display())),
+            Err(err) => {
+                self.status.push(format!("Failed to write {}: {}", target.display(), err));
+                self.select = Some((select, SelectTarget::Output { name }));
+            }
+        }
+    }
+
+    fn select_project(&mut self, app: &Arc<App>, select: FileSelect) -> Result<(), FatalError> {
+        let selected_file = match self.resolve_file_selection(select, SelectTarget::Project) {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        let mut sink = app.sink.as_sink();
+        let file = match fs::File::open(&selected_file) {
+            Err(io) => {
+                self.status.push(format!("Failed to open file: {:?}", io));
+                return Ok(())
+            },
+            Ok(file) => file,
+        };
+
+        let title = selected_file.file_name().map(|name| name.to_string_lossy().into_owned());
+        let mut file = io::BufReader::new(file);
+        let mut project = Project::new(&mut sink, &mut file)?;
+        project.meta.title = title;
+        project.meta.origin = selected_file.canonicalize().ok();
+        self.pdf_seen = None;
+        self.pdf_changed = None;
+        self.start_job(JobKind::Explode, project, app, |project, app| {
+            project.explode(app)?;
+            project.store()
+        });
+        self.status.dismiss();
+
+        Ok(())
+    }
+
+    /// Move the project to another thread for some work, showing its progress until it is done.
+    fn start_job(
+        &mut self,
+        kind: JobKind,
+        mut project: Project,
+        app: &Arc<App>,
+        work: impl FnOnce(&mut Project, &App) -> Result<(), FatalError> + Send + 'static,
+    ) {
+        let (send, done) = mpsc::channel();
+        let progress = project.progress.clone();
+        let app = app.clone();
+
+        thread::spawn(move || {
+            let result = work(&mut project, &app);
+            // The receiver is only gone when we quit.
+            let _ = send.send((project, result));
+        });
+
+        self.job = Some(Job { kind, progress, stage: Stage::Idle, done });
+    }
+
+    /// Render the selected slide for the preview, unless it already is.
+    fn update_preview(&mut self, app: &App) {
+        let (preview, project) = match (&mut self.preview, &mut self.project) {
+            (Some(preview), Some(project)) if !project.meta.slides.is_empty() => (preview, project),
+            _ => return,
+        };
+
+        let idx = self.slide_idx.min(project.meta.slides.len() - 1);
+        if let Some((current, _)) = preview.image {
+            if current == idx {
+                return;
+            }
+        }
+
+        let image = project
+            .page_thumbnail(idx, SlidePreview::WIDTH, app)
+            .and_then(|path| Ok(image::open(path)?.to_rgb8()));
+
+        let image = match image {
+            Ok(image) => Some(image),
+            Err(err) => {
+                self.status.push(format!("Can't preview the slide: {}", describe_err(&err)));
+                None
+            }
+        };
+
+        if let Some(ref mut preview) = self.preview {
+            preview.image = Some((idx, image));
+        }
+    }
+
+    /// If there is work in the background whose results should be shown.
+    fn is_busy(&self) -> bool {
+        let listing = match self.select {
+            Some((ref select, _)) => select.listing.is_some(),
+            None => false,
+        };
+        // Changes from the web server and to the pdf are looked for regularly.
+        let watching = (self.shared || self.watch.is_some()) && self.project.is_some();
+        self.job.is_some() || listing || watching
+    }
+
+    /// Collect the results of work in the background.
+    fn poll(&mut self, app: &Arc<App>, output: &Path) -> Result<(), FatalError> {
+        if let Some((ref mut select, _)) = self.select {
+            if let Err(err) = select.poll_listing() {
+                self.status.push(format!("Can't list the directory: {}", err));
+            }
+        }
+
+        self.poll_shared(app);
+        self.poll_source(app);
+        self.poll_job(output)
+    }
+
+    /// Convert the pdf again when it changed, with `--watch`.
+    fn poll_source(&mut self, app: &Arc<App>) {
+        let watch = match self.watch {
+            // Not while a slide is edited, the pdf may have fewer pages now.
+            Some(watch) if self.job.is_none() && self.edit.is_none() => watch,
+            _ => return,
+        };
+
+        let origin = match self.project.as_ref().and_then(|project| project.meta.origin.clone()) {
+            Some(origin) => origin,
+            None => return,
+        };
+
+        // Exporting a pdf takes a while, we wait until it stopped changing.
+        let meta = fs::metadata(&origin).ok();
+        let stamp = (
+            meta.as_ref().and_then(|meta| meta.modified().ok()),
+            meta.as_ref().map(fs::Metadata::len),
+        );
+        if self.pdf_seen != Some(stamp) {
+            if self.pdf_seen.is_some() {
+                self.pdf_changed = Some(Instant::now());
+            }
+            self.pdf_seen = Some(stamp);
+            return;
+        }
+
+        match self.pdf_changed {
+            Some(changed) if stamp.1.is_some() && changed.elapsed() >= Self::SOURCE_SETTLE => {
+                self.pdf_changed = None;
+            }
+            _ => return,
+        }
+
+        let project = match self.project.take() {
+            Some(project) => project,
+            None => return,
+        };
+
+        self.status.push(format!("The pdf changed, converting `{}` again", origin.display()));
+        self.start_job(JobKind::Reload(watch), project, app, move |project, app| {
+            let mut file = io::BufReader::new(fs::File::open(&origin)?);
+            let matched = project.replace_source(&mut file, app)?;
+            tracing::info!(matched, "Converted the changed pdf");
+            if watch == Watch::Render {
+                project.assemble(app)?;
+            }
+            project.store()
+        });
+    }
+
+    /// Take the changes of the web server to our project, such as narration uploaded there.
+    fn poll_shared(&mut self, app: &App) {
+        let project = match self.project {
+            // A job has the project and will store it, we look again afterwards.
+            Some(ref mut project) if self.shared && self.job.is_none() => project,
+            _ => return,
+        };
+
+        let modified = project.modified();
+        if modified == self.seen {
+            return;
+        }
+
+        self.seen = modified;
+        match project.reload(app) {
+            Ok(false) => {}
+            Ok(true) => self.status.push("The project was changed in the web interface"),
+            Err(err) => self.status.push(format!("Can't reload the project: {}", describe_err(&err))),
+        }
+        self.fit_to_slides();
+    }
+
+    /// Take back the project of a finished job.
+    fn poll_job(&mut self, output: &Path) -> Result<(), FatalError> {
+        if let Some(ref mut job) = self.job {
+            let stage = job.progress.snapshot().stage;
+            if stage != job.stage && stage != Stage::Idle {
+                self.status.push(stage.describe());
+            }
+            job.stage = stage;
+        }
+
+        let (project, result) = match self.job.as_ref().map(|job| job.done.try_recv()) {
+            Some(Ok(done)) => done,
+            None | Some(Err(mpsc::TryRecvError::Empty)) => return Ok(()),
+            Some(Err(mpsc::TryRecvError::Disconnected)) => {
+                self.job = None;
+                self.status.push("The work on the project failed unexpectedly");
+                return Ok(());
+            }
+        };
+
+        let kind = self.job.take().map(|job| job.kind);
+        match (kind, result) {
+            (_, Err(error)) => {
+                self.status.push(format!("Error: {}", describe_err(&error)));
+            }
+            (Some(JobKind::Explode), Ok(())) => {
+                self.status.push("Press `enter` to select next audio, `p` to play it, `s` to generate output");
+            }
+            (Some(JobKind::Reload(watch)), Ok(())) => {
+                if let Some(ref mut preview) = self.preview {
+                    preview.image = None;
+                }
+                self.outfile = project.meta.output.clone();
+                match (watch, &self.outfile) {
+                    (Watch::Render, Some(path)) => {
+                        self.status.push(format!("Video of the changed pdf generated in `{}`", path.display()));
+                    }
+                    _ => self.status.push("Converted the changed pdf, unchanged slides keep their narration"),
+                }
+            }
+            (_, Ok(())) => {
+                self.outfile = project.meta.output.clone();
+                if let Some(ref path) = self.outfile {
+                    self.status.push(format!("Video generated in `{}`", path.display()));
+                    let failed = project.meta.slides
+                        .iter()
+                        .filter(|slide| slide.enabled && slide.error.is_some())
+                        .count();
+                    if failed > 0 {
+                        self.status.push(format!("{} slides are shown as a placeholder, the list says why", failed));
+                    }
+                    self.start_save(output);
+                }
+            }
+        }
+
+        // A project whose pdf could not be opened is of no use.
+        if !project.meta.slides.is_empty() {
+            self.project = Some(project);
+            self.fit_to_slides();
+        }
+
+        Ok(())
+    }
+
+    /// Keep the selected slide and the edit within the slides, after they changed.
+    fn fit_to_slides(&mut self) {
+        let count = self.project.as_ref().map_or(0, |project| project.meta.slides.len());
+        if self.edit.as_ref().map_or(false, |edit| edit.idx >= count) {
+            self.edit = None;
+        }
+        self.slide_idx = self.slide_idx.min(count.saturating_sub(1));
+    }
+
+    fn select_slide_audio(&mut self, select: FileSelect, idx: usize, app: &App)
+        -> Result<(), FatalError>
+    {
+        let selected_file = match self.resolve_file_selection(select, SelectTarget::AudioOf(idx)) {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        let project = match self.project {
+            Some(ref mut project) => project,
+            None => {
+                self.status.push("Selecting an audio file without project does nothing. How did you end up here?");
+                return Ok(())
+            }
+        };
+
+        if project.meta.slides.len() <= idx {
+            self.status.push(format!("Slide index {} is out of range. How did you end up here?", idx));
+            return Ok(())
+        }
+
+        let mut source = match FileSource::new_from_existing(selected_file) {
+            Ok(source) => source,
+            Err(err) => {
+                self.status.push(format!("Error opening selected audio file: {:?}", err));
+                return Ok(());
+            }
+        };
+
+        project.import_audio(idx, &mut source)?;
+        // Stored at once, the web interface may store and we would reload without it.
+        if !self.store_project(app)? {
+            return Ok(());
+        }
+        self.status.push("Press `enter` to select next audio, `p` to play it, `s` to generate output");
+
+        Ok(())
+    }
+
+    fn compute_video(&mut self, app: &Arc<App>) {
+        let project = match self.project.take() {
+            Some(project) => project,
+            None => {
+                self.status.push("Generating video file without project does nothing. How did you end up here?");
+                return;
+            }
+        };
+
+        self.status.push("Generating video output, this may take a while.");
+        self.start_job(JobKind::Render, project, app, |project, app| {
+            project.assemble(app)?;
+            // The web interface may change the project meanwhile.
+            project.store_rendered()
+        });
+    }
+
+    fn resolve_file_selection(&mut self, mut select: FileSelect, kind: SelectTarget)
+        -> Option<PathBuf>
+    {
+        let selected_file = if let Some(select) = select.take_selected() {
+            select
+        } else {
+            self.status.push("no file selected");
+            return None;
+        };
+
+        match fs::metadata(&selected_file) {
+            Err(io) => {
+                self.status.push(format!("Failed to inspect file: {:?}", io));
+                return None;
+            }
+            Ok(meta) if meta.is_dir() => {
+                select.pivot(selected_file);
+                self.select = Some((select, kind));
+                return None;
+            }
+            Ok(meta) if !meta.is_file() => {
+                self.status.push("Neither a file nor a directory");
+                return None;
+            }
+            Ok(_) => Some(selected_file),
+        }
+    }
+
+    /// Play the audio of the selected slide, or stop what is playing.
+    fn toggle_playback(&mut self, app: &App) {
+        if let Some(mut playback) = self.playback.take() {
+            if let Ok(None) = playback.child.try_wait() {
+                self.status.push("Playback stopped");
+                return;
+            }
+        }
+
+        let project = match &mut self.project {
+            Some(project) => project,
+            None => {
+                self.status.push("No project to play audio from");
+                return;
+            }
+        };
+
+        let mut src = match project.meta.slides.get(self.slide_idx) {
+            Some(Slide { audio: Audio::File { src }, .. }) => src.clone(),
+            _ => {
+                self.status.push("Selected slide does not have any audio");
+                return;
+            }
+        };
+
+        if let Some(cue) = self.cue {
+            match app.ffmpeg.cued(cue, &src, &mut project.dir) {
+                Ok(cued) => src = cued,
+                Err(err) => {
+                    let cue = cue.name();
+                    self.status.push(format!("Failed to add the {} before the audio: {:?}", cue, err));
+                    return;
+                }
+            }
+        }
+
+        let ffplay = match require_tool("ffplay", None) {
+            Ok(ffplay) => ffplay,
+            Err(err) => {
+                self.status.push(err.to_string());
+                return;
+            }
+        };
+
+        let child = process::Command::new(ffplay.as_path())
+            .args(&["-nodisp", "-autoexit", "-loglevel", "quiet"])
+            .arg(&src)
+            .stdin(process::Stdio::null())
+            .stdout(process::Stdio::null())
+            .stderr(process::Stdio::null())
+            .spawn();
+
+        match child {
+            Ok(child) => {
+                self.playback = Some(Playback { child });
+                self.status.push(format!("Playing {}, `p` to stop", src.display()));
+            }
+            Err(err) => self.status.push(format!("Failed to start ffplay: {}", err)),
+        }
+    }
+
+    fn preview_slide(&mut self)
+        -> Result<(), FatalError>
+    {
+        let project = match &mut self.project {
+            Some(project) => project,
+            None => {
+                self.status.push("No project to open a slide from");
+                return Ok(());
+            },
+        };
+
+        project.thumbnail()?;
 
         let path = match project.meta.slides.get(self.slide_idx) {
             Some(Slide { svg: Some(svg), .. }) => svg,
             Some(Slide { png: Some(png), .. }) => png,
             Some(Slide { visual: Visual::Slide { src, .. }, .. }) => src,
             None => {
-                self.status = Some("Selected slide does not have any visual".into());
+                self.status.push("Selected slide does not have any visual");
                 return Ok(());
             },
         };
@@ -438,29 +1634,453 @@ impl Tui {
     }
 }
 
+impl StatusLog {
+    const CAPACITY: usize = 200;
+
+    /// Show a message in the status line and keep it in the log.
+    fn push(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        self.current = true;
+
+        if let Some(last) = self.entries.back_mut() {
+            if last.message == message {
+                last.at = SystemTime::now();
+                last.repeated += 1;
+                return;
+            }
+        }
+
+        if self.entries.len() >= Self::CAPACITY {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(LogEntry {
+            at: SystemTime::now(),
+            message,
+            repeated: 1,
+        });
+
+        if self.scroll > 0 {
+            // Keep the same entries in view while reading.
+            self.scroll = (self.scroll + 1).min(self.entries.len() - 1);
+        }
+    }
+
+    /// Clear the status line, the message stays in the log.
+    fn dismiss(&mut self) {
+        self.current = false;
+    }
+
+    /// The message for the status line.
+    fn current(&self) -> Option<&str> {
+        match self.entries.back() {
+            Some(entry) if self.current => Some(&entry.message),
+            _ => None,
+        }
+    }
+
+    /// Open and scroll the pane, at any time.
+    ///
+    /// Returns if the event was consumed.
+    fn use_keys(&mut self, event: &Event) -> bool {
+        let code = match *event {
+            Event::Key(KeyEvent { code, modifiers: KeyModifiers::NONE }) => code,
+            _ => return false,
+        };
+
+        match code {
+            KeyCode::Char('l') => {
+                self.open = !self.open;
+                self.scroll = 0;
+            }
+            KeyCode::Char('[') if self.open => {
+                self.scroll = (self.scroll + 1).min(self.entries.len().saturating_sub(1));
+            }
+            KeyCode::Char(']') if self.open => {
+                self.scroll = self.scroll.saturating_sub(1);
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn draw(&self, frame: &mut tui::Frame<'_, impl Backend>, rect: layout::Rect) {
+        let visible = usize::from(rect.height.saturating_sub(2));
+        let last = self.entries.len() - self.scroll.min(self.entries.len());
+        let first = last.saturating_sub(visible);
+
+        let lines = self.entries
+            .range(first..last)
+            .map(|entry| match entry.repeated {
+                1 => format!("{} {}", fmt_clock(entry.at), entry.message),
+                n => format!("{} {} (x{})", fmt_clock(entry.at), entry.message, n),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let title = if self.scroll > 0 {
+            format!("Log, {} newer (times in UTC)", self.scroll)
+        } else {
+            String::from("Log (times in UTC)")
+        };
+
+        let block = widgets::Block::default()
+            .title(title)
+            .borders(widgets::Borders::ALL);
+        frame.render_widget(widgets::Clear, rect);
+        frame.render_widget(widgets::Paragraph::new(lines).block(block), rect);
+    }
+}
+
+impl ResumePicker {
+    fn draw(&mut self, frame: &mut tui::Frame<'_, impl Backend>) {
+        let block_rect = frame.size().inner(&layout::Margin { horizontal: 5, vertical: 3 });
+        let items = self.projects
+            .iter()
+            .map(|project| widgets::ListItem::new(format!(
+                "{}, {} pages, changed {}",
+                project.title.as_deref().unwrap_or("Untitled"),
+                project.pages,
+                project.modified.map_or_else(|| String::from("at an unknown time"), fmt_http_date),
+            )))
+            .collect::<Vec<_>>();
+
+        let list = widgets::List::new(items)
+            .block(widgets::Block::default()
+                .title("Continue a project")
+                .borders(widgets::Borders::ALL))
+            .highlight_symbol("*");
+        frame.render_widget(widgets::Clear, block_rect);
+        frame.render_stateful_widget(list, block_rect, &mut self.state);
+    }
+}
+
+impl SlideEdit {
+    fn draw(&self, frame: &mut tui::Frame<'_, impl Backend>, project: &Project) {
+        let slide = match project.meta.slides.get(self.idx) {
+            Some(slide) => slide,
+            None => return,
+        };
+
+        let size = frame.size();
+        let rect = size.inner(&layout::Margin { horizontal: 5, vertical: 1 });
+        let rect = layout::Rect {
+            y: rect.y + rect.height.saturating_sub(8) / 2,
+            height: rect.height.min(8),
+            ..rect
+        };
+
+        let marker = |field| if field == self.field { ">" } else { " " };
+        let text = format!(
+            "{}Duration: {}{}\n\
+             {}Offset:   {}ms{}\n\
+             {}Tempo:    {}{}\n\
+             {}Enabled:  {}\n\
+             {}Subtitle: {}{}\n\
+             {}Overlay:  {}{}",
+            marker(EditField::Duration),
+            if self.duration.is_empty() { "length of the audio" } else { &self.duration },
+            if self.field == EditField::Duration { "_" } else { "" },
+            marker(EditField::Offset),
+            if self.offset.is_empty() { "0" } else { &self.offset },
+            if self.field == EditField::Offset { "_" } else { "" },
+            marker(EditField::Tempo),
+            if self.tempo.is_empty() { "as recorded" } else { &self.tempo },
+            if self.field == EditField::Tempo { "_" } else { "" },
+            marker(EditField::Enabled),
+            if slide.enabled { "yes" } else { "no" },
+            marker(EditField::Subtitle),
+            slide.subtitle.as_deref().unwrap_or(""),
+            if self.field == EditField::Subtitle { "_" } else { "" },
+            marker(EditField::Overlay),
+            slide.overlay.as_ref().map_or("", |overlay| &overlay.text),
+            if self.field == EditField::Overlay { "_" } else { "" },
+        );
+
+        let block = widgets::Block::default()
+            .title(format!("Slide {}", self.idx + 1))
+            .borders(widgets::Borders::ALL);
+        frame.render_widget(widgets::Clear, rect);
+        frame.render_widget(widgets::Paragraph::new(text).block(block), rect);
+    }
+}
+
+impl EditField {
+    fn next(self) -> Self {
+        match self {
+            EditField::Duration => EditField::Offset,
+            EditField::Offset => EditField::Tempo,
+            EditField::Tempo => EditField::Enabled,
+            EditField::Enabled => EditField::Subtitle,
+            EditField::Subtitle => EditField::Overlay,
+            EditField::Overlay => EditField::Duration,
+        }
+    }
+
+    fn prev(self) -> Self {
+        match self {
+            EditField::Duration => EditField::Overlay,
+            EditField::Offset => EditField::Duration,
+            EditField::Tempo => EditField::Offset,
+            EditField::Enabled => EditField::Tempo,
+            EditField::Subtitle => EditField::Enabled,
+            EditField::Overlay => EditField::Subtitle,
+        }
+    }
+}
+
+impl Drop for Playback {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl SlidePreview {
+    /// Pixels wide, sharp enough for a large terminal.
+    const WIDTH: u32 = 640;
+
+    fn draw(&mut self, frame: &mut tui::Frame<'_, impl Backend>, rect: layout::Rect, overlaid: bool) {
+        let title = match self.image {
+            Some((idx, _)) => format!("Page {}", idx + 1),
+            None => String::from("Page"),
+        };
+
+        let block = widgets::Block::default()
+            .title(title)
+            .borders(widgets::Borders::ALL);
+        let inner = block.inner(rect);
+        frame.render_widget(block, rect);
+
+        let image = match self.image {
+            Some((_, Some(ref image))) => image,
+            _ => return,
+        };
+
+        if !self.graphics.is_protocol() {
+            frame.render_widget(HalfBlocks(image), inner);
+        } else if !overlaid {
+            // The image would cover dialogs drawn over it.
+            self.area = Some(inner);
+        }
+    }
+
+    fn wanted(&self) -> Option<(usize, layout::Rect)> {
+        match (self.area, &self.image) {
+            (Some(area), Some((idx, Some(_)))) => Some((*idx, area)),
+            _ => None,
+        }
+    }
+}
+
+impl SelectTarget {
+    fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            SelectTarget::AudioOf(_) => &["wav", "mp3", "ogg", "flac", "m4a", "opus"],
+            SelectTarget::Project => &["pdf"],
+            SelectTarget::Output { .. } => &[],
+        }
+    }
+}
+
 impl FileSelect {
+   /// Start listing a directory, the entries are added by `poll_listing` as they arrive.
+   fn new(path: &Path, extensions: &'static [&'static str]) -> Self {
+        let mut select = FileSelect {
+            path: path.to_owned(),
+            idx: usize::MAX,
+            extensions,
+            filter: String::new(),
+            entries: vec![FileEntry::parent_of(path)],
+            listing: Some(Self::read_dir(path)),
+            files: vec![],
+            state: widgets::ListState::default(),
+        };
+        select.refilter();
+        select
+   }
+
    fn take_selected(&mut self) -> Option<PathBuf> {
        match self.files.get_mut(self.idx) {
             None => None,
-            Some(item) => Some(mem::take(item)),
+            Some(item) => Some(mem::take(&mut item.path)),
        }
    }
 
-   fn pivot(&mut self, folder: PathBuf) -> Result<(), io::Error> {
-        self.files = Self::read_dir(Path::new(&folder))?;
+   fn pivot(&mut self, folder: PathBuf) {
+        self.entries = vec![FileEntry::parent_of(&folder)];
+        // Dropping the previous receiver stops its thread.
+        self.listing = Some(Self::read_dir(&folder));
         if let Ok(canonical) = folder.canonicalize() {
             self.path = canonical;
         }
-        Ok(())
+        self.filter.clear();
+        self.idx = usize::MAX;
+        self.refilter();
    }
 
-    fn read_dir(path: &Path) -> Result<Vec<PathBuf>, io::Error> {
-        let mut entries = fs::read_dir(path)?
-            .map(|r| r.map(|entry| entry.path()))
-            // TODO: potentially collecting for multiple seconds..
-            .collect::<Result<Vec<PathBuf>, _>>()?;
-        entries.push(path.join(".."));
-        entries.sort();
-        Ok(entries)
+    /// Add the entries read so far, keeping the list sorted.
+    fn poll_listing(&mut self) -> Result<(), io::Error> {
+        let listing = match self.listing {
+            Some(ref listing) => listing,
+            None => return Ok(()),
+        };
+
+        let mut changed = false;
+        let result = loop {
+            match listing.try_recv() {
+                Ok(Ok(entry)) => {
+                    // After the parent, which always comes first.
+                    let pos = self.entries[1..]
+                        .binary_search_by(|other| other.path.cmp(&entry.path))
+                        .unwrap_or_else(|pos| pos);
+                    self.entries.insert(pos + 1, entry);
+                    changed = true;
+                }
+                Ok(Err(err)) => break Err(err),
+                Err(mpsc::TryRecvError::Empty) => break Ok(()),
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.listing = None;
+                    break Ok(());
+                }
+            }
+        };
+
+        if result.is_err() {
+            self.listing = None;
+        }
+
+        if changed {
+            self.refilter();
+        }
+
+        result
+    }
+
+    /// Choose the offered entries again, after the filter changed.
+    fn refilter(&mut self) {
+        let filter = self.filter.to_lowercase();
+        let extensions = self.extensions;
+        let mut files = self.entries
+            .iter()
+            .filter(|entry| entry.is_dir || extensions.is_empty() || {
+                let ext = entry.path.extension().map(|ext| ext.to_string_lossy().to_lowercase());
+                extensions.iter().any(|&wanted| ext.as_deref() == Some(wanted))
+            })
+            .filter_map(|entry| Some((entry.matches(&filter)?, entry)))
+            .collect::<Vec<_>>();
+        // Stable, such that entries with an equal match stay sorted by name.
+        files.sort_by_key(|&(rank, _)| rank);
+
+        // Keep the selection while entries arrive, otherwise take the best match of the filter.
+        let selected = self.files.get(self.idx).map(|entry| entry.path.clone());
+        self.files = files.into_iter().map(|(_, entry)| entry.clone()).collect();
+        self.idx = match selected.and_then(|path| self.files.iter().position(|entry| entry.path == path)) {
+            Some(idx) => idx,
+            None if filter.is_empty() || self.files.is_empty() => usize::MAX,
+            None => 0,
+        };
+    }
+
+    /// Read the directory on another thread, which can take seconds on network mounts.
+    fn read_dir(path: &Path) -> mpsc::Receiver<io::Result<FileEntry>> {
+        let (send, entries) = mpsc::channel();
+        let path = path.to_owned();
+
+        thread::spawn(move || {
+            let dir = match fs::read_dir(&path) {
+                Ok(dir) => dir,
+                Err(err) => {
+                    let _ = send.send(Err(err));
+                    return;
+                }
+            };
+
+            for entry in dir {
+                let entry = entry.map(|entry| {
+                    let path = entry.path();
+                    // Follows links, unlike the metadata of the entry.
+                    let meta = fs::metadata(&path).ok();
+                    FileEntry {
+                        is_dir: meta.as_ref().map_or(false, |meta| meta.is_dir()),
+                        size: meta.as_ref().map_or(0, |meta| meta.len()),
+                        modified: meta.and_then(|meta| meta.modified().ok()),
+                        path,
+                    }
+                });
+
+                // The selection was closed or moved to another directory.
+                if send.send(entry).is_err() {
+                    return;
+                }
+            }
+        });
+
+        entries
+    }
+}
+
+impl FileEntry {
+    fn parent_of(path: &Path) -> Self {
+        FileEntry {
+            path: path.join(".."),
+            is_dir: true,
+            size: 0,
+            modified: None,
+        }
+    }
+
+    fn name(&self) -> String {
+        match self.path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => String::from(".."),
+        }
+    }
+
+    /// How well the name matches a lowercase filter: `0` for a prefix, `1` for the letters in
+    /// order, `None` if not at all. The parent is always offered.
+    fn matches(&self, filter: &str) -> Option<u8> {
+        let name = self.name().to_lowercase();
+        if self.path.file_name().is_none() || name.starts_with(filter) {
+            return Some(0);
+        }
+
+        let mut letters = name.chars();
+        if filter.chars().all(|wanted| letters.any(|ch| ch == wanted)) {
+            Some(1)
+        } else {
+            None
+        }
+    }
+
+    fn describe(&self) -> String {
+        if self.is_dir {
+            return format!("{}/", self.name());
+        }
+
+        let modified = self.modified.map_or_else(String::new, fmt_http_date);
+        format!("{:<40} {:>10}  {}", self.name(), human_size(self.size), modified)
+    }
+}
+
+/// The time of day, as in `08:49:37`.
+fn fmt_clock(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs()) % 86400;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
     }
+    format!("{:.1} {}", size, UNITS[unit])
 }
@@ -0,0 +1,132 @@
+//! Conditional requests, so that clients need not download unchanged files again.
+use std::{convert::TryFrom, fs};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+/// What identifies a version of a file to a cache.
+pub struct Validators {
+    pub etag: String,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    /// For content without a modification time, such as assets embedded into the binary.
+    pub fn of_content(data: &[u8]) -> Self {
+        let hash = Sha256::digest(data);
+        let etag = hash[..16].iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+        Validators {
+            etag: format!("\"{}\"", etag),
+            last_modified: None,
+        }
+    }
+
+    /// For a file on disk, without reading it.
+    pub fn of_file(meta: &fs::Metadata) -> Self {
+        let modified = meta.modified().ok();
+        let nanos = modified
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_nanos());
+        Validators {
+            etag: format!("\"{:x}-{:x}\"", meta.len(), nanos),
+            last_modified: modified,
+        }
+    }
+
+    /// The value of the `Last-Modified` header.
+    pub fn last_modified_header(&self) -> Option<String> {
+        self.last_modified.map(fmt_http_date)
+    }
+
+    /// If the copy of the client, as described by its conditional headers, is still current.
+    pub fn is_fresh(&self, if_none_match: Option<&str>, if_modified_since: Option<&str>) -> bool {
+        // The tag is more precise and takes precedence when both are present.
+        if let Some(tags) = if_none_match {
+            return tags
+                .split(',')
+                .map(|tag| tag.trim())
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == self.etag);
+        }
+
+        match (if_modified_since.and_then(parse_http_date), self.last_modified) {
+            // The header has a resolution of seconds only.
+            (Some(since), Some(modified)) => modified
+                .duration_since(since)
+                .map_or(true, |newer| newer < Duration::from_secs(1)),
+            _ => false,
+        }
+    }
+}
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Format as in `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn fmt_http_date(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    let days = secs / 86400;
+    let (year, month, day) = civil_from_days(days as i64);
+    let secs = secs % 86400;
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+    )
+}
+
+/// Parse the format of `fmt_http_date`, the only one that current clients send.
+pub fn parse_http_date(date: &str) -> Option<SystemTime> {
+    let mut parts = date.split_whitespace().skip(1);
+    let day: u32 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|&name| name == month)? as u32 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+
+    let mut clock = parts.next()?.split(':').map(|part| part.parse::<u64>().ok());
+    let hours = clock.next()??;
+    let minutes = clock.next()??;
+    let seconds = clock.next()??;
+
+    if parts.next()? != "GMT" || day == 0 || day > 31 || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    let secs = u64::try_from(days).ok()? * 86400 + hours * 3600 + minutes * 60 + seconds;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+// Conversions between days since the epoch and the proleptic Gregorian calendar, after
+// <http://howardhinnant.github.io/date_algorithms.html>.
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = i64::from(month);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
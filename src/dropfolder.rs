@@ -0,0 +1,292 @@
+//! Render the decks put into a directory, such as a shared drive of a department.
+//!
+//! Each `deck.pdf` in the input directory is converted to `deck.mp4` in the output directory,
+//! narrated by the files of the directory `deck/` beside it named by page, such as
+//! `deck/slide-01.wav`. A deck is rendered once its files stopped changing between two looks at
+//! the directory. A state file in the output directory remembers the version of each deck that
+//! was rendered, such that it is rendered again only after it changes, also across restarts.
+use std::collections::{BTreeMap, HashMap};
+use std::{fs, io, path::Path, path::PathBuf, sync::Arc, thread, time::Duration};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ErrorClass, FatalError};
+use crate::app::App;
+use crate::pipeline::Pipeline;
+use crate::project::Project;
+
+pub(crate) struct DropFolder {
+    input: PathBuf,
+    output: PathBuf,
+    state: State,
+    /// The decks as of the last look, those that are still the same are ready.
+    seen: HashMap<String, Fingerprint>,
+    /// The state changed since it was last stored.
+    unsaved: bool,
+}
+
+/// A pdf with its narration.
+pub(crate) struct Deck {
+    pub name: String,
+    pub pdf: PathBuf,
+    /// The directory with the narration, if there is one.
+    pub audio: Option<PathBuf>,
+    fingerprint: Fingerprint,
+}
+
+/// The size and modification time of each file of a deck.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Fingerprint(Vec<Stamp>);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Stamp {
+    name: String,
+    size: u64,
+    /// Milliseconds since the epoch.
+    modified: Option<u64>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct State {
+    decks: BTreeMap<String, Rendered>,
+}
+
+/// The outcome of rendering one version of a deck.
+#[derive(Serialize, Deserialize)]
+struct Rendered {
+    fingerprint: Fingerprint,
+    video: Option<PathBuf>,
+    error: Option<String>,
+    /// The error was not caused by the deck, try again after a restart.
+    #[serde(default)]
+    retry: bool,
+}
+
+/// Render the decks of `input` into `output` until interrupted.
+pub fn watch(app: Arc<App>, input: &Path, output: &Path) -> Result<(), FatalError> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let on_signal = stop.clone();
+    // Without our hook, an interrupt ends us at once which is fine as well.
+    let _ = ctrlc::set_handler(move || on_signal.store(true, Ordering::SeqCst));
+
+    let pipeline = Pipeline::from_app(app);
+    let mut folder = DropFolder::new(input, output)?;
+    tracing::info!(input = %input.display(), output = %output.display(), "Watching for decks");
+
+    while !stop.load(Ordering::SeqCst) {
+        // A shared drive may be away for a moment, we try again at the next poll.
+        if folder.unsaved {
+            if let Err(err) = folder.store() {
+                tracing::warn!(?err, "Could not store the state of the decks");
+            }
+        }
+
+        let ready = folder.ready().unwrap_or_else(|err| {
+            tracing::warn!(?err, "Could not look at the decks");
+            vec![]
+        });
+
+        for deck in ready {
+            let result = folder.render(&pipeline, &deck);
+            // The interrupt also reaches ffmpeg, this is not a failure of the deck.
+            if stop.load(Ordering::SeqCst) {
+                break;
+            }
+            // The outcome is remembered regardless, and stored again later.
+            if let Err(err) = folder.finish(deck, result) {
+                tracing::warn!(?err, "Could not store the state of the decks");
+            }
+        }
+
+        let mut slept = Duration::from_secs(0);
+        while slept < DropFolder::POLL && !stop.load(Ordering::SeqCst) {
+            thread::sleep(DropFolder::TICK);
+            slept += DropFolder::TICK;
+        }
+    }
+
+    tracing::info!("Stopped watching for decks");
+    Ok(())
+}
+
+impl DropFolder {
+    /// How long to wait between looks at the input directory.
+    const POLL: Duration = Duration::from_secs(5);
+    /// How often to check for an interrupt while waiting.
+    const TICK: Duration = Duration::from_millis(100);
+    /// Hidden, such that it is not mistaken for a video.
+    const STATE: &'static str = ".vid-from-pdf-state.json";
+
+    /// Watch `input`, continuing with the state in `output`.
+    pub fn new(input: &Path, output: &Path) -> Result<Self, FatalError> {
+        fs::create_dir_all(output)?;
+        let mut state: State = match fs::read(output.join(Self::STATE)) {
+            Ok(content) => serde_json::from_slice(&content).map_err(FatalError::Corrupt)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => State::default(),
+            Err(err) => return Err(err.into()),
+        };
+        state.decks.retain(|_, rendered| !rendered.retry);
+
+        Ok(DropFolder {
+            input: input.to_owned(),
+            output: output.to_owned(),
+            state,
+            seen: HashMap::new(),
+            unsaved: false,
+        })
+    }
+
+    /// Look at the input directory, returning the decks that were not rendered yet and did not
+    /// change since the last look.
+    pub fn ready(&mut self) -> Result<Vec<Deck>, FatalError> {
+        let mut ready = vec![];
+        let mut seen = HashMap::new();
+
+        for deck in self.decks()? {
+            let unchanged = self.seen.get(&deck.name) == Some(&deck.fingerprint);
+            let rendered = self.state.decks
+                .get(&deck.name)
+                .map_or(false, |rendered| rendered.fingerprint == deck.fingerprint);
+            seen.insert(deck.name.clone(), deck.fingerprint.clone());
+            if unchanged && !rendered {
+                ready.push(deck);
+            }
+        }
+
+        self.seen = seen;
+        Ok(ready)
+    }
+
+    /// Convert a deck to a video in the output directory.
+    pub fn render(&self, pipeline: &Pipeline, deck: &Deck) -> Result<PathBuf, FatalError> {
+        tracing::info!(deck = %deck.name, "Rendering");
+        let mut project = pipeline.create(&deck.pdf)?;
+        let result = self.convert(pipeline, &mut project, deck);
+        // The video was copied, the project is not needed anymore.
+        let _ = fs::remove_dir_all(project.dir.work_dir());
+        result
+    }
+
+    /// Remember the outcome of rendering a deck, such that it is not rendered again.
+    pub fn finish(&mut self, deck: Deck, result: Result<PathBuf, FatalError>)
+        -> Result<(), FatalError>
+    {
+        let rendered = match result {
+            Ok(video) => {
+                tracing::info!(deck = %deck.name, video = %video.display(), "Rendered");
+                Rendered {
+                    fingerprint: deck.fingerprint,
+                    video: Some(video),
+                    error: None,
+                    retry: false,
+                }
+            }
+            Err(err) => {
+                tracing::error!(deck = %deck.name, ?err, "Rendering failed");
+                Rendered {
+                    fingerprint: deck.fingerprint,
+                    video: None,
+                    error: Some(err.to_string()),
+                    retry: err.class() != ErrorClass::User,
+                }
+            }
+        };
+
+        self.state.decks.insert(deck.name, rendered);
+        self.store()
+    }
+
+    fn convert(&self, pipeline: &Pipeline, project: &mut Project, deck: &Deck)
+        -> Result<PathBuf, FatalError>
+    {
+        pipeline.explode(project)?;
+        if let Some(audio) = &deck.audio {
+            let attached = pipeline.attach_audio_files(project, audio)?;
+            tracing::info!(deck = %deck.name, pages = attached.len(), "Attached audio");
+        }
+        let video = pipeline.render(project)?;
+
+        // Others watching the output directory must not see a partial video.
+        let target = self.output.join(format!("{}.mp4", deck.name));
+        let partial = self.output.join(format!(".{}.mp4.partial", deck.name));
+        fs::copy(video, &partial)?;
+        fs::rename(partial, &target)?;
+        Ok(target)
+    }
+
+    fn decks(&self) -> Result<Vec<Deck>, FatalError> {
+        let mut decks = vec![];
+        for entry in fs::read_dir(&self.input)? {
+            let pdf = entry?.path();
+            let is_pdf = pdf
+                .extension()
+                .map_or(false, |ext| ext.eq_ignore_ascii_case("pdf"));
+            let name = match pdf.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) if is_pdf && !name.starts_with('.') && pdf.is_file() => name,
+                _ => continue,
+            };
+
+            let dir = self.input.join(name);
+            let audio = Some(dir).filter(|dir| dir.is_dir());
+            let fingerprint = match Fingerprint::of(&pdf, audio.as_deref()) {
+                Ok(fingerprint) => fingerprint,
+                // Removed while we looked, it is gone at the next look.
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            decks.push(Deck {
+                name: name.to_owned(),
+                pdf,
+                audio,
+                fingerprint,
+            });
+        }
+
+        decks.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(decks)
+    }
+
+    fn store(&mut self) -> Result<(), FatalError> {
+        self.unsaved = true;
+        let file = self.output.join(Self::STATE);
+        let partial = file.with_extension("partial");
+        let content = serde_json::to_vec_pretty(&self.state).map_err(io::Error::from)?;
+        fs::write(&partial, content)?;
+        fs::rename(partial, file)?;
+        self.unsaved = false;
+        Ok(())
+    }
+}
+
+impl Fingerprint {
+    fn of(pdf: &Path, audio: Option<&Path>) -> io::Result<Self> {
+        let mut stamps = vec![Stamp::of(pdf, String::new())?];
+        if let Some(dir) = audio {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                let name = match path.file_name().and_then(|name| name.to_str()) {
+                    Some(name) if !name.starts_with('.') && path.is_file() => name.to_owned(),
+                    _ => continue,
+                };
+                stamps.push(Stamp::of(&path, name)?);
+            }
+        }
+
+        stamps.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Fingerprint(stamps))
+    }
+}
+
+impl Stamp {
+    fn of(path: &Path, name: String) -> io::Result<Self> {
+        let meta = fs::metadata(path)?;
+        let modified = meta.modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_millis() as u64);
+        Ok(Stamp { name, size: meta.len(), modified })
+    }
+}
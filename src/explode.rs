@@ -1,60 +1,191 @@
 /// Turn a pdf into multiple images of that each page.
-use std::{collections::BTreeMap, fmt, fs, io, process::Command};
-use image::{io::Reader as ImageReader, imageops};
+use std::{collections::BTreeMap, fmt, fs, io, path::Path, path::PathBuf, process::Command, time::Duration};
+use std::io::Read as _;
+use image::{io::Reader as ImageReader, imageops, GenericImageView as _};
 use mupdf::Document;
 use which::CanonicalPath;
 
 use crate::FatalError;
+use crate::app::{Frame, LimitExceeded, Limits};
+use crate::cache::RenderCache;
+use crate::progress::{Progress, Stage};
 use crate::sink::{Sink, Source};
 use crate::resources::{RequiredToolError, require_tool};
+use crate::watchdog::Watchdog;
 
 pub trait ExplodePdf: Send + Sync + 'static {
     /// Create all pages as files, import them into sink.
-    fn explode(&self, src: &mut dyn Source, into: &mut Sink) -> Result<(), FatalError>;
+    ///
+    /// Pages that can not be converted should be imported as a placeholder and reported instead
+    /// of failing the whole document. Each page advances the `Explode` stage of `progress`. Pages
+    /// converted before may be taken from the render cache of the sink.
+    fn explode(&self, src: &mut dyn Source, into: &mut Sink, progress: &Progress)
+        -> Result<ExplodeReport, FatalError>;
     /// Describe the pdf exploder to a `-verbose` cli user.
     fn verbose_describe(&self, into: &mut dyn io::Write) -> Result<(), FatalError>;
+    /// Short name of the library or tool doing the work.
+    fn name(&self) -> &'static str;
+    /// Open the pdf without converting anything, and count its pages.
+    ///
+    /// Returns `None` if that is not possible any faster than exploding.
+    fn probe(&self, _: &Path) -> Result<Option<usize>, FatalError> {
+        Ok(None)
+    }
+}
+
+/// Where a pdf must start, though readers tolerate some garbage before it.
+const PDF_MAGIC: &[u8] = b"%PDF-";
+
+/// If the start of a file contains the header of a pdf.
+pub fn has_pdf_header(head: &[u8]) -> bool {
+    head.len() >= PDF_MAGIC.len()
+        && head.windows(PDF_MAGIC.len()).take(1024).any(|window| window == PDF_MAGIC)
+}
+
+/// Check a pdf cheaply, before converting it.
+pub fn validate(explode: &dyn ExplodePdf, path: &Path, limits: &Limits)
+    -> Result<(), FatalError>
+{
+    let limit = limits.pdf_size();
+    if fs::metadata(path)?.len() > limit {
+        return Err(LimitExceeded::PdfSize { limit }.into());
+    }
+
+    let mut head = Vec::with_capacity(1024 + PDF_MAGIC.len());
+    fs::File::open(path)?
+        .take(1024 + PDF_MAGIC.len() as u64)
+        .read_to_end(&mut head)?;
+    if !has_pdf_header(&head) {
+        return Err(FatalError::BadPdf("The file has no `%PDF-` header.".into()));
+    }
+
+    if let Some(count) = explode.probe(path)? {
+        let (count, limit) = (count as u64, limits.page_count());
+        if count > limit {
+            return Err(LimitExceeded::PageCount { count, limit }.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Problems encountered while exploding a pdf that did not stop the conversion.
+#[derive(Default)]
+pub struct ExplodeReport {
+    /// Pages that were replaced by a placeholder, with the reason.
+    pub failed: Vec<(usize, String)>,
+    /// Width by height of the first page that could be read.
+    pub aspect: Option<f32>,
+    /// Pages in portrait that were turned, to fill the video like the landscape pages around them.
+    pub rotated: Vec<usize>,
+}
+
+impl ExplodeReport {
+    /// Note the shape of a page, returning if it should be turned clockwise.
+    fn orient(&mut self, idx: usize, width: f32, height: f32, frame: &Frame) -> bool {
+        if !(height > 0.0) {
+            return false;
+        }
+
+        let aspect = width / height;
+        let first = *self.aspect.get_or_insert(aspect);
+        // Portrait decks get a video with bars anyways, their pages are better left upright.
+        let rotate = frame.rotate && first > 1.0 && aspect < 1.0;
+        if rotate {
+            self.rotated.push(idx);
+        }
+        rotate
+    }
 }
 
 struct PdfToPpm {
     exe: CanonicalPath,
+    /// Counts the pages before any are converted, shipped with `pdftoppm` by poppler.
+    info: Option<CanonicalPath>,
+    frame: Frame,
 }
 
-struct MuPdf {}
+struct MuPdf {
+    /// Pages are scaled to fit inside its padding.
+    frame: Frame,
+}
 
 pub enum LoadPdfExploderError {
     CantFindPdfToPpm(RequiredToolError),
 }
 
 impl ExplodePdf for PdfToPpm {
-    fn explode(&self, src: &mut dyn Source, sink: &mut Sink) -> Result<(), FatalError> {
+    fn explode(&self, src: &mut dyn Source, sink: &mut Sink, progress: &Progress)
+        -> Result<ExplodeReport, FatalError>
+    {
+        // The page count is only known once pdftoppm is done, the resizing is counted instead.
+        progress.start(Stage::Explode, 0);
         PdfToPpm::explode(self, src, sink)?;
         let paths = sink.imported().collect::<Vec<_>>();
-        for mut path in paths {
-            let image = ImageReader::open(&path)?
+        progress.start(Stage::Explode, paths.len());
+        let mut report = ExplodeReport::default();
+        for (idx, mut path) in paths.into_iter().enumerate() {
+            let mut image = ImageReader::open(&path)?
                 .with_guessed_format()?
                 .decode()?;
-            let image = image.resize(1920, 1080, imageops::FilterType::Lanczos3);
+            if report.orient(idx, image.width() as f32, image.height() as f32, &self.frame) {
+                image = image.rotate90();
+            }
+            let (width, height) = self.frame.inner();
+            let image = image.resize(width, height, imageops::FilterType::Lanczos3);
             path.set_extension("ppm");
             image.save(&path)?;
             sink.import(path);
+            progress.advance();
         }
-        Ok(())
+        Ok(report)
     }
 
     fn verbose_describe(&self, into: &mut dyn io::Write) -> Result<(), FatalError> {
         writeln!(into, "Using pdftoppm to deconstruct pdf")?;
         writeln!(into, " pdftoppm: {}", self.exe.display())?;
+        if let Some(info) = &self.info {
+            writeln!(into, " pdfinfo: {}", info.display())?;
+        }
         Ok(())
     }
+
+    fn name(&self) -> &'static str {
+        "pdftoppm"
+    }
+
+    fn probe(&self, path: &Path) -> Result<Option<usize>, FatalError> {
+        let info = match &self.info {
+            Some(info) => info,
+            None => return Ok(None),
+        };
+
+        let output = Watchdog::deadline(Duration::from_secs(60)).output_required("pdfinfo", {
+            Command::new(info).arg(path)
+        })?;
+
+        if !output.status.success() {
+            return Err(FatalError::tool_failed("pdfinfo", &output));
+        }
+
+        let pages = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .find_map(|line| line.strip_prefix("Pages:"))
+            .and_then(|count| count.trim().parse().ok());
+        Ok(pages)
+    }
 }
 
 impl PdfToPpm {
-    fn new() -> Result<PdfToPpm, LoadPdfExploderError> {
-        let pdf_to_ppm = require_tool("pdftoppm")
+    fn new(frame: Frame) -> Result<PdfToPpm, LoadPdfExploderError> {
+        let pdf_to_ppm = require_tool("pdftoppm", None)
             .map_err(LoadPdfExploderError::CantFindPdfToPpm)?;
         // TODO: version validation?
         Ok(PdfToPpm {
             exe: pdf_to_ppm,
+            // Without it, the page limit is checked only after converting all pages.
+            info: require_tool("pdfinfo", None).ok(),
+            frame,
         })
     }
 
@@ -66,13 +197,18 @@ impl PdfToPpm {
 
         // TODO: we could fancily check that the paths do not collide.
 
-        Command::new(&self.exe)
-            .current_dir(sink.work_dir())
-            .args(&["-forcenum", "-rx", "600", "-ry", "600"])
-            .arg(path)
-            .arg("pages")
-            .status()
-            .expect("Converting pdf with `pdftoppm` failed");
+        // Even in a dry run, the pages are needed for the later steps.
+        let output = Watchdog::deadline(Duration::from_secs(30*60)).output_required("pdftoppm", {
+            Command::new(&self.exe)
+                .current_dir(sink.work_dir())
+                .args(&["-forcenum", "-rx", "600", "-ry", "600"])
+                .arg(path)
+                .arg("pages")
+        })?;
+
+        if !output.status.success() {
+            return Err(FatalError::tool_failed("pdftoppm", &output));
+        }
 
         let mut entries = BTreeMap::new();
         for entry in fs::read_dir(sink.work_dir())? {
@@ -109,9 +245,9 @@ impl PdfToPpm {
 }
 
 impl dyn ExplodePdf {
-    pub fn new() -> Result<Box<Self>, LoadPdfExploderError> {
+    pub fn new(frame: Frame) -> Result<Box<Self>, LoadPdfExploderError> {
         // TODO: detect if ffmpeg was compiled with librsvg.
-        Ok(Box::new(MuPdf {}))
+        Ok(Box::new(MuPdf { frame }))
     }
 }
 
@@ -126,46 +262,190 @@ impl fmt::Display for LoadPdfExploderError {
 }
 
 impl MuPdf {
-    /// Rescale page and normalize placement without distorting.
-    fn normalize_page_matrix(&self, bounds: mupdf::Rect) -> mupdf::Matrix {
+    /// Rescale page and normalize placement without distorting, turning it clockwise if asked.
+    fn normalize_page_matrix(&self, bounds: mupdf::Rect, rotate: bool) -> mupdf::Matrix {
         let (width, height) = (bounds.width(), bounds.height());
         let origin = bounds.origin();
+        let (shown_w, shown_h) = if rotate { (height, width) } else { (width, height) };
 
-        let mut matrix = mupdf::Matrix::IDENTITY;
-        let scale_w = 1920.0/width;
-        let scale_h = 1080.0/height;
+        let (inner_w, inner_h) = self.frame.inner();
+        let scale_w = inner_w as f32/shown_w;
+        let scale_h = inner_h as f32/shown_h;
         // Scale to contain.
-        let scale = scale_w.min(scale_h);
-        matrix.pre_translate(-origin.x, -origin.y);
-        matrix.scale(scale, scale);
+        let s = scale_w.min(scale_h);
 
-        matrix
+        // Points map to `(a*x + c*y + e, b*x + d*y + f)`.
+        if rotate {
+            // The left edge of the page becomes its top edge.
+            mupdf::Matrix { a: 0.0, b: s, c: -s, d: 0.0, e: s * (height + origin.y), f: -s * origin.x }
+        } else {
+            mupdf::Matrix { a: s, b: 0.0, c: 0.0, d: s, e: -s * origin.x, f: -s * origin.y }
+        }
     }
 
-    fn convert_document(&self, path: &str, sink: &mut Sink) -> Result<(), mupdf::Error> {
-        let document = Document::open(path)?;
+    fn convert_document(&self, path: &str, sink: &mut Sink, progress: &Progress)
+        -> Result<ExplodeReport, FatalError>
+    {
+        let document = Document::open(path)
+            .map_err(|err| FatalError::BadPdf(err.to_string()))?;
+        let mut report = ExplodeReport::default();
+        let pages = document.page_count().map_or(0, |count| count.max(0) as usize);
+        progress.start(Stage::Explode, pages);
+
+        let cache = sink.render_cache().cloned();
+        let digest = match cache {
+            Some(_) => Some(RenderCache::digest(Path::new(path))?),
+            None => None,
+        };
+
+        for (idx, page) in (&document).into_iter().enumerate() {
+            let converted = page
+                .map_err(PageError::Pdf)
+                .and_then(|page| {
+                    let bounds = page.bounds()?;
+                    let rotate = report.orient(idx, bounds.width(), bounds.height(), &self.frame);
+                    let (width, height) = self.frame.inner();
+                    let key = digest.as_ref().map(|digest| {
+                        format!("{}:{}:{}:{}x{}:{}", self.name(), digest, idx, width, height, rotate)
+                    });
+
+                    let cached = cache.as_ref().zip(key.as_ref())
+                        .and_then(|(cache, key)| cache.get(key, sink));
+                    if let Some(path) = cached {
+                        sink.import(path);
+                        return Ok(());
+                    }
 
-        for page in &document {
-            let page = page?;
-            let matrix = self.normalize_page_matrix(page.bounds()?);
-            let mut svg = io::Cursor::new(page.to_svg(&matrix)?);
-            let filepath = sink.store_to_file(&mut svg)?;
-            sink.import(filepath);
+                    let path = self.convert_page(&page, rotate, sink)?;
+                    if let (Some(cache), Some(key)) = (&cache, &key) {
+                        if let Err(err) = cache.put(key, &path) {
+                            tracing::debug!("Can't cache page: {}", err);
+                        }
+                    }
+                    sink.import(path);
+                    Ok(())
+                });
+
+            match converted {
+                Ok(()) => {},
+                Err(PageError::Fatal(err)) => return Err(err),
+                Err(PageError::Pdf(err)) => {
+                    let reason = err.to_string();
+                    let placeholder = Self::placeholder(idx, &reason);
+                    let filepath = sink.store_to_file(&mut io::Cursor::new(placeholder))?;
+                    sink.import(filepath);
+                    report.failed.push((idx, reason));
+                }
+            }
+
+            progress.advance();
         }
 
-        Ok(())
+        // The pages were converted, a cache that is too large does not change that.
+        if let Some(Err(err)) = cache.as_ref().map(RenderCache::evict) {
+            tracing::warn!(%err, "Could not evict pages from the cache");
+        }
+
+        Ok(report)
+    }
+
+    /// Convert a page, returning the file for the sink to import.
+    fn convert_page(&self, page: &mupdf::Page, rotate: bool, sink: &mut Sink)
+        -> Result<PathBuf, PageError>
+    {
+        let matrix = self.normalize_page_matrix(page.bounds()?, rotate);
+        let svg = page.to_svg(&matrix)?;
+
+        // Scanned pages are a single large image. Going through svg would only re-encode it,
+        // and rasterizing that svg is slow. Let mupdf scale the image directly instead.
+        if Self::is_image_only(&svg) {
+            let pixmap = page.to_pixmap(&matrix, &mupdf::Colorspace::device_rgb(), 0.0, false)?;
+            let image = Self::pixmap_to_image(&pixmap);
+            let mut unique = sink.unique_path().map_err(PageError::Fatal)?;
+            unique.path.set_extension("png");
+            image
+                .save_with_format(&unique.path, image::ImageFormat::Png)
+                .map_err(|err| PageError::Fatal(err.into()))?;
+            Ok(unique.path)
+        } else {
+            sink.store_to_file(&mut io::Cursor::new(svg))
+                .map_err(|err| PageError::Fatal(err.into()))
+        }
+    }
+
+    /// An svg slide telling the user that a page could not be converted.
+    fn placeholder(idx: usize, reason: &str) -> String {
+        let mut escaped = String::with_capacity(reason.len());
+        for ch in reason.chars() {
+            match ch {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                other => escaped.push(other),
+            }
+        }
+
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"1920\" height=\"1080\">\
+            <rect width=\"1920\" height=\"1080\" fill=\"#303030\"/>\
+            <text x=\"960\" y=\"500\" font-size=\"64\" text-anchor=\"middle\" fill=\"#ffffff\">\
+            Page {} could not be converted</text>\
+            <text x=\"960\" y=\"600\" font-size=\"32\" text-anchor=\"middle\" fill=\"#c0c0c0\">\
+            {}</text></svg>",
+            idx + 1,
+            escaped,
+        )
+    }
+
+    /// Check if the svg of a page only draws images.
+    ///
+    /// This is a heuristic on the output of the mupdf svg device. Paths in clip paths only
+    /// restrict the visible area of images, images may be referenced from a symbol with `use`.
+    fn is_image_only(svg: &str) -> bool {
+        let images = svg.matches("<image").count();
+        let image_uses = svg.matches("<use xlink:href=\"#image").count();
+        let clips = svg.matches("<clipPath").count();
+        let drawing = svg.matches("<path").count()
+            + svg.matches("<text").count()
+            + svg.matches("<use").count() - image_uses;
+        images > 0 && drawing <= clips
+    }
+
+    fn pixmap_to_image(pixmap: &mupdf::Pixmap) -> image::RgbImage {
+        let (width, height) = (pixmap.width() as u32, pixmap.height() as u32);
+        let channels = pixmap.n() as usize;
+        let samples = pixmap.samples();
+        let stride = samples.len() / (height as usize).max(1);
+
+        let mut image = image::RgbImage::new(width, height);
+        for (y, row) in samples.chunks(stride.max(1)).take(height as usize).enumerate() {
+            for (x, pixel) in row.chunks(channels).take(width as usize).enumerate() {
+                let rgb = match *pixel {
+                    [r, g, b, ..] => [r, g, b],
+                    // Grayscale, with or without alpha.
+                    [l, ..] => [l, l, l],
+                    [] => continue,
+                };
+                image.put_pixel(x as u32, y as u32, image::Rgb(rgb));
+            }
+        }
+
+        image
     }
 }
 
 impl ExplodePdf for MuPdf {
-    fn explode(&self, src: &mut dyn Source, sink: &mut Sink) -> Result<(), FatalError> {
+    fn explode(&self, src: &mut dyn Source, sink: &mut Sink, progress: &Progress)
+        -> Result<ExplodeReport, FatalError>
+    {
         let path = sink.store_to_file(src.as_buf_read())?;
         match path.to_str() {
             None => Err(FatalError::Io(io::Error::new(
                 io::ErrorKind::Other,
                 "Non-UTF8 path is not supported",
             ))),
-            Some(path) => self.convert_document(path, sink).map_err(fatal_pdf_page)
+            Some(path) => self.convert_document(path, sink, progress),
         }
     }
 
@@ -173,11 +453,34 @@ impl ExplodePdf for MuPdf {
         writeln!(into, "Using `mupdf` to deconstruct pdf")?;
         Ok(())
     }
+
+    fn name(&self) -> &'static str {
+        "mupdf"
+    }
+
+    fn probe(&self, path: &Path) -> Result<Option<usize>, FatalError> {
+        let path = path.to_str().ok_or_else(|| {
+            FatalError::Io(io::Error::new(io::ErrorKind::Other, "Non-UTF8 path is not supported"))
+        })?;
+        let document = Document::open(path)
+            .map_err(|err| FatalError::BadPdf(err.to_string()))?;
+        let count = document.page_count()
+            .map_err(|err| FatalError::BadPdf(err.to_string()))?;
+        Ok(Some(count.max(0) as usize))
+    }
 }
 
-fn fatal_pdf_page(err: mupdf::Error) -> FatalError {
-    FatalError::Io(io::Error::new(
-        io::ErrorKind::Other,
-        err
-    ))
+/// Failure to convert a single page.
+enum PageError {
+    /// The page itself is broken, or uses features not supported.
+    Pdf(mupdf::Error),
+    /// We failed to store the result, which will not work for other pages either.
+    Fatal(FatalError),
 }
+
+impl From<mupdf::Error> for PageError {
+    fn from(err: mupdf::Error) -> Self {
+        PageError::Pdf(err)
+    }
+}
+
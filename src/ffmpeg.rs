@@ -1,10 +1,15 @@
-use std::{fmt, fs, io, process::Command, process::Stdio, path::PathBuf};
-use libloading::{Library, Symbol,library_filename};
+use std::{fmt, fs, process::Command, process::Output, process::Stdio, path::Path, path::PathBuf, time::Duration};
+use std::{sync::{mpsc, Arc, Mutex}, thread};
 use which::CanonicalPath;
 
 use crate::FatalError;
+use crate::app::{Ending, Frame};
+use crate::cache::RenderCache;
+use crate::project::{Overlay, OverlayStyle, Position, Repeat, Trim};
+use crate::timeline::{self, Clip};
 use crate::sink::{FileSource, Sink};
-use crate::resources::{RequiredToolError, require_tool};
+use crate::resources::{RequiredToolError, ToolPaths, require_tool};
+use crate::watchdog::{self, Watchdog, WatchdogError};
 
 pub struct Ffmpeg {
     /// The main ffmpeg executable.
@@ -17,6 +22,22 @@ pub struct Ffmpeg {
     pub version: Version,
     /// The hardware acceleration to use.
     pub hw_accel: HwAccelFlavor,
+    /// How the audio of videos is encoded.
+    pub audio: AudioEncoding,
+    /// How many bits the video may take.
+    pub video: VideoRate,
+    /// Supervision of encoding runs, which report progress as heartbeat.
+    pub watchdog: Watchdog,
+}
+
+/// An unsuccessful run of ffmpeg, with what it had to say about it.
+#[derive(Debug)]
+pub struct FfmpegError {
+    pub args: Vec<String>,
+    /// The exit code, `None` if killed by a signal.
+    pub exit: Option<i32>,
+    /// The last lines of its error output, which usually say why.
+    pub stderr_tail: String,
 }
 
 #[derive(Clone, Copy)]
@@ -26,12 +47,106 @@ pub enum HwAccelFlavor {
     VdPau,
 }
 
+/// The pauses found in audio.
+#[derive(Clone, Debug, Default)]
+pub struct Silences {
+    /// Start and end of each pause, in seconds.
+    pub pauses: Vec<(f32, f32)>,
+    /// The start of the silence lasting until the end, if any.
+    pub trailing: Option<f32>,
+}
+
+/// A sound played before the narration of a slide, to start speaking along with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cue {
+    /// One short beep, a second before the narration.
+    Beep,
+    /// Three beeps a second apart, as in 3-2-1.
+    Countdown,
+}
+
+/// The codec, bitrate and channels of the audio in the video.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AudioEncoding {
+    pub codec: AudioCodec,
+    /// In kbit/s, the default of the encoder if `None`.
+    pub bitrate: Option<u32>,
+    pub channels: Channels,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioCodec {
+    Aac,
+    Opus,
+    Mp3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channels {
+    Mono,
+    Stereo,
+}
+
+/// The size of the video, left to the encoder or given as a bitrate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoRate {
+    /// The quality of the preset of the encoder, whatever size that takes.
+    Quality,
+    /// The video at this many kbit/s, without the audio.
+    Bitrate(u32),
+    /// Video and audio in at most this many bytes, such as the upload limit of a platform.
+    Size(u64),
+}
+
 pub struct Assembly {
-    video_list: fs::File,
-    video_path: PathBuf,
-    audio_list: fs::File,
-    audio_path: PathBuf,
-    slide_list: Vec<(PathBuf, f32)>,
+    segments: Vec<Segment>,
+    /// The end of the audio of all slides so far, in seconds.
+    audio_end: f64,
+    /// The frames of all slides so far.
+    frames: u64,
+}
+
+/// When the audio of a slide plays, and for how long the slide is shown.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timing {
+    /// The seconds the slide is shown, instead of the length of its audio.
+    pub shown: Option<f32>,
+    /// Milliseconds of silence before the audio, or of audio skipped when negative.
+    pub offset_ms: i32,
+    /// The speed of the audio, `1.0` as recorded.
+    pub tempo: f32,
+    /// The part of the audio file that is played, before the tempo and offset.
+    pub trim: Option<Trim>,
+    /// Play the slide more than once, or its audio.
+    pub repeat: Option<Repeat>,
+    /// Show only this part of the slide, as fractions, when slides share one audio.
+    pub portion: Option<(f32, f32)>,
+    /// The least seconds the slide is shown without an explicit duration, `0.0` for no minimum.
+    pub min: f32,
+}
+
+/// A slide with its audio, encoded on its own.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Segment {
+    pub(crate) visual: PathBuf,
+    pub(crate) audio: PathBuf,
+    pub(crate) offset_ms: i32,
+    pub(crate) tempo: f32,
+    pub(crate) trim: Option<Trim>,
+    /// Samples of the audio repeated while the slide is shown, after trim and tempo.
+    pub(crate) loop_samples: Option<u64>,
+    /// Seconds of the audio played after the offset, at its tempo and before padding.
+    pub(crate) audible: f32,
+    /// How long the slide is shown, in whole frames.
+    pub(crate) frames: u64,
+    /// How often the segment is played in a row, encoded once.
+    pub(crate) copies: u32,
+    /// Part of the same slide as the segment before, which is the same chapter.
+    pub(crate) continued: bool,
+    /// Seconds of fading out the audio at the end, for the last slide.
+    pub(crate) fade: f32,
+    /// A `drawtext` filter for each text over the slide.
+    pub(crate) overlays: Vec<String>,
 }
 
 pub struct Version {
@@ -42,16 +157,20 @@ pub enum LoadFfmpegError {
     CantFindTool(RequiredToolError),
     VersionNumberIsGibberish,
     VersionNumberIsUnrecognized(String),
+    TooOld(String),
+    /// Listing the compiled-in components failed.
+    CantListFeatures(WatchdogError),
+    /// Codecs, formats or filters that we use but which are not compiled in.
+    MissingFeatures(Vec<String>),
 }
 
 impl Ffmpeg {
-    pub fn new() -> Result<Ffmpeg, LoadFfmpegError> {
-        let ffprobe = require_tool("ffprobe")?;
-        let ffmpeg = require_tool("ffmpeg")?;
-
-        let hw_accel = Self::detect_hardware_accel();
+    pub fn new(tools: &ToolPaths, audio: AudioEncoding, video: VideoRate)
+        -> Result<Ffmpeg, LoadFfmpegError>
+    {
+        let ffprobe = require_tool("ffprobe", tools.ffprobe.as_deref())?;
+        let ffmpeg = require_tool("ffmpeg", tools.ffmpeg.as_deref())?;
 
-        // TODO: minimum version requirements?
         let version = Command::new(&ffmpeg)
             .stdin(Stdio::null())
             .stdout(Stdio::piped())
@@ -61,6 +180,13 @@ impl Ffmpeg {
             .map_err(LoadFfmpegError::io_error)
             .and_then(parse_version)?;
 
+        let minimum = versions::Version::new(Self::MINIMUM_VERSION).unwrap();
+        if version.version < minimum {
+            return Err(LoadFfmpegError::TooOld(version.version.to_string()));
+        }
+
+        let hw_accel = Self::check_features(&ffmpeg, audio.codec)?;
+
         // We don't really care for version. ffprobe should be distributed with ffmpeg so let's
         // assume that if it is present then it is generally the same.
         match {
@@ -83,187 +209,711 @@ impl Ffmpeg {
             ffprobe,
             version,
             hw_accel,
+            audio,
+            video,
+            watchdog: Watchdog {
+                heartbeat: Some(Duration::from_secs(60)),
+                deadline: Duration::from_secs(6*60*60),
+            },
         })
     }
 
-    fn detect_hardware_accel() -> HwAccelFlavor {
-        let filename = library_filename("avcodec");
-        let library = match Library::new(filename) {
-            Ok(lib) => lib,
-            Err(_) => return HwAccelFlavor::None,
+    /// Check that everything we pass on the command line is compiled in.
+    ///
+    /// Returns the encoder of the video, a hardware encoder if this ffmpeg has one and otherwise
+    /// the software encoder. The libraries of the system may differ, such as for a static ffmpeg.
+    fn check_features(ffmpeg: &CanonicalPath, audio: AudioCodec)
+        -> Result<HwAccelFlavor, LoadFfmpegError>
+    {
+        let listing = |listing: &str| -> Result<String, LoadFfmpegError> {
+            let output = Watchdog::deadline(Self::PROBE_DEADLINE)
+                .output_required("ffmpeg", Command::new(ffmpeg).args(&["-hide_banner", listing]))
+                .map_err(LoadFfmpegError::CantListFeatures)?;
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
         };
 
-        type Type = unsafe fn(name: *const std::os::raw::c_char) -> *const std::os::raw::c_void;
-        // SAFETY: that's a function pointer according to libavcodec.
-        // We also don't leak that pointer statically as the type would technically permit.
-        let avcodec_find_encoder_by_name: Symbol<Type> = unsafe {
-            match library.get(b"avcodec_find_encoder_by_name\0") {
-                Ok(fn_symbol) => fn_symbol,
-                Err(_) => return HwAccelFlavor::None,
-            }
-        };
+        let encoders = listing("-encoders")?;
+        let available = list_features(&encoders);
+        let hw_accel = [HwAccelFlavor::NvEnc, HwAccelFlavor::VdPau]
+            .iter()
+            .copied()
+            .find(|flavor| available.contains(&flavor.as_encoder_str()))
+            .unwrap_or(HwAccelFlavor::None);
 
-        const NVENC_NAME: &'static str = "h264_nvenc\0";
-        const VDPAU_NAME: &'static str = "h264_vdpau\0";
+        let required: &[(&str, &[&str])] = &[
+            ("-encoders", &[audio.encoder(), hw_accel.as_encoder_str()]),
+            ("-demuxers", &["concat", "lavfi"]),
+            ("-muxers", &["mp4", "wav"]),
+            ("-filters", &[
+                "anullsrc", "scale", "pad", "apad", "adelay", "atrim", "atempo", "afade",
+                "aresample", "aloop",
+            ]),
+        ];
+        // Not `drawtext`, which needs freetype. Only slides with overlay text use it.
 
-        if !unsafe { avcodec_find_encoder_by_name(NVENC_NAME.as_ptr() as *const _) }.is_null() {
-            return HwAccelFlavor::NvEnc;
+        let mut missing = vec![];
+        for &(kind, names) in required {
+            let stdout = match kind {
+                "-encoders" => encoders.clone(),
+                _ => listing(kind)?,
+            };
+            let available = list_features(&stdout);
+            missing.extend(names
+                .iter()
+                .filter(|name| !available.iter().any(|feature| feature == *name))
+                .map(|name| format!("{} {}", &kind[1..kind.len()-1], name)));
         }
 
-        if !unsafe { avcodec_find_encoder_by_name(VDPAU_NAME.as_ptr() as *const _) }.is_null() {
-            return HwAccelFlavor::VdPau;
+        if missing.is_empty() {
+            Ok(hw_accel)
+        } else {
+            Err(LoadFfmpegError::MissingFeatures(missing))
         }
-
-        return HwAccelFlavor::None;
     }
 
     /// Determine the duration of an audio file with ffmpeg tools.
     pub fn audio_duration(&self, file: &FileSource, sink: &mut Sink) -> Result<f32, FatalError> {
-        // TODO: might be more convenient to have another error type here.
-        let output = Command::new(self.ffprobe.as_path())
-            .current_dir(sink.work_dir())
-            .args(&["-v", "error"])
-            .args(&["-show_entries", "format=duration"])
-            .args(&["-of", "default=noprint_wrappers=1:nokey=1"])
-            .arg(file.as_path())
-            .output()?;
+        let output = Watchdog::deadline(Self::PROBE_DEADLINE).output_required("ffprobe", {
+            Command::new(self.ffprobe.as_path())
+                .current_dir(sink.work_dir())
+                .args(&["-v", "error"])
+                .args(&["-show_entries", "format=duration"])
+                .args(&["-of", "default=noprint_wrappers=1:nokey=1"])
+                .arg(file.as_path())
+        })?;
 
-        let stdout = String::from_utf8(output.stdout).unwrap();
-        let stderr = output.stderr;
+        let stdout = String::from_utf8_lossy(&output.stdout);
 
+        // Without a duration the file is not audio that ffmpeg understands.
         let duration: f32 = stdout
             .trim()
             .parse()
-            .map_err(|err| {
-                eprintln!("{}", &stdout);
-                eprintln!("{}", String::from_utf8_lossy(&stderr));
-                io::Error::new(io::ErrorKind::InvalidData, err)
+            .map_err(|_| match FatalError::tool_failed("ffprobe", &output) {
+                FatalError::Tool { detail, .. } => FatalError::BadAudio { page: None, detail },
+                other => other,
             })?;
         Ok(duration)
     }
 
+    /// The pauses in audio, such as between slides of a recording.
+    ///
+    /// Needed to split a recording, so it runs even in a dry run.
+    pub fn silences(&self, audio: &Path, sink: &Sink) -> Result<Silences, FatalError> {
+        let mut command = Command::new(self.ffmpeg.as_path());
+        command
+            .current_dir(sink.work_dir())
+            // The progress is the heartbeat, while the pauses are written to stderr.
+            .args(Self::PROGRESS)
+            .arg("-i")
+            .arg(audio)
+            .args(&["-af", Self::SILENCE_DETECT, "-f", "null", "-"]);
+
+        let output = self.watchdog.output_required("ffmpeg", &mut command)?;
+        if !output.status.success() {
+            return Err(FfmpegError::new(&command, &output).into());
+        }
+
+        // Lines such as `[silencedetect @ 0x5581] silence_end: 13.2 | silence_duration: 0.8`.
+        let value = |line: &str, key: &str| -> Option<f32> {
+            let rest = &line[line.find(key)? + key.len()..];
+            rest.split_whitespace().next()?.parse().ok()
+        };
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut silences = Silences::default();
+        for line in stderr.lines() {
+            if let Some(time) = value(line, "silence_start:") {
+                silences.trailing = Some(time.max(0.0));
+            } else if let (Some(start), Some(end)) = (silences.trailing, value(line, "silence_end:")) {
+                silences.pauses.push((start, end));
+                silences.trailing = None;
+            }
+        }
+
+        Ok(silences)
+    }
+
+    /// A part of an audio file, from `start` to `end` or the end of the file.
+    ///
+    /// The part is probed like any other audio, so it is needed even in a dry run.
+    pub fn cut(&self, audio: &Path, start: f32, end: Option<f32>, sink: &mut Sink)
+        -> Result<PathBuf, FatalError>
+    {
+        let mut unique = sink.unique_path()?;
+        unique.path.set_extension("wav");
+
+        let mut command = Command::new(self.ffmpeg.as_path());
+        command
+            .current_dir(sink.work_dir())
+            .args(Self::PROGRESS)
+            .arg("-i")
+            .arg(audio)
+            .arg("-ss")
+            .arg(start.to_string());
+        if let Some(end) = end {
+            command.arg("-to").arg(end.to_string());
+        }
+        command
+            .args(&["-f", "wav"])
+            .arg(&unique.path);
+
+        let output = self.watchdog.output_required("ffmpeg", &mut command)?;
+        if !output.status.success() {
+            return Err(FfmpegError::new(&command, &output).into());
+        }
+
+        Ok(unique.path)
+    }
+
     pub fn replacement_audio(&self, duration: f32, sink: &mut Sink) -> Result<(), FatalError> {
         let duration = duration.to_string();
         let unique = sink.unique_path()?;
 
-        let success = Command::new(self.ffmpeg.as_path())
+        let mut command = Command::new(self.ffmpeg.as_path());
+        command
             .current_dir(sink.work_dir())
+            .args(Self::PROGRESS)
             .args(&["-f", "lavfi", "-i", "anullsrc=r=11025:cl=mono", "-t"])
             .arg(duration)
             .args(&["-f", "wav"])
-            .arg(&unique.path)
-            .stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .status()
-            .map(|status| status.success())?;
-        
-        if !success {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "ffmpeg was unable to produce silent audio"
-            ).into());
+            .arg(&unique.path);
+
+        // The silence is probed like any other audio, so it is needed even in a dry run.
+        let output = self.watchdog.output_required("ffmpeg", &mut command)?;
+        if !output.status.success() {
+            return Err(FfmpegError::new(&command, &output).into());
         }
 
         sink.import(unique.path);
         Ok(())
     }
+
+    /// The audio with the cue before it, stored in the sink for playing it.
+    ///
+    /// Only for previews, the cue is never part of a video.
+    pub fn cued(&self, cue: Cue, audio: &Path, sink: &mut Sink) -> Result<PathBuf, FatalError> {
+        let stem = audio.file_stem().unwrap_or_default().to_string_lossy();
+        let path = sink.work_dir().join(format!("cued-{}-{}.wav", cue.name(), stem));
+        if path.exists() {
+            return Ok(path);
+        }
+
+        Self::run(&self.watchdog, {
+            Command::new(self.ffmpeg.as_path())
+                .current_dir(sink.work_dir())
+                .args(Self::PROGRESS)
+                .args(&["-f", "lavfi", "-i"])
+                .arg(cue.source())
+                .arg("-i")
+                .arg(audio)
+                .args(&["-filter_complex", "[0:a][1:a]concat=n=2:v=0:a=1"])
+                .args(&["-f", "wav"])
+                .arg(&path)
+        })?;
+
+        Ok(path)
+    }
+
+    /// Run ffmpeg under a watchdog, and fail with its complaint if it exits unsuccessfully.
+    fn run(watchdog: &Watchdog, command: &mut Command) -> Result<Output, FatalError> {
+        let output = watchdog.output("ffmpeg", command)?;
+        if !output.status.success() {
+            return Err(FfmpegError::new(command, &output).into());
+        }
+        Ok(output)
+    }
+
+    /// A file of silent audio, stored in the sink.
+    pub fn silence(&self, duration: f32, sink: &mut Sink) -> Result<PathBuf, FatalError> {
+        self.replacement_audio(duration, sink)?;
+        let file = sink
+            .imported()
+            .next()
+            .ok_or_else(|| std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "ffmpeg failed to produce replacement audio",
+            ))?;
+        Ok(file)
+    }
+
+    /// Arguments making ffmpeg report its progress on stdout, which we use as a heartbeat.
+    const PROGRESS: &'static [&'static str] = &["-nostats", "-progress", "pipe:1"];
+    /// Pauses of half a second, quiet as a room without speaking.
+    const SILENCE_DETECT: &'static str = "silencedetect=noise=-35dB:duration=0.5";
+    /// Tag the video as BT.709, which the scaling converted the sRGB slides to. Untagged, players
+    /// guess and many pick BT.601 which shifts the colors.
+    const COLOR: &'static [&'static str] = &[
+        "-color_primaries", "bt709", "-color_trc", "bt709", "-colorspace", "bt709", "-color_range", "tv",
+    ];
+    /// Arguments for reproducible output, when renders are compared.
+    const BITEXACT: &'static [&'static str] = &[
+        "-fflags", "+bitexact", "-flags:v", "+bitexact", "-flags:a", "+bitexact",
+    ];
+    /// Probing only reads headers and should be quick.
+    const PROBE_DEADLINE: Duration = Duration::from_secs(60);
+    /// The oldest release we have tested the command lines with.
+    const MINIMUM_VERSION: &'static str = "4.0";
 }
 
 impl Assembly {
-    pub fn new(sink: &mut Sink) -> Result<Self, FatalError> {
-        let video_ctrl = sink.unique_file(fs::OpenOptions::new().write(true))?;
-        let audio_ctrl = sink.unique_file(fs::OpenOptions::new().write(true))?;
-        Ok(Assembly {
-            audio_list: audio_ctrl.file,
-            audio_path: audio_ctrl.path,
-            video_list: video_ctrl.file,
-            video_path: video_ctrl.path,
-            slide_list: vec![],
-        })
+    /// Slides are still, a few frames per second are enough and keep the encode quick.
+    const FRAME_RATE: u32 = 2;
+    /// The audio of all segments, so that they can be joined as is.
+    const SAMPLE_RATE: u32 = 48000;
+    /// Segments encoded at once. Each is short, so starting ffmpeg takes a good part of the time.
+    const PARALLEL_SEGMENTS: usize = 4;
+    /// Segments encoded at once on the graphics card. Consumer cards limit the sessions of their
+    /// encoder, to as few as two, and more sessions only wait for the same hardware anyways.
+    const PARALLEL_HW_SEGMENTS: usize = 2;
+
+    pub fn new() -> Self {
+        Assembly {
+            segments: vec![],
+            audio_end: 0.0,
+            frames: 0,
+        }
     }
 
+    /// Add a slide shown for the length of its audio, or for an explicit duration.
+    ///
+    /// With a duration the audio is cut short or followed by silence, as is audio shorter than
+    /// the minimum, unless it loops. The offset of the audio changes its length, as far as the
+    /// slide is concerned. A slide repeated some times is all shown in one segment, whose
+    /// frames are those of the first copy. With a portion, only that part of the slide is added
+    /// and the audio is cut to match.
     pub fn add_linked(
         &mut self,
         ffmpeg: &Ffmpeg,
         visual: &FileSource,
         audio: &FileSource,
+        timing: Timing,
         sink: &mut Sink,
     )
         -> Result<(), FatalError>
     {
-        use std::io::Write as _;
-        let duration = ffmpeg.audio_duration(audio, sink)?;
-        self.slide_list.push((visual.as_path().to_owned(), duration));
-        writeln!(&self.video_list, "file '{}'", visual.as_path().display()).unwrap();
-        writeln!(&self.video_list, "duration {}", duration).unwrap();
-        writeln!(&self.audio_list, "file {}", audio.as_path().display())?;
+        let recorded = ffmpeg.audio_duration(audio, sink)?;
+        let played = timing.trim.map_or(recorded, |trim| trim.length(recorded));
+        let clip = played / timing.tempo + timing.offset_ms as f32 / 1000.0;
+        let duration = timing.shown.unwrap_or_else(|| clip.max(timing.min));
+        let (duration, trim, offset_ms) = match timing.portion {
+            Some(portion) => Self::portion(&timing, recorded, duration, portion),
+            None => (duration, timing.trim, timing.offset_ms),
+        };
+        let audible = match (timing.portion, trim) {
+            (Some(_), Some(Trim { start, end: Some(end) })) => (end - start) / timing.tempo,
+            (Some(_), _) => 0.0,
+            (None, _) => played / timing.tempo - (-offset_ms).max(0) as f32 / 1000.0,
+        };
+        let (copies, loop_samples) = match timing.repeat {
+            Some(Repeat::Times(times)) => (times.max(1), None),
+            Some(Repeat::Loop) => {
+                let seconds = f64::from(played / timing.tempo);
+                let samples = (seconds * f64::from(Self::SAMPLE_RATE)).round() as u64;
+                (1, Some(samples).filter(|&samples| samples > 0))
+            }
+            None => (1, None),
+        };
+
+        let frames = self.count_frames(duration, copies);
+        self.segments.push(Segment {
+            visual: visual.as_path().to_owned(),
+            audio: audio.as_path().to_owned(),
+            offset_ms,
+            tempo: timing.tempo,
+            trim,
+            loop_samples,
+            audible: audible.max(0.0),
+            frames,
+            copies,
+            continued: false,
+            fade: 0.0,
+            overlays: vec![],
+        });
+
         Ok(())
     }
 
-    // FIXME: this MUST be async or run in another thread.
-    pub fn finalize(&self, ffmpeg: &Ffmpeg, sink: &mut Sink) -> Result<(), FatalError> {
-        // concatenate all audio
-        let mut audio_out = sink.unique_path()?;
-        audio_out.path.set_extension("wav");
-        let output = Command::new(&ffmpeg.ffmpeg)
-            .current_dir(sink.work_dir())
-            // ffmpeg rejects paths if any component has a leading `.`. That's pretty stupid for
-            // scripting as tempfile does begin all its tempdirs with a literal dot.
-            .args(&["-f", "concat", "-safe", "0", "-i"])
-            .arg(&self.audio_path)
-            .args(&["-c", "copy"])
-            .arg(&audio_out.path)
-            .output()?;
+    /// Count a slide shown for `duration`, `copies` times in a row, returning the frames of each.
+    ///
+    /// Frames are counted from the start of the video, so that rounding each slide to whole
+    /// frames does not add up. Each slide ends within half a frame of its audio.
+    pub(crate) fn count_frames(&mut self, duration: f32, copies: u32) -> u64 {
+        self.audio_end += f64::from(duration);
+        let end = (self.audio_end * f64::from(Self::FRAME_RATE)).round() as u64;
+        let frames = end.saturating_sub(self.frames).max(1);
+        // Copies are of the same frames, the next slide makes up for the difference.
+        self.frames += frames * u64::from(copies);
+        self.audio_end += f64::from(duration) * f64::from(copies - 1);
+        frames
+    }
 
-        if !output.status.success() {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("{:?}", output),
-            ).into());
+    /// The part of a slide shown with one of the slides sharing it.
+    ///
+    /// Of the slide shown for `duration`, the part from `from` to `to` as fractions. Returns the
+    /// seconds of the part, with the trim and offset of the audio playing meanwhile.
+    pub(crate) fn portion(timing: &Timing, recorded: f32, duration: f32, (from, to): (f32, f32))
+        -> (f32, Option<Trim>, i32)
+    {
+        let (start, end) = (from * duration, to * duration);
+        let offset = timing.offset_ms as f32 / 1000.0;
+        let first = timing.trim.map_or(0.0, |trim| trim.start);
+        let last = timing.trim.and_then(|trim| trim.end).map_or(recorded, |end| end.min(recorded));
+
+        // How far into the audio file a point of the slide is.
+        let in_file = |time: f32| first + (time - offset).max(0.0) * timing.tempo;
+        let trim_start = in_file(start).min(last);
+        let trim_end = in_file(end).min(last);
+        if trim_end <= trim_start {
+            // No audio plays during the part, it is delayed until after.
+            let delay = ((end - start) * 1000.0).ceil() as i32 + 1000;
+            return (end - start, None, delay);
+        }
+
+        let delay = ((offset - start).max(0.0) * 1000.0).round() as i32;
+        let trim = Trim { start: trim_start, end: Some(trim_end) };
+        (end - start, Some(trim), delay)
+    }
+
+    /// Show the last slide for longer, after its audio, and fade the audio out.
+    ///
+    /// The picture is not faded. At the few frames per second of the video, it would darken in
+    /// visible steps, and segments of another frame rate can not be joined as they are.
+    pub fn end(&mut self, ending: Ending) {
+        // Only the last copy of a repeated slide is held and faded.
+        if let Some(last) = self.segments.last_mut().filter(|last| last.copies > 1) {
+            last.copies -= 1;
+            let copy = Segment { copies: 1, continued: true, ..last.clone() };
+            self.segments.push(copy);
+        }
+
+        let last = match self.segments.last_mut() {
+            Some(last) => last,
+            None => return,
+        };
+
+        self.audio_end += f64::from(ending.hold);
+        let end = (self.audio_end * f64::from(Self::FRAME_RATE)).round() as u64;
+        let frames = end.saturating_sub(self.frames);
+        self.frames += frames;
+        last.frames += frames;
+
+        let seconds = last.frames as f64 / f64::from(Self::FRAME_RATE);
+        last.fade = ending.fade.min(seconds as f32);
+    }
+
+    /// Draw text over the slide added last.
+    pub fn overlay(&mut self, overlay: &Overlay, style: &OverlayStyle) {
+        let last = match self.segments.last_mut() {
+            Some(last) => last,
+            None => return,
+        };
+
+        if overlay.text.trim().is_empty() {
+            return;
+        }
+
+        // Kept off the edges by the height of the text.
+        let margin = style.size;
+        let x = match overlay.position {
+            Position::BottomLeft | Position::TopLeft => format!("{}", margin),
+            Position::Bottom | Position::Top => "(w-tw)/2".to_owned(),
+            Position::BottomRight | Position::TopRight => format!("w-tw-{}", margin),
+        };
+        let y = match overlay.position {
+            Position::TopLeft | Position::Top | Position::TopRight => format!("{}", margin),
+            _ => format!("h-th-{}", margin),
+        };
+
+        // Without expansion, a `%` in the text is drawn as is.
+        let mut filter = format!(
+            "drawtext=expansion=none:text={}:fontsize={}:fontcolor={}:x={}:y={}",
+            escape_filter_value(&overlay.text),
+            style.size,
+            escape_filter_value(&style.color),
+            x,
+            y,
+        );
+        if let Some(background) = &style.background {
+            filter += &format!(
+                ":box=1:boxcolor={}:boxborderw={}",
+                escape_filter_value(background),
+                style.size / 4,
+            );
+        }
+        if let Some(font) = &style.font {
+            filter += &format!(":fontfile={}", escape_filter_value(&font.to_string_lossy()));
+        }
+
+        last.overlays.push(filter);
+    }
+
+    /// The slides added so far as an OpenTimelineIO document, with slides and audio in `sink`.
+    pub fn timeline(&self, name: &str, sink: &Sink) -> serde_json::Value {
+        timeline::otio(name, &self.clips(sink), Self::FRAME_RATE)
+    }
+
+    /// Each slide as a clip, each copy of a repeated slide on its own.
+    ///
+    /// Looping audio is played once.
+    fn clips(&self, sink: &Sink) -> Vec<Clip> {
+        let mut clips = vec![];
+        for segment in &self.segments {
+            let skipped = f64::from((-segment.offset_ms).max(0)) / 1000.0;
+            let clip = || Clip {
+                visual: sink.work_dir().join(&segment.visual),
+                audio: sink.work_dir().join(&segment.audio),
+                seconds: segment.frames as f64 / f64::from(Self::FRAME_RATE),
+                delay: f64::from(segment.offset_ms.max(0)) / 1000.0,
+                audio_start: f64::from(segment.trim.map_or(0.0, |trim| trim.start))
+                    + skipped * f64::from(segment.tempo),
+                audible: f64::from(segment.audible),
+                tempo: f64::from(segment.tempo),
+            };
+            clips.extend((0..segment.copies).map(|_| clip()));
+        }
+        clips
+    }
+
+    /// The number of slides added so far, each encoded on its own.
+    pub fn segments(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// The total duration of all slides added so far, in seconds.
+    pub fn duration(&self) -> f32 {
+        (self.frames as f64 / f64::from(Self::FRAME_RATE)) as f32
+    }
+
+    /// Encode each slide with its audio to a segment, then join them without encoding again.
+    ///
+    /// Segments of an earlier render with the same slide, audio and settings are reused. Each
+    /// segment encoded calls `advance`. Returns the list of segments, the meta data and each
+    /// segment once, which are the inputs of ffmpeg besides slides and audio.
+    pub fn finalize(&self, ffmpeg: &Ffmpeg, frame: Frame, sink: &mut Sink, advance: &dyn Fn())
+        -> Result<Vec<PathBuf>, FatalError>
+    {
+        let hw_encoder = ffmpeg.hw_accel.as_encoder_str();
+        // Fit each slide within the padding, centered on the background.
+        let (width, height) = frame.inner();
+        let (outer_width, outer_height) = frame.size();
+        let fit = format!(
+            "scale=w={}:h={}:force_original_aspect_ratio=decrease:flags=lanczos:out_color_matrix=bt709:out_range=tv,\
+            pad=w={}:h={}:x=(ow-iw)/2:y=(oh-ih)/2:color=0x{:06x},format=yuv420p",
+            width, height, outer_width, outer_height, frame.background,
+        );
+        // Without the version of ffmpeg and other varying data in the output.
+        let bitexact = match sink.seed() {
+            Some(_) => Ffmpeg::BITEXACT,
+            None => &[],
+        };
+
+        let audio = ffmpeg.audio.args();
+        let video = ffmpeg.video.args(self.duration(), ffmpeg.audio);
+        // Only x264 encodes twice to meet the bitrate, the hardware encoders are given just that.
+        let two_pass = !video.is_empty() && matches!(ffmpeg.hw_accel, HwAccelFlavor::None);
+        let settings = format!("{}:{}:{:?}:{:?}:{:?}", fit, hw_encoder, bitexact, audio, video);
+        let mut paths = vec![];
+        let mut pending = vec![];
+        for segment in &self.segments {
+            let path = sink.work_dir().join(segment.name(&settings)?);
+            paths.push(path.clone());
+            if path.exists() {
+                advance();
+                continue;
+            }
+
+            let seconds = (segment.frames as f64 / f64::from(Self::FRAME_RATE)).to_string();
+            // Removed with the unused segments, as it shares their prefix.
+            let passlog = path.with_extension("pass");
+            let encode = |pass: Option<&str>| {
+                // The first pass only looks at the video.
+                let first = pass == Some("1");
+                let mut command = Command::new(&ffmpeg.ffmpeg);
+                command
+                    .current_dir(sink.work_dir())
+                    .args(Ffmpeg::PROGRESS)
+                    .args(&["-loop", "1", "-framerate"])
+                    .arg(Self::FRAME_RATE.to_string())
+                    .arg("-i")
+                    .arg(&segment.visual);
+                if !first {
+                    command.arg("-i").arg(&segment.audio);
+                }
+                command.args(&["-map", "0:v"]);
+                if !first {
+                    command
+                        .args(&["-map", "1:a", "-af"])
+                        .arg(segment.audio_filter());
+                }
+                command
+                    .arg("-vf")
+                    .arg(segment.video_filter(&fit))
+                    .args(&["-c:v", hw_encoder, "-preset", "fast"])
+                    .args(&video);
+                if let Some(pass) = pass {
+                    command.args(&["-pass", pass, "-passlogfile"]).arg(&passlog);
+                }
+                command
+                    .arg("-r")
+                    .arg(Self::FRAME_RATE.to_string());
+                if !first {
+                    command
+                        .args(&audio)
+                        .arg("-ar")
+                        .arg(Self::SAMPLE_RATE.to_string());
+                }
+                command
+                    .args(Ffmpeg::COLOR)
+                    .args(bitexact)
+                    .arg("-t")
+                    .arg(&seconds);
+                match first {
+                    true => command.args(&["-f", "null", "-"]),
+                    false => command.arg(&path),
+                };
+                command
+            };
+
+            pending.push(match two_pass {
+                true => vec![encode(Some("1")), encode(Some("2"))],
+                false => vec![encode(None)],
+            });
+        }
+
+        // Lines of commands printed at once would be mixed.
+        let workers = match ffmpeg.hw_accel {
+            _ if watchdog::is_dry_run() => 1,
+            HwAccelFlavor::None => Self::PARALLEL_SEGMENTS,
+            _ => Self::PARALLEL_HW_SEGMENTS,
+        };
+        Self::run_parallel(ffmpeg.watchdog, pending, workers, advance)?;
+
+        let mut list = sink.unique_path()?;
+        list.path.set_extension("ffconcat");
+        let list_file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&list.path)?;
+        for (path, segment) in paths.iter().zip(&self.segments) {
+            use std::io::Write as _;
+            for _ in 0..segment.copies {
+                writeln!(&list_file, "file '{}'", path.display())?;
+            }
         }
 
         let meta = self.create_meta_data(sink)?;
+        if watchdog::is_dry_run() {
+            watchdog::print_file(&list.path);
+            watchdog::print_file(&meta);
+        }
 
         let mut video_out = sink.unique_path()?;
         video_out.path.set_extension("mp4");
-        let hw_encoder = ffmpeg.hw_accel.as_encoder_str();
 
-        // Join audio to concatenated video.
-        let output = Command::new(&ffmpeg.ffmpeg)
-            .current_dir(sink.work_dir())
-            // ffmpeg rejects paths if any component has a leading `.`. That's pretty stupid for
-            // scripting as tempfile does begin all its tempdirs with a literal dot.
-            .arg("-i")
-            .arg(&audio_out.path)
-            .args(&["-f", "concat", "-safe", "0", "-i"])
-            .arg(&self.video_path)
-            .arg("-i")
-            .arg(&meta)
-            .args(&["-map_metadata", "2"])
-            // FIXME: use `h264_nvenc` or `h264_vaapi` where available.
-            // Find out how to probe for these.
-            .args(&["-c:v", hw_encoder, "-framerate", "2", "-preset", "fast", "-c:a", "aac"])
-            .args(&["-vf", "scale=w=1920:h=1080:force_original_aspect_ratio=decrease:flags=lanczos"])
-            .arg(&video_out.path)
-            .output()?;
+        Ffmpeg::run(&ffmpeg.watchdog, {
+            Command::new(&ffmpeg.ffmpeg)
+                .current_dir(sink.work_dir())
+                .args(Ffmpeg::PROGRESS)
+                // ffmpeg rejects paths if any component has a leading `.`. That's pretty stupid for
+                // scripting as tempfile does begin all its tempdirs with a literal dot.
+                .args(&["-f", "concat", "-safe", "0", "-i"])
+                .arg(&list.path)
+                .arg("-i")
+                .arg(&meta)
+                .args(&["-map_metadata", "1", "-c", "copy"])
+                .args(ffmpeg.audio.codec.strict())
+                .args(bitexact)
+                .arg(&video_out.path)
+        })?;
 
-        if !output.status.success() {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("{:?}", output),
-            ).into());
+        sink.import(video_out.path);
+        Self::remove_unused_segments(sink, &paths);
+
+        let mut inputs = vec![list.path, meta];
+        for path in paths {
+            if !inputs.contains(&path) {
+                inputs.push(path);
+            }
         }
+        Ok(inputs)
+    }
 
-        sink.import(video_out.path);
+    /// Run the encodes of segments, a few at a time.
+    ///
+    /// Each job is a list of commands run one after the other, such as the passes of an encode.
+    fn run_parallel(watchdog: Watchdog, jobs: Vec<Vec<Command>>, workers: usize, advance: &dyn Fn())
+        -> Result<(), FatalError>
+    {
+        let count = jobs.len();
+        let queue = Arc::new(Mutex::new(jobs));
+        let (done, results) = mpsc::channel();
+        let workers = (0..workers.min(count))
+            .map(|_| {
+                let queue = queue.clone();
+                let done = done.clone();
+                thread::spawn(move || loop {
+                    let next = queue.lock().unwrap_or_else(|err| err.into_inner()).pop();
+                    let mut job = match next {
+                        Some(job) => job,
+                        None => break,
+                    };
+                    let result = job
+                        .iter_mut()
+                        .try_for_each(|command| Ffmpeg::run(&watchdog, command).map(drop));
+                    if done.send(result).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(done);
 
-        Ok(())
+        let mut first_err = None;
+        for result in results.iter().take(count) {
+            match result {
+                Ok(()) => advance(),
+                Err(err) => {
+                    // The others would most likely fail the same way.
+                    queue.lock().unwrap_or_else(|err| err.into_inner()).clear();
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+
+        for worker in workers {
+            let _ = worker.join();
+        }
+
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Remove segments of earlier renders, which changed since.
+    fn remove_unused_segments(sink: &Sink, used: &[PathBuf]) {
+        let entries = match fs::read_dir(sink.work_dir()) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            let is_segment = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| name.starts_with(Segment::PREFIX));
+            if is_segment && !used.contains(&path) {
+                let _ = fs::remove_file(path);
+            }
+        }
     }
 
     fn create_meta_data(&self, sink: &mut Sink) -> Result<PathBuf, FatalError> {
         use std::io::Write as _;
 
-        let meta = sink.unique_path()?;
+        let mut meta = sink.unique_path()?;
+        meta.path.set_extension("ffmeta");
         let meta_file = fs::OpenOptions::new()
             .write(true)
             .create(true)
@@ -275,19 +925,29 @@ impl Assembly {
             title=Created with vid-from-pdf",
         )?;
 
-        let mut up_to_now = 0.0;
-        for (idx, (_, ch_len)) in self.slide_list.iter().enumerate() {
-            let start = up_to_now;
-            up_to_now += ch_len;
+        // Each slide is one chapter, of all its segments.
+        let mut chapters: Vec<(u64, u64)> = vec![];
+        let mut frames = 0;
+        for segment in &self.segments {
+            let start = frames;
+            frames += segment.frames * u64::from(segment.copies);
+            match chapters.last_mut() {
+                Some(chapter) if segment.continued => chapter.1 = frames,
+                _ => chapters.push((start, frames)),
+            }
+        }
+
+        for (idx, &(start, end)) in chapters.iter().enumerate() {
             writeln!(
                 &meta_file,
                 "[CHAPTER]\n\
-                TIMEBASE=1/1000\n\
+                TIMEBASE=1/{rate}\n\
                 START={start}\n\
                 END={end}\n\
                 title=Chapter {chapter_idx}",
-                start=(start*1000.0) as u64,
-                end=(up_to_now*1000.0) as u64,
+                rate=Self::FRAME_RATE,
+                start=start,
+                end=end,
                 chapter_idx=idx+1,
             )?;
         }
@@ -296,6 +956,282 @@ impl Assembly {
     }
 }
 
+impl Segment {
+    const PREFIX: &'static str = "segment-";
+
+    /// Named by everything that goes into the segment, so that an unchanged slide is reused.
+    pub(crate) fn name(&self, settings: &str) -> Result<String, FatalError> {
+        let key = format!(
+            "{}:{}:{}:{}:{:?}:{:?}:{}:{}:{:?}:{}",
+            RenderCache::digest(&self.visual)?,
+            RenderCache::digest(&self.audio)?,
+            self.offset_ms,
+            self.tempo,
+            self.trim,
+            self.loop_samples,
+            self.frames,
+            self.fade,
+            self.overlays,
+            settings,
+        );
+        let digest = RenderCache::digest_of(key.as_bytes());
+        Ok(format!("{}{}.mp4", Self::PREFIX, digest))
+    }
+
+    /// Fit the slide into the frame and draw its text.
+    fn video_filter(&self, fit: &str) -> String {
+        let mut filter = fit.to_owned();
+        for overlay in &self.overlays {
+            filter += ",";
+            filter += overlay;
+        }
+
+        filter
+    }
+
+    /// Trim the audio, change its tempo and shift it by its offset, then add silence if the slide
+    /// is shown for longer.
+    fn audio_filter(&self) -> String {
+        let mut filter = String::new();
+        if let Some(trim) = self.trim {
+            filter += &format!("atrim=start={}", trim.start);
+            if let Some(end) = trim.end {
+                filter += &format!(":end={}", end);
+            }
+            filter += ",asetpts=PTS-STARTPTS,";
+        }
+
+        if self.tempo != 1.0 {
+            filter += &format!("atempo={},", self.tempo);
+        }
+
+        // The loop is counted in samples, of a known rate.
+        if let Some(samples) = self.loop_samples {
+            filter += &format!(
+                "aresample={},aloop=loop=-1:size={},asetpts=N/SR/TB,",
+                Assembly::SAMPLE_RATE,
+                samples,
+            );
+        }
+
+        filter += &match self.offset_ms {
+            // The delay of each channel, those beyond the channels of the audio are ignored.
+            ms if ms > 0 => format!("adelay={}|{},apad", ms, ms),
+            ms if ms < 0 => format!(
+                "atrim=start={},asetpts=PTS-STARTPTS,apad",
+                f64::from(-ms) / 1000.0,
+            ),
+            _ => "apad".to_owned(),
+        };
+
+        if self.fade > 0.0 {
+            let seconds = self.frames as f64 / f64::from(Assembly::FRAME_RATE);
+            let start = seconds - f64::from(self.fade);
+            filter += &format!(",afade=t=out:st={}:d={}", start, self.fade);
+        }
+
+        filter
+    }
+}
+
+impl FfmpegError {
+    const STDERR_LINES: usize = 50;
+
+    pub fn new(command: &Command, output: &Output) -> Self {
+        FfmpegError {
+            args: command.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect(),
+            exit: output.status.code(),
+            stderr_tail: crate::stderr_tail(output, Self::STDERR_LINES),
+        }
+    }
+
+    /// The line complaining about a codec or filter that our ffmpeg was built without, if any.
+    ///
+    /// Such as `drawtext` for the slide numbers, which requires ffmpeg built with freetype.
+    pub fn missing_codec(&self) -> Option<&str> {
+        self.stderr_tail
+            .lines()
+            .find(|line| {
+                line.contains("Unknown encoder")
+                    || line.contains("Encoder not found")
+                    || line.contains("Decoder not found")
+                    || (line.contains("codec") && line.contains("not found"))
+                    || line.contains("No such filter")
+            })
+            .map(str::trim)
+    }
+}
+
+impl fmt::Display for FfmpegError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.exit {
+            Some(code) => writeln!(f, "`ffmpeg {}` exited with {}:", self.args.join(" "), code)?,
+            None => writeln!(f, "`ffmpeg {}` was killed:", self.args.join(" "))?,
+        }
+        f.write_str(&self.stderr_tail)
+    }
+}
+
+impl Silences {
+    /// A pause ending this many seconds before the end of the audio lasts until the end.
+    const END: f32 = 0.05;
+
+    /// The pauses, with the one reaching the end of audio of `duration` as `trailing`.
+    ///
+    /// Since 4.2 ffmpeg ends a silence at the end of the stream, before it was left open.
+    pub fn ending_at(&self, duration: f32) -> Silences {
+        let mut silences = self.clone();
+        if silences.trailing.is_none() {
+            if let Some(&(start, end)) = silences.pauses.last() {
+                if duration - end < Self::END {
+                    silences.pauses.pop();
+                    silences.trailing = Some(start);
+                }
+            }
+        }
+        silences
+    }
+}
+
+impl Cue {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "beep" => Some(Cue::Beep),
+            "countdown" => Some(Cue::Countdown),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Cue::Beep => "beep",
+            Cue::Countdown => "countdown",
+        }
+    }
+
+    /// The tone as a source of `lavfi`, beeping at the start of each second.
+    fn source(self) -> &'static str {
+        match self {
+            Cue::Beep => "aevalsrc=0.5*sin(2*PI*1000*t)*lt(t\\,0.15):d=1",
+            Cue::Countdown => "aevalsrc=0.5*sin(2*PI*880*t)*lt(mod(t\\,1)\\,0.15):d=3",
+        }
+    }
+}
+
+impl AudioEncoding {
+    /// The arguments of ffmpeg encoding the audio, besides its sample rate.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec!["-c:a".to_owned(), self.codec.encoder().to_owned()];
+        args.extend(self.codec.strict().iter().map(|&arg| arg.to_owned()));
+        if let Some(bitrate) = self.bitrate {
+            args.extend(vec!["-b:a".to_owned(), format!("{}k", bitrate)]);
+        }
+        args.extend(vec!["-ac".to_owned(), self.channels.count().to_string()]);
+        args
+    }
+
+    /// The bitrate, or what the encoders default to at most.
+    fn kbps(&self) -> u32 {
+        self.bitrate.unwrap_or(128)
+    }
+}
+
+impl Default for AudioEncoding {
+    fn default() -> Self {
+        AudioEncoding {
+            codec: AudioCodec::Aac,
+            bitrate: None,
+            channels: Channels::Stereo,
+        }
+    }
+}
+
+impl VideoRate {
+    /// The least bitrate given to the encoder, below which slides are not legible anymore.
+    const MIN_KBPS: u32 = 50;
+    /// The share of the size taken by the container, such as the index of packets.
+    const OVERHEAD: f64 = 0.02;
+
+    /// The arguments of ffmpeg for the bitrate of a video of `seconds`, none to leave it to the
+    /// encoder.
+    pub fn args(self, seconds: f32, audio: AudioEncoding) -> Vec<String> {
+        let kbps = match self {
+            VideoRate::Quality => return vec![],
+            VideoRate::Bitrate(kbps) => kbps,
+            VideoRate::Size(bytes) => {
+                let kbits = bytes as f64 * 8.0 / 1000.0 * (1.0 - Self::OVERHEAD);
+                let total = kbits / f64::from(seconds).max(1.0);
+                let video = (total - f64::from(audio.kbps())).floor();
+                if video < f64::from(Self::MIN_KBPS) {
+                    tracing::warn!(bytes, seconds, "The video can not be made small enough");
+                }
+                (video as u32).max(Self::MIN_KBPS)
+            }
+        };
+        vec!["-b:v".to_owned(), format!("{}k", kbps)]
+    }
+}
+
+impl AudioCodec {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "aac" => Some(AudioCodec::Aac),
+            "opus" => Some(AudioCodec::Opus),
+            "mp3" => Some(AudioCodec::Mp3),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AudioCodec::Aac => "aac",
+            AudioCodec::Opus => "opus",
+            AudioCodec::Mp3 => "mp3",
+        }
+    }
+
+    /// The encoder of ffmpeg, the native ones of opus and mp3 are missing or experimental.
+    fn encoder(self) -> &'static str {
+        match self {
+            AudioCodec::Aac => "aac",
+            AudioCodec::Opus => "libopus",
+            AudioCodec::Mp3 => "libmp3lame",
+        }
+    }
+
+    /// Before ffmpeg 4.3, opus in mp4 is experimental and must be allowed when muxing.
+    fn strict(self) -> &'static [&'static str] {
+        match self {
+            AudioCodec::Opus => &["-strict", "experimental"],
+            _ => &[],
+        }
+    }
+}
+
+impl Channels {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mono" => Some(Channels::Mono),
+            "stereo" => Some(Channels::Stereo),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Channels::Mono => "mono",
+            Channels::Stereo => "stereo",
+        }
+    }
+
+    fn count(self) -> u32 {
+        match self {
+            Channels::Mono => 1,
+            Channels::Stereo => 2,
+        }
+    }
+}
+
 impl HwAccelFlavor {
     pub fn as_encoder_str(self) -> &'static str {
         match self {
@@ -350,6 +1286,38 @@ fn parse_version(output: std::process::Output) -> Result<Version, LoadFfmpegErro
     })
 }
 
+/// Escape the value of a filter option, in a filter graph passed as one argument.
+///
+/// Once for the options of the filter and once more for the graph, such that `'`, `:` and `,`
+/// are taken literally.
+pub(crate) fn escape_filter_value(value: &str) -> String {
+    let escape = |value: &str, special: &[char]| -> String {
+        let mut escaped = String::with_capacity(value.len());
+        for ch in value.chars() {
+            if ch == '\\' || special.contains(&ch) {
+                escaped.push('\\');
+            }
+            escaped.push(ch);
+        }
+        escaped
+    };
+
+    let option = escape(value, &['\'', ':']);
+    escape(&option, &['\'', '[', ']', ',', ';'])
+}
+
+/// The names in a listing such as `ffmpeg -encoders`.
+///
+/// Each entry is made of flags, names separated by a comma, and a description. Lines of the
+/// legend have a `=` in place of the names and match nothing we look for.
+fn list_features(listing: &str) -> Vec<&str> {
+    listing
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .flat_map(|names| names.split(','))
+        .collect()
+}
+
 impl From<RequiredToolError> for LoadFfmpegError {
     fn from(err: RequiredToolError) -> Self {
         LoadFfmpegError::CantFindTool(err)
@@ -366,6 +1334,15 @@ impl fmt::Display for LoadFfmpegError {
             LoadFfmpegError::VersionNumberIsUnrecognized(nr) => {
                 write!(f, "The ffmpeg program provided version number `{}` but it was not understood.", nr)
             }
+            LoadFfmpegError::TooOld(nr) => {
+                write!(f, "The ffmpeg program has version `{}` but at least `{}` is required.", nr, Ffmpeg::MINIMUM_VERSION)
+            }
+            LoadFfmpegError::CantListFeatures(err) => {
+                write!(f, "The ffmpeg program could not list its features: {}", err)
+            }
+            LoadFfmpegError::MissingFeatures(missing) => {
+                write!(f, "The ffmpeg program was built without: {}.", missing.join(", "))
+            }
         }
     }
 }
@@ -0,0 +1,420 @@
+//! Converting a pdf without interaction, for scripts and programs wrapping us.
+//!
+//! With `--json` each step is written to stdout as one line of json, otherwise a short
+//! description goes to stderr. A whole job, down to the settings of each slide, can be described
+//! by one json file instead of flags, see `RenderSpec`.
+use std::{fs, io, path::Path, path::PathBuf, sync::Arc, sync::mpsc, thread, time::Duration};
+use std::io::Write as _;
+
+use serde::{Deserialize, Serialize};
+
+use crate::FatalError;
+use crate::app::App;
+use crate::pipeline::Pipeline;
+use crate::progress::{Progress, Snapshot, Stage};
+use crate::project::{Filter, Overlay, OverlayStyle, Position, Project, Repeat, Slide, Split};
+use crate::watchdog;
+
+/// Where the events go.
+#[derive(Clone, Copy)]
+struct Report {
+    json: bool,
+}
+
+/// One line of the output of `--json`.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    /// Work on the pages, `page` of `pages` are done.
+    Progress {
+        stage: &'static str,
+        page: usize,
+        pages: usize,
+        percent: f64,
+    },
+    /// Waiting for other projects before the next stage, `position` 1 is next.
+    Queued {
+        position: usize,
+    },
+    /// A file was created, kind is one of `project`, `intermediates`, `video`, `timeline`.
+    Artifact {
+        kind: &'static str,
+        path: &'a Path,
+    },
+    /// The video was uploaded to object storage.
+    Uploaded {
+        url: &'a str,
+    },
+    /// The last event.
+    Result {
+        ok: bool,
+        output: Option<&'a Path>,
+        error: Option<String>,
+    },
+}
+
+/// Where the narration of the slides comes from.
+pub enum Narration<'a> {
+    None,
+    /// A zip archive or directory with files such as `slide-01.wav`.
+    Files(&'a Path),
+    /// One recording of all slides, split in order.
+    Recording(&'a Path, Split),
+}
+
+/// The files written by a render.
+pub struct Outputs<'a> {
+    pub video: &'a Path,
+    /// A directory for the files given to ffmpeg.
+    pub intermediates: Option<&'a Path>,
+    /// A file for the timeline of the video.
+    pub timeline: Option<&'a Path>,
+}
+
+/// A conversion described by a json file, for `--render-spec`.
+///
+/// Relative paths are relative to the directory of the file. The narration in `audio` or
+/// `recording` is attached first, the audio of `slides` replaces it for single slides.
+///
+/// ```json
+/// {
+///   "pdf": "talk.pdf",
+///   "audio": "narration/",
+///   "output": "talk.mp4",
+///   "slides": [
+///     { "page": 1, "audio": "intro.wav", "subtitle": "Welcome" },
+///     { "page": 4, "duration": 8, "enabled": false }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenderSpec {
+    pub pdf: PathBuf,
+    /// A zip archive or directory with files such as `slide-01.wav`.
+    #[serde(default)]
+    pub audio: Option<PathBuf>,
+    /// One recording of all slides, instead of `audio`.
+    #[serde(default)]
+    pub recording: Option<PathBuf>,
+    /// Where `recording` is split, as for `--markers`, at the longest pauses if not given.
+    #[serde(default)]
+    pub markers: Option<String>,
+    pub output: PathBuf,
+    #[serde(default)]
+    pub keep_intermediates: Option<PathBuf>,
+    #[serde(default)]
+    pub timeline: Option<PathBuf>,
+    #[serde(default)]
+    pub filter: Filter,
+    #[serde(default)]
+    pub min_slide_seconds: Option<f32>,
+    #[serde(default)]
+    pub default_slide_seconds: Option<f32>,
+    #[serde(default)]
+    pub overlay_style: Option<OverlayStyle>,
+    #[serde(default)]
+    pub slide_numbers: Option<Position>,
+    #[serde(default)]
+    pub slides: Vec<SlideSpec>,
+}
+
+/// The settings of one slide, those not given are left as they are.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SlideSpec {
+    /// The page of the pdf, the first is 1.
+    pub page: usize,
+    #[serde(default)]
+    pub audio: Option<PathBuf>,
+    #[serde(default)]
+    pub duration: Option<f32>,
+    #[serde(default)]
+    pub sync_offset_ms: Option<i32>,
+    #[serde(default)]
+    pub tempo: Option<f32>,
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    #[serde(default)]
+    pub subtitle: Option<String>,
+    #[serde(default)]
+    pub overlay: Option<Overlay>,
+    #[serde(default)]
+    pub repeat: Option<Repeat>,
+}
+
+/// Convert a pdf and its narration to a video at `outputs.video`.
+pub fn render(
+    app: Arc<App>,
+    pdf: &Path,
+    audio: Narration,
+    filter: Filter,
+    outputs: Outputs,
+    json: bool,
+) -> Result<(), FatalError> {
+    run(app, pdf, audio, filter, outputs, json, None)
+}
+
+/// Convert as described by the json file at `path`.
+pub fn render_spec(app: Arc<App>, path: &Path, json: bool) -> Result<(), FatalError> {
+    let prepared = RenderSpec::read(path).and_then(|spec| Ok((spec.split()?, spec)));
+    let (split, spec) = match prepared {
+        Ok(prepared) => prepared,
+        Err(err) => {
+            let error = Some(format!("{:?}", err));
+            if json {
+                Report { json }.emit(&Event::Result { ok: false, output: None, error })?;
+            }
+            return Err(err);
+        }
+    };
+
+    let audio = match (&spec.recording, &spec.audio) {
+        (Some(recording), _) => Narration::Recording(recording, split),
+        (None, Some(audio)) => Narration::Files(audio),
+        (None, None) => Narration::None,
+    };
+    let outputs = Outputs {
+        video: &spec.output,
+        intermediates: spec.keep_intermediates.as_deref(),
+        timeline: spec.timeline.as_deref(),
+    };
+    run(app, &spec.pdf, audio, spec.filter, outputs, json, Some(&spec))
+}
+
+fn run(
+    app: Arc<App>,
+    pdf: &Path,
+    audio: Narration,
+    filter: Filter,
+    outputs: Outputs,
+    json: bool,
+    spec: Option<&RenderSpec>,
+) -> Result<(), FatalError> {
+    let report = Report { json };
+    let pipeline = Pipeline::from_app(app);
+    let result = convert(&pipeline, pdf, audio, filter, &outputs, report, spec);
+
+    let (output, error) = match &result {
+        // Nothing was encoded.
+        Ok(()) if watchdog::is_dry_run() => (None, None),
+        Ok(()) => (Some(outputs.video), None),
+        Err(err) => (None, Some(format!("{:?}", err))),
+    };
+
+    // Failures are described by `main`, unless asked for json.
+    if json || error.is_none() {
+        report.emit(&Event::Result { ok: error.is_none(), output, error })?;
+    }
+
+    result
+}
+
+fn convert(
+    pipeline: &Pipeline,
+    pdf: &Path,
+    audio: Narration,
+    filter: Filter,
+    outputs: &Outputs,
+    report: Report,
+    spec: Option<&RenderSpec>,
+) -> Result<(), FatalError> {
+    let mut project = pipeline.create(pdf)?;
+    project.meta.filter = filter;
+    report.emit(&Event::Artifact { kind: "project", path: project.dir.work_dir() })?;
+
+    let progress = project.progress.clone();
+    report.watch(progress.clone(), || pipeline.explode(&mut project))?;
+
+    let attached = match audio {
+        Narration::None => vec![],
+        Narration::Files(path) => pipeline.attach_audio_files(&mut project, path)?,
+        Narration::Recording(path, split) => pipeline.attach_recording(&mut project, path, &split)?,
+    };
+    tracing::info!(pages = attached.len(), "Attached audio");
+
+    if let Some(spec) = spec {
+        spec.apply(pipeline, &mut project)?;
+    }
+
+    let video = report.watch(progress, || match outputs.intermediates {
+        Some(dir) => pipeline.render_keeping(&mut project, dir),
+        None => pipeline.render(&mut project),
+    })?;
+    if let Some(dir) = outputs.intermediates {
+        report.emit(&Event::Artifact { kind: "intermediates", path: dir })?;
+    }
+
+    if watchdog::is_dry_run() {
+        return Ok(());
+    }
+
+    fs::copy(video, outputs.video)?;
+    report.emit(&Event::Artifact { kind: "video", path: outputs.video })?;
+
+    if let Some(url) = &project.meta.uploaded {
+        report.emit(&Event::Uploaded { url })?;
+    }
+
+    if let (Some(path), Some(written)) = (outputs.timeline, &project.meta.timeline) {
+        fs::copy(written, path)?;
+        report.emit(&Event::Artifact { kind: "timeline", path })?;
+    }
+
+    Ok(())
+}
+
+impl RenderSpec {
+    /// Read a spec, with its paths resolved.
+    pub fn read(path: &Path) -> Result<Self, FatalError> {
+        let content = fs::read(path)?;
+        let mut spec: RenderSpec = serde_json::from_slice(&content)
+            .map_err(|err| invalid_spec(format!("`{}`: {}", path.display(), err)))?;
+
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        let paths = vec![&mut spec.pdf, &mut spec.output]
+            .into_iter()
+            .chain(spec.audio.as_mut())
+            .chain(spec.recording.as_mut())
+            .chain(spec.keep_intermediates.as_mut())
+            .chain(spec.timeline.as_mut())
+            .chain(spec.slides.iter_mut().filter_map(|slide| slide.audio.as_mut()));
+        for path in paths {
+            *path = base.join(&*path);
+        }
+
+        Ok(spec)
+    }
+
+    fn split(&self) -> Result<Split, FatalError> {
+        match &self.markers {
+            None => Ok(Split::Silence),
+            Some(markers) => Split::from_markers(markers).ok_or_else(|| invalid_spec(format!(
+                "The markers `{}` are not increasing times", markers,
+            ))),
+        }
+    }
+
+    /// Change the project as described, after its narration was attached.
+    fn apply(&self, pipeline: &Pipeline, project: &mut Project) -> Result<(), FatalError> {
+        let meta = &mut project.meta;
+        meta.min_slide_seconds = self.min_slide_seconds.or(meta.min_slide_seconds);
+        meta.default_slide_seconds = self.default_slide_seconds.or(meta.default_slide_seconds);
+        meta.slide_numbers = self.slide_numbers.or(meta.slide_numbers);
+        if let Some(style) = &self.overlay_style {
+            meta.overlay_style = style.clone();
+        }
+
+        for spec in &self.slides {
+            let pages = project.meta.slides.len();
+            let idx = match spec.page.checked_sub(1).filter(|&idx| idx < pages) {
+                Some(idx) => idx,
+                None => return Err(invalid_spec(format!(
+                    "No page {}, the pdf has {}", spec.page, pages,
+                ))),
+            };
+
+            if let Some(audio) = &spec.audio {
+                pipeline.attach_audio(project, idx, audio)?;
+            }
+
+            let slide = &mut project.meta.slides[idx];
+            if let Some(duration) = spec.duration {
+                if !(duration > 0.0 && duration.is_finite()) {
+                    return Err(invalid_spec(format!("Page {} has no valid duration", spec.page)));
+                }
+                slide.duration = Some(duration);
+            }
+            if let Some(tempo) = spec.tempo {
+                if !Slide::is_valid_tempo(tempo) {
+                    return Err(invalid_spec(format!("Page {} has no valid tempo", spec.page)));
+                }
+                slide.tempo = Some(tempo);
+            }
+            slide.sync_offset_ms = spec.sync_offset_ms.unwrap_or(slide.sync_offset_ms);
+            slide.enabled = spec.enabled.unwrap_or(slide.enabled);
+            slide.subtitle = spec.subtitle.clone().or_else(|| slide.subtitle.take());
+            slide.overlay = spec.overlay.clone().or_else(|| slide.overlay.take());
+            slide.repeat = spec.repeat.or(slide.repeat);
+        }
+
+        project.store()
+    }
+}
+
+fn invalid_spec(detail: String) -> FatalError {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid render spec: {}", detail)).into()
+}
+
+impl Report {
+    /// How often the progress is looked at.
+    const TICK: Duration = Duration::from_millis(200);
+
+    fn emit(self, event: &Event) -> io::Result<()> {
+        if self.json {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            serde_json::to_writer(&mut stdout, event)?;
+            return writeln!(stdout);
+        }
+
+        let stderr = io::stderr();
+        let mut stderr = stderr.lock();
+        match event {
+            Event::Progress { .. } | Event::Queued { .. } => Ok(()),
+            Event::Artifact { kind, path } => writeln!(stderr, "Created {} `{}`", kind, path.display()),
+            Event::Uploaded { url } => writeln!(stderr, "Uploaded to {}", url),
+            Event::Result { output: Some(output), .. } => writeln!(stderr, "Video written to `{}`", output.display()),
+            Event::Result { .. } => Ok(()),
+        }
+    }
+
+    fn progress(self, snapshot: Snapshot, new_stage: bool) {
+        if !self.json {
+            // One line per stage, the pages would flood a terminal.
+            if new_stage {
+                eprintln!("{}", snapshot.stage.describe());
+            }
+            return;
+        }
+
+        if snapshot.stage == Stage::Queued {
+            let _ = self.emit(&Event::Queued { position: snapshot.total });
+            return;
+        }
+
+        let _ = self.emit(&Event::Progress {
+            stage: snapshot.stage.name(),
+            page: snapshot.done,
+            pages: snapshot.total,
+            percent: (snapshot.ratio() * 1000.0).round() / 10.0,
+        });
+    }
+
+    /// Report the progress of `work` until it is done.
+    fn watch<T>(self, progress: Arc<Progress>, work: impl FnOnce() -> T) -> T {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let watcher = thread::spawn(move || {
+            let mut last = None;
+            loop {
+                let snapshot = progress.snapshot();
+                let current = (snapshot.stage, snapshot.done, snapshot.total);
+                if snapshot.stage != Stage::Idle && last != Some(current) {
+                    let new_stage = last.map_or(true, |(stage, _, _)| stage != snapshot.stage);
+                    self.progress(snapshot, new_stage);
+                    last = Some(current);
+                }
+
+                match stopped.recv_timeout(Self::TICK) {
+                    Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                    _ => break,
+                }
+            }
+        });
+
+        let result = work();
+        drop(stop);
+        let _ = watcher.join();
+        result
+    }
+}
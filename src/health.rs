@@ -0,0 +1,132 @@
+//! Status of the server, for supervisors and container orchestrators.
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::app::App;
+use crate::watchdog::Watchdog;
+
+/// Counts the jobs, such as renders, that are currently running.
+#[derive(Default)]
+pub struct Jobs {
+    active: AtomicUsize,
+    /// Set when shutting down, no new jobs are accepted.
+    draining: AtomicBool,
+}
+
+/// A running job, counted until dropped.
+pub struct Job<'a> {
+    jobs: &'a Jobs,
+}
+
+/// The versions of the tools we use, which do not change while running.
+pub struct Tools {
+    pub ffmpeg: String,
+    pub magick: Option<String>,
+    pub pdf: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct Health<'a> {
+    pub ffmpeg: &'a str,
+    pub magick: Option<&'a str>,
+    pub pdf: &'static str,
+    /// Bytes available in the directory of projects.
+    pub free_space: Option<u64>,
+    pub active_jobs: usize,
+    /// Jobs waiting for others to finish.
+    pub queued_jobs: usize,
+}
+
+impl Jobs {
+    /// Start a job, unless we are shutting down.
+    pub fn start(&self) -> Option<Job<'_>> {
+        self.active.fetch_add(1, Ordering::SeqCst);
+        let job = Job { jobs: self };
+        // Checked after counting, so that `drain` either sees the job or the job sees the flag.
+        if self.is_draining() {
+            return None;
+        }
+        Some(job)
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    /// Refuse all jobs from now on.
+    pub fn drain(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for Job<'_> {
+    fn drop(&mut self) {
+        self.jobs.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Tools {
+    pub fn detect(app: &App) -> Self {
+        Tools {
+            ffmpeg: app.ffmpeg.version.version.to_string(),
+            magick: magick_version(app.magick.path()),
+            pdf: app.explode.name(),
+        }
+    }
+
+    pub fn health(&self, app: &App) -> Health<'_> {
+        Health {
+            ffmpeg: &self.ffmpeg,
+            magick: self.magick.as_deref(),
+            pdf: self.pdf,
+            free_space: free_space(app.sink.work_dir()),
+            active_jobs: app.jobs.active(),
+            queued_jobs: app.limits.renders.waiting() + app.limits.explodes.waiting(),
+        }
+    }
+}
+
+impl Health<'_> {
+    /// If we could take on a new project of the maximum size.
+    pub fn is_ready(&self, app: &App) -> bool {
+        !app.jobs.is_draining()
+            && self.free_space.map_or(true, |free| free >= app.limits.upload_size())
+    }
+}
+
+/// The first line of `magick -version`, such as `Version: ImageMagick 7.0.10-48 Q16 x86_64`.
+fn magick_version(magick: &Path) -> Option<String> {
+    let output = Watchdog::deadline(Duration::from_secs(10))
+        .output("magick", Command::new(magick).arg("-version"))
+        .ok()?;
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    let line = stdout.lines().next()?;
+    Some(line.trim_start_matches("Version:").trim().to_owned())
+}
+
+#[cfg(unix)]
+fn free_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt as _;
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_space(_: &Path) -> Option<u64> {
+    None
+}
@@ -0,0 +1,249 @@
+//! Convert the pages of a pdf into a video, with narration for each page.
+//!
+//! The binary offers a web interface, a terminal interface and a mode for scripts. Other programs
+//! can use the same conversion through `pipeline::Pipeline`.
+mod api;
+pub mod app;
+mod auth;
+mod cache;
+pub mod cli;
+mod conditional;
+pub mod dropfolder;
+mod explode;
+mod ffmpeg;
+pub mod headless;
+mod health;
+pub mod logging;
+pub mod pipeline;
+pub mod preview;
+pub mod progress;
+pub mod project;
+mod queue;
+pub mod resources;
+pub mod sandbox;
+mod session;
+mod share;
+mod sink;
+#[cfg(test)]
+mod test;
+mod timeline;
+mod upload;
+mod watchdog;
+pub mod web;
+
+use std::fmt;
+
+/// An error of the conversion, or of starting up.
+///
+/// Despite the name, not all of them end the program. Use `class` to decide who should hear
+/// about it and how loudly.
+pub enum FatalError {
+    Io(std::io::Error),
+    /// A corrupt, __internal__ data dump.
+    Corrupt(serde_json::Error),
+    /// Some error in image conversion.
+    Image(image::error::ImageError),
+    /// Some error while rendering to svg.
+    Svg(svg_to_image::Error),
+    /// An input slide that we could not convert to a pixmap.
+    /// This is a theoretical concern as everything is SVG which we try to render. However, just
+    /// preparing for future ideas where this might be more dynamic.
+    UnrecognizedInputSlide,
+    /// The project can not be handled within the configured limits.
+    Limit(app::LimitExceeded),
+    /// The input document could not be opened as a pdf.
+    BadPdf(String),
+    /// An uploaded archive could not be read, or its content not be matched to the project.
+    BadArchive(String),
+    /// A narration is not audio that ffmpeg understands.
+    BadAudio {
+        /// The index of the slide, once known.
+        page: Option<usize>,
+        detail: String,
+    },
+    /// ffmpeg failed, because of the input or because it was built without a codec we need.
+    Ffmpeg(ffmpeg::FfmpegError),
+    /// An external tool failed, often because it does not support the input.
+    Tool {
+        tool: &'static str,
+        detail: String,
+    },
+    /// The project was stored by someone else, such as the web server, since it was read.
+    Conflict,
+}
+
+/// Who has to act on an error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The input can not be used and must be changed, retrying will not help.
+    User,
+    /// The installation or the machine, such as a missing codec or a full disk.
+    Environment,
+    /// Should never happen, regardless of the input.
+    Bug,
+}
+
+impl FatalError {
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            FatalError::Limit(_)
+            | FatalError::BadPdf(_)
+            | FatalError::BadArchive(_)
+            | FatalError::BadAudio { .. }
+            // Pages use features that we can not render.
+            | FatalError::Svg(_)
+            // Most likely the input, in a format the tool does not support.
+            | FatalError::Tool { .. }
+            // Reloading the project and doing the change again works.
+            | FatalError::Conflict => ErrorClass::User,
+            FatalError::Ffmpeg(err) if err.missing_codec().is_some() => ErrorClass::Environment,
+            FatalError::Ffmpeg(_) => ErrorClass::User,
+            FatalError::Io(_) => ErrorClass::Environment,
+            FatalError::Corrupt(_)
+            | FatalError::Image(_)
+            | FatalError::UnrecognizedInputSlide => ErrorClass::Bug,
+        }
+    }
+
+    /// What a tool or the system complained about, for users to fix it.
+    pub fn detail(&self) -> Option<String> {
+        match self {
+            FatalError::Io(err) => Some(err.to_string()),
+            FatalError::BadPdf(detail)
+            | FatalError::BadArchive(detail)
+            | FatalError::BadAudio { detail, .. }
+            | FatalError::Tool { detail, .. } => Some(detail.clone()),
+            FatalError::Ffmpeg(err) => Some(err.stderr_tail.clone()),
+            FatalError::Svg(err) => Some(err.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Attribute an error about the input to a slide.
+    pub fn at_page(self, idx: usize) -> Self {
+        match self {
+            FatalError::BadAudio { page: None, detail } => FatalError::BadAudio {
+                page: Some(idx),
+                detail,
+            },
+            other => other,
+        }
+    }
+
+    /// The tool exited unsuccessfully. The end of its error output usually says why.
+    pub fn tool_failed(tool: &'static str, output: &std::process::Output) -> Self {
+        FatalError::Tool {
+            tool,
+            detail: stderr_tail(output, 20),
+        }
+    }
+}
+
+/// The last lines of the error output of a tool.
+pub(crate) fn stderr_tail(output: &std::process::Output, count: usize) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<_> = stderr.trim_end().lines().collect();
+    lines[lines.len().saturating_sub(count)..].join("\n")
+}
+
+/// Percent-encode what is not allowed in the path of a url, keeping the `/` between segments.
+pub(crate) fn escape_url_path(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    for &byte in path.as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                escaped.push(char::from(byte));
+            }
+            _ => escaped.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    escaped
+}
+
+impl From<ffmpeg::FfmpegError> for FatalError {
+    fn from(err: ffmpeg::FfmpegError) -> FatalError {
+        FatalError::Ffmpeg(err)
+    }
+}
+
+impl From<std::io::Error> for FatalError {
+    fn from(err: std::io::Error) -> FatalError {
+        FatalError::Io(err)
+    }
+}
+
+impl From<image::error::ImageError> for FatalError {
+    fn from(err: image::error::ImageError) -> FatalError {
+        FatalError::Image(err)
+    }
+}
+
+impl From<svg_to_image::Error> for FatalError {
+    fn from(err: svg_to_image::Error) -> FatalError {
+        FatalError::Svg(err)
+    }
+}
+
+impl From<app::LimitExceeded> for FatalError {
+    fn from(err: app::LimitExceeded) -> FatalError {
+        FatalError::Limit(err)
+    }
+}
+
+/// A description for users, without the internals of `Debug`.
+impl fmt::Display for FatalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FatalError::Limit(err) => write!(f, "{}", err),
+            FatalError::BadPdf(_) => f.write_str("The document could not be read as a pdf."),
+            FatalError::BadArchive(_) => f.write_str("The archive could not be used."),
+            FatalError::BadAudio { page: Some(idx), .. } => {
+                write!(f, "The audio of slide {} could not be read.", idx + 1)
+            }
+            FatalError::BadAudio { page: None, .. } => f.write_str("The audio could not be read."),
+            FatalError::Svg(_) => f.write_str("A page could not be rendered."),
+            FatalError::Tool { tool, .. } => write!(f, "Processing with `{}` failed.", tool),
+            FatalError::Conflict => f.write_str("The project was changed elsewhere, reload it."),
+            FatalError::Ffmpeg(err) if err.missing_codec().is_some() => {
+                f.write_str("The installed ffmpeg lacks a required codec or filter.")
+            }
+            FatalError::Ffmpeg(_) => f.write_str("Processing with `ffmpeg` failed."),
+            FatalError::Io(_) => f.write_str("A file could not be accessed."),
+            FatalError::Corrupt(_)
+            | FatalError::Image(_)
+            | FatalError::UnrecognizedInputSlide => f.write_str("An internal error occurred."),
+        }
+    }
+}
+
+impl fmt::Debug for FatalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Not a bug but a problem with the input, no need to be dramatic.
+        if self.class() == ErrorClass::User {
+            write!(f, "{}", self)?;
+            return match (self, self.detail()) {
+                // With the command line, to reproduce it.
+                (FatalError::Ffmpeg(err), _) => write!(f, "\n{}", err),
+                (_, Some(detail)) => write!(f, "\n{}", detail),
+                (_, None) => Ok(()),
+            };
+        }
+
+        writeln!(f, "The program will quit due to a fatal error.")?;
+        writeln!(f, "This should never happen and might be caused by a bad installation.")?;
+        match self {
+            FatalError::Io(io) => write!(f, "I/O error: {}", io),
+            FatalError::Corrupt(err) => write!(f, "Corrupt data structure: {:?}", err),
+            FatalError::Image(err) => write!(f, "Bad image data: {:?}", err),
+            FatalError::Svg(err) => write!(f, "Could not convert svg to pixmap:\n{}", err),
+            FatalError::UnrecognizedInputSlide => write!(f, "An input slide was in unrecognized image format after conversion"),
+            FatalError::Limit(err) => write!(f, "{}", err),
+            FatalError::BadPdf(err) => write!(f, "Could not open pdf: {}", err),
+            FatalError::BadArchive(err) => write!(f, "Could not use archive: {}", err),
+            FatalError::Tool { tool, detail } => write!(f, "The tool `{}` failed:\n{}", tool, detail),
+            FatalError::BadAudio { detail, .. } => write!(f, "Could not read audio: {}", detail),
+            FatalError::Ffmpeg(err) => write!(f, "The tool {}", err),
+            FatalError::Conflict => write!(f, "The project was stored by someone else"),
+        }
+    }
+}
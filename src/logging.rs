@@ -0,0 +1,38 @@
+//! Diagnostic output of the pipeline, as text or as json lines for log collectors.
+use std::io;
+
+use tracing_subscriber::EnvFilter;
+
+use crate::FatalError;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(LogFormat::Text),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Log to stderr everything enabled by the filter, such as `info` or `vid_from_pdf=debug`.
+pub fn init(format: LogFormat, filter: &str) -> Result<(), FatalError> {
+    let filter = EnvFilter::try_new(filter)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr);
+
+    let result = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    };
+
+    result.map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()).into())
+}
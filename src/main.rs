@@ -1,16 +1,7 @@
-mod app;
-mod cli;
-mod explode;
-mod ffmpeg;
-mod project;
-mod resources;
-mod sink;
-#[cfg(test)]
-mod test;
-mod web;
+use std::io::{self, Write as _};
+use std::sync::Arc;
 
-use std::fmt;
-use std::io::Write as _;
+use vid_from_pdf::{app, cli, dropfolder, headless, logging, preview, resources, web, FatalError};
 
 static COMPRESSED_DEPENDENCY_LIST: &[u8] = auditable::inject_dependency_list!();
 
@@ -23,17 +14,49 @@ fn main() {
 
 fn run() -> Result<(), FatalError> {
     let mut cfg = resources::Configuration::from_env()?;
+    let is_daemon = cfg.drop_folder.is_some();
+    let is_headless = cfg.render.is_some() || cfg.render_spec.is_some() || is_daemon;
+    let is_tui = !is_headless && (cfg.force_tui
+        || (crossterm::tty::IsTty::is_tty(&cfg.stdout) && !cfg.force_web));
+    let is_web = !is_headless && (cfg.force_web || !is_tui);
+    if cfg.dry_run && (!is_headless || is_daemon) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "`--dry-run` requires `--render`").into());
+    }
+    if is_daemon && (cfg.render.is_some() || cfg.render_spec.is_some()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "`--drop-folder` can not be combined with `--render`",
+        ).into());
+    }
+    if cfg.render.is_some() && cfg.render_spec.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "`--render-spec` names the pdf itself, it can not be combined with `--render`",
+        ).into());
+    }
+    // The interfaces share the terminal with our log, keep it quiet by default.
+    let log_level = cfg.log_level
+        .as_deref()
+        .unwrap_or(if is_tui || (is_headless && !is_daemon) { "warn" } else { "info" });
+    logging::init(cfg.log_format, log_level)?;
+
     let resources = resources::Resources::force(&cfg)?;
     if cfg.verbose {
         writeln!(cfg.stderr, "Using ffmpeg")?;
         writeln!(cfg.stderr, " ffmpeg: {}", resources.ffmpeg.ffmpeg.as_path().display())?;
         writeln!(cfg.stderr, " ffprobe: {}", resources.ffmpeg.ffprobe.as_path().display())?;
         writeln!(cfg.stderr, " hw acceleration: {}", resources.ffmpeg.hw_accel.as_encoder_str())?;
+        let audio = resources.ffmpeg.audio;
+        writeln!(cfg.stderr, " audio: {} {}", audio.codec.name(), audio.channels.name())?;
         writeln!(cfg.stderr, " version: {}", resources.ffmpeg.version.version)?;
         writeln!(cfg.stderr, "Using temporary directory")?;
         writeln!(cfg.stderr, " path: {}", resources.tempdir.path().display())?;
         writeln!(cfg.stderr, "Using ImageMagick")?;
         writeln!(cfg.stderr, " magick: {}", resources.magick.path().display())?;
+        if let Some(sandbox) = &resources.sandbox {
+            writeln!(cfg.stderr, "Using sandbox")?;
+            writeln!(cfg.stderr, " {}: {}", sandbox.kind().name(), sandbox.path().display())?;
+        }
         resources.explode.verbose_describe(&mut cfg.stderr)?;
 
         writeln!(cfg.stderr, "There is `auditable` information")?;
@@ -50,60 +73,49 @@ fn run() -> Result<(), FatalError> {
         }
             
     }
-    let app = app::App::new(resources);
+    let app = Arc::new(app::App::new(resources));
+
+    if let Some(ref input) = cfg.drop_folder {
+        let output = cfg.drop_output.as_deref().unwrap_or(input);
+        dropfolder::watch(app, input, output)?;
+    } else if let Some(ref spec) = cfg.render_spec {
+        headless::render_spec(app, spec, cfg.json)?;
+    } else if let Some(ref pdf) = cfg.render {
+        let audio = match (&cfg.recording, &cfg.audio) {
+            (Some(recording), _) => headless::Narration::Recording(recording, cfg.split.clone()),
+            (None, Some(audio)) => headless::Narration::Files(audio),
+            (None, None) => headless::Narration::None,
+        };
+        headless::render(
+            app,
+            pdf,
+            audio,
+            cfg.filter,
+            headless::Outputs {
+                video: &cfg.output,
+                intermediates: cfg.keep_intermediates.as_deref(),
+                timeline: cfg.timeline.as_deref(),
+            },
+            cfg.json,
+        )?;
+    } else if is_tui {
+        let graphics = cfg.preview.unwrap_or_else(preview::Graphics::detect);
+        let web = if is_web {
+            web::spawn(app.clone(), &cfg.listen)?;
+            Some(cfg.listen.as_str())
+        } else {
+            None
+        };
 
-    if crossterm::tty::IsTty::is_tty(&cfg.stdout) && !cfg.force_web {
-        cli::tui(app)?;
+        let result = cli::tui(app.clone(), &cfg.output, graphics, web, cfg.watch, cfg.cue);
+        if is_web {
+            web::shutdown(&app);
+        }
+        result?;
         writeln!(cfg.stdout, "")?;
     } else {
-        web::serve(app)?;
+        web::serve(app, &cfg.listen)?;
     }
 
     Ok(())
 }
-
-pub enum FatalError {
-    Io(std::io::Error),
-    /// A corrupt, __internal__ data dump.
-    Corrupt(serde_json::Error),
-    /// Some error in image conversion.
-    Image(image::error::ImageError),
-    /// Some error while rendering to svg.
-    Svg(svg_to_image::Error),
-    /// An input slide that we could not convert to a pixmap.
-    /// This is a theoretical concern as everything is SVG which we try to render. However, just
-    /// preparing for future ideas where this might be more dynamic.
-    UnrecognizedInputSlide,
-}
-
-impl From<std::io::Error> for FatalError {
-    fn from(err: std::io::Error) -> FatalError {
-        FatalError::Io(err)
-    }
-}
-
-impl From<image::error::ImageError> for FatalError {
-    fn from(err: image::error::ImageError) -> FatalError {
-        FatalError::Image(err)
-    }
-}
-
-impl From<svg_to_image::Error> for FatalError {
-    fn from(err: svg_to_image::Error) -> FatalError {
-        FatalError::Svg(err)
-    }
-}
-
-impl fmt::Debug for FatalError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "The program will quit due to a fatal error.")?;
-        writeln!(f, "This should never happen and might be caused by a bad installation.")?;
-        match self {
-            FatalError::Io(io) => write!(f, "I/O error: {}", io),
-            FatalError::Corrupt(err) => write!(f, "Corrupt data structure: {:?}", err),
-            FatalError::Image(err) => write!(f, "Bad image data: {:?}", err),
-            FatalError::Svg(err) => write!(f, "Could not convert svg to pixmap:\n{}", err),
-            FatalError::UnrecognizedInputSlide => write!(f, "An input slide was in unrecognized image format after conversion"),
-        }
-    }
-}
@@ -0,0 +1,169 @@
+//! The conversion of a pdf to a video, for programs embedding it instead of running our binary.
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use vid_from_pdf::pipeline::Pipeline;
+//!
+//! # fn main() -> Result<(), vid_from_pdf::FatalError> {
+//! let pipeline = Pipeline::new()?;
+//! let mut project = pipeline.create(Path::new("talk.pdf"))?;
+//! pipeline.explode(&mut project)?;
+//! pipeline.attach_audio(&mut project, 0, Path::new("intro.wav"))?;
+//! let video = pipeline.render(&mut project)?;
+//! println!("Video in {}", video.display());
+//! # Ok(()) }
+//! ```
+use std::{fs, io, path::Path, path::PathBuf, sync::Arc};
+
+use crate::FatalError;
+use crate::app::App;
+use crate::progress::Snapshot;
+use crate::project::{AssembleReport, Project, Split};
+use crate::resources::{Configuration, Resources};
+use crate::sink::FileSource;
+
+/// The tools and the directory of projects, shared by all projects.
+#[derive(Clone)]
+pub struct Pipeline {
+    app: Arc<App>,
+}
+
+impl Pipeline {
+    /// Find the tools in `PATH` and keep projects in a temporary directory.
+    pub fn new() -> Result<Self, FatalError> {
+        Self::with_configuration(&Configuration::new())
+    }
+
+    /// Use the tools, limits and data directory of a configuration.
+    pub fn with_configuration(cfg: &Configuration) -> Result<Self, FatalError> {
+        let resources = Resources::force(cfg)?;
+        Ok(Pipeline::from_app(Arc::new(App::new(resources))))
+    }
+
+    /// Share the state of a running server or interface.
+    pub fn from_app(app: Arc<App>) -> Self {
+        Pipeline { app }
+    }
+
+    pub fn app(&self) -> &Arc<App> {
+        &self.app
+    }
+
+    /// Create a project with a copy of the pdf, without any slides yet.
+    pub fn create(&self, pdf: &Path) -> Result<Project, FatalError> {
+        let mut file = io::BufReader::new(fs::File::open(pdf)?);
+        let mut project = Project::new(&mut self.app.sink.as_sink(), &mut file)?;
+        project.meta.title = pdf.file_name().map(|name| name.to_string_lossy().into_owned());
+        project.store()?;
+        Ok(project)
+    }
+
+    /// Make one slide of each page of the pdf.
+    pub fn explode(&self, project: &mut Project) -> Result<(), FatalError> {
+        project.explode(&self.app)?;
+        project.store()
+    }
+
+    /// Narrate a slide with a copy of an audio file, in any format ffmpeg understands.
+    pub fn attach_audio(&self, project: &mut Project, idx: usize, audio: &Path)
+        -> Result<(), FatalError>
+    {
+        if idx >= project.meta.slides.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("No slide {}, the project has {}", idx, project.meta.slides.len()),
+            ).into());
+        }
+
+        let mut source = FileSource::new_from_existing(audio.to_owned())?;
+        project.import_audio(idx, &mut source)?;
+        project.analyze_audio(&self.app)?;
+        project.store()
+    }
+
+    /// Narrate the slides with the files of a zip archive or directory, named by page such as
+    /// `slide-01.wav`. Returns the indices of the slides that received audio.
+    pub fn attach_audio_files(&self, project: &mut Project, path: &Path)
+        -> Result<Vec<usize>, FatalError>
+    {
+        let attached = if path.is_dir() {
+            project.attach_audio_dir(path)?
+        } else {
+            project.attach_audio_archive(path, self.app.limits.upload_size())?
+        };
+        project.analyze_audio(&self.app)?;
+        project.store()?;
+        Ok(attached)
+    }
+
+    /// Narrate the enabled slides with the parts of one recording, split at markers or pauses.
+    /// Returns the indices of the slides that received audio.
+    pub fn attach_recording(&self, project: &mut Project, recording: &Path, split: &Split)
+        -> Result<Vec<usize>, FatalError>
+    {
+        let attached = project.attach_recording(recording, split, &self.app)?;
+        project.analyze_audio(&self.app)?;
+        project.store()?;
+        Ok(attached)
+    }
+
+    /// Generate the video, returning its path within the project.
+    pub fn render(&self, project: &mut Project) -> Result<PathBuf, FatalError> {
+        self.assemble(project).map(|(video, _)| video)
+    }
+
+    /// Generate the video as `render`, and copy the files given to ffmpeg into a directory.
+    ///
+    /// These are the pages and pixmaps of the slides, the segments with their list and the meta
+    /// data, for finding out why a video does not look as expected. The copied list names the
+    /// copied segments, such that ffmpeg can join them again within the directory.
+    pub fn render_keeping(&self, project: &mut Project, dir: &Path)
+        -> Result<PathBuf, FatalError>
+    {
+        let (video, report) = self.assemble(project)?;
+        fs::create_dir_all(dir)?;
+        for path in &report.intermediates {
+            let name = match path.file_name() {
+                Some(name) => name,
+                None => continue,
+            };
+
+            if path.extension().map_or(false, |ext| ext == "ffconcat") {
+                fs::write(dir.join(name), Self::relative_list(&fs::read_to_string(path)?))?;
+            } else {
+                fs::copy(path, dir.join(name))?;
+            }
+        }
+        Ok(video)
+    }
+
+    /// A list of segments naming them by file name, relative to the list itself.
+    pub(crate) fn relative_list(list: &str) -> String {
+        let relative = |line: &str| {
+            let path = line.strip_prefix("file '")?.strip_suffix('\'')?;
+            let name = Path::new(path).file_name()?;
+            Some(format!("file '{}'", name.to_string_lossy()))
+        };
+
+        list.lines()
+            .map(|line| relative(line).unwrap_or_else(|| line.to_owned()) + "\n")
+            .collect()
+    }
+
+    fn assemble(&self, project: &mut Project) -> Result<(PathBuf, AssembleReport), FatalError> {
+        let report = project.assemble(&self.app)?;
+        for (idx, reason) in &report.failed {
+            tracing::warn!(page = idx, %reason, "Slide shown as placeholder");
+        }
+        project.store()?;
+        let video = project.meta.output
+            .clone()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No video was generated"))?;
+        Ok((video, report))
+    }
+
+    /// How far `explode` or `render` have come, such as from another thread.
+    pub fn progress(&self, project: &Project) -> Snapshot {
+        project.progress.snapshot()
+    }
+}
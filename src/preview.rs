@@ -0,0 +1,228 @@
+//! Previews of slides inside the terminal, with one of the graphics protocols of terminals.
+use std::io::{self, Write};
+
+use crossterm::{cursor::MoveTo, queue};
+use image::{imageops, RgbImage};
+use tui::{buffer::Buffer, layout::Rect, style::Color, widgets::Widget};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Graphics {
+    /// Images in the protocol of kitty.
+    Kitty,
+    /// Inline images as in iTerm2, also understood by WezTerm.
+    Iterm,
+    Sixel,
+    /// Two pixels per character with unicode half blocks, works in all true color terminals.
+    Blocks,
+    None,
+}
+
+/// Draws an image with half blocks, in a size fitting the area.
+pub struct HalfBlocks<'a>(pub &'a RgbImage);
+
+impl Graphics {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "kitty" => Some(Graphics::Kitty),
+            "iterm" => Some(Graphics::Iterm),
+            "sixel" => Some(Graphics::Sixel),
+            "blocks" => Some(Graphics::Blocks),
+            "none" => Some(Graphics::None),
+            _ => None,
+        }
+    }
+
+    /// Guess the protocol from the environment, since querying the terminal would race with input.
+    pub fn detect() -> Self {
+        let var = |name| std::env::var(name).unwrap_or_default();
+        let term = var("TERM");
+        let program = var("TERM_PROGRAM");
+
+        if term == "xterm-kitty" || !var("KITTY_WINDOW_ID").is_empty() {
+            Graphics::Kitty
+        } else if program == "iTerm.app" || program == "WezTerm" {
+            Graphics::Iterm
+        } else if term.starts_with("mlterm") || term.starts_with("foot") {
+            Graphics::Sixel
+        } else {
+            Graphics::Blocks
+        }
+    }
+
+    /// If the image is written to the terminal by `show`, instead of drawing `HalfBlocks`.
+    pub fn is_protocol(self) -> bool {
+        match self {
+            Graphics::Kitty | Graphics::Iterm | Graphics::Sixel => true,
+            Graphics::Blocks | Graphics::None => false,
+        }
+    }
+
+    /// Write the image into an area of the terminal, keeping its aspect ratio.
+    pub fn show(self, image: &RgbImage, area: Rect, out: &mut impl Write) -> io::Result<()> {
+        let (cell_width, cell_height) = cell_size();
+        let (width, height) = image.dimensions();
+        let scale = f64::min(
+            f64::from(area.width) * cell_width / f64::from(width.max(1)),
+            f64::from(area.height) * cell_height / f64::from(height.max(1)),
+        );
+        let pixels = (
+            ((f64::from(width) * scale) as u32).max(1),
+            ((f64::from(height) * scale) as u32).max(1),
+        );
+        let cells = (
+            (f64::from(pixels.0) / cell_width).ceil().min(f64::from(area.width)) as u16,
+            (f64::from(pixels.1) / cell_height).ceil().min(f64::from(area.height)) as u16,
+        );
+
+        queue!(out, MoveTo(area.x, area.y)).map_err(crossterm_err)?;
+        match self {
+            Graphics::Kitty => {
+                let png = encode_png(image)?;
+                let encoded = base64::encode(&png);
+                let chunks = encoded.as_bytes().chunks(4096);
+                let count = chunks.len();
+                for (idx, chunk) in chunks.enumerate() {
+                    let more = if idx + 1 < count { 1 } else { 0 };
+                    if idx == 0 {
+                        write!(out, "\x1b_Ga=T,f=100,q=2,c={},r={},m={};", cells.0, cells.1, more)?;
+                    } else {
+                        write!(out, "\x1b_Gm={};", more)?;
+                    }
+                    out.write_all(chunk)?;
+                    write!(out, "\x1b\\")?;
+                }
+            }
+            Graphics::Iterm => {
+                let png = encode_png(image)?;
+                write!(
+                    out,
+                    "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07",
+                    png.len(),
+                    cells.0,
+                    cells.1,
+                    base64::encode(&png),
+                )?;
+            }
+            Graphics::Sixel => {
+                let image = imageops::resize(image, pixels.0, pixels.1, imageops::FilterType::Triangle);
+                write_sixel(&image, out)?;
+            }
+            Graphics::Blocks | Graphics::None => {}
+        }
+        out.flush()
+    }
+
+    /// Remove images that are not part of the text, before the area is used otherwise.
+    pub fn clear(self, out: &mut impl Write) -> io::Result<()> {
+        if let Graphics::Kitty = self {
+            write!(out, "\x1b_Ga=d,q=2\x1b\\")?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Widget for HalfBlocks<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let (width, height) = self.0.dimensions();
+        if area.width == 0 || area.height == 0 || width == 0 || height == 0 {
+            return;
+        }
+
+        // Characters are about twice as high as wide, so each half is about square.
+        let scale = f64::min(
+            f64::from(area.width) / f64::from(width),
+            f64::from(area.height) * 2.0 / f64::from(height),
+        );
+        let columns = ((f64::from(width) * scale) as u32).max(1);
+        let rows = ((f64::from(height) * scale) as u32).max(2);
+        let image = imageops::resize(self.0, columns, rows, imageops::FilterType::Triangle);
+
+        for y in 0..(rows / 2).min(u32::from(area.height)) {
+            for x in 0..columns.min(u32::from(area.width)) {
+                let top = image.get_pixel(x, 2 * y);
+                let bottom = image.get_pixel(x, 2 * y + 1);
+                buf.get_mut(area.x + x as u16, area.y + y as u16)
+                    .set_char('▀')
+                    .set_fg(Color::Rgb(top[0], top[1], top[2]))
+                    .set_bg(Color::Rgb(bottom[0], bottom[1], bottom[2]));
+            }
+        }
+    }
+}
+
+fn encode_png(image: &RgbImage) -> io::Result<Vec<u8>> {
+    let mut png = vec![];
+    image::DynamicImage::ImageRgb8(image.clone())
+        .write_to(&mut png, image::ImageOutputFormat::Png)
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    Ok(png)
+}
+
+/// Write as sixels, with the colors reduced to a cube of 6 levels per channel.
+fn write_sixel(image: &RgbImage, out: &mut impl Write) -> io::Result<()> {
+    let (width, height) = image.dimensions();
+    let level = |value: u8| (u32::from(value) * 5 + 127) / 255;
+    let color = |x, y| {
+        let pixel = image.get_pixel(x, y);
+        (level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2])) as usize
+    };
+
+    write!(out, "\x1bPq\"1;1;{};{}", width, height)?;
+    for idx in 0..216u32 {
+        let percent = |level: u32| level * 100 / 5;
+        write!(out, "#{};2;{};{};{}", idx, percent(idx / 36), percent(idx / 6 % 6), percent(idx % 6))?;
+    }
+
+    for band in (0..height).step_by(6) {
+        let rows = (height - band).min(6);
+        let mut used = [false; 216];
+        for y in band..band + rows {
+            for x in 0..width {
+                used[color(x, y)] = true;
+            }
+        }
+
+        for idx in (0..216).filter(|&idx| used[idx]) {
+            write!(out, "#{}", idx)?;
+            for x in 0..width {
+                let bits = (0..rows)
+                    .filter(|&row| color(x, band + row) == idx)
+                    .fold(0u8, |bits, row| bits | 1 << row);
+                out.write_all(&[63 + bits])?;
+            }
+            // Back to the start of the band, for the next color.
+            out.write_all(b"$")?;
+        }
+        out.write_all(b"-")?;
+    }
+
+    write!(out, "\x1b\\")
+}
+
+/// The size of a character in pixels, as reported by the terminal or a common guess.
+#[cfg(unix)]
+fn cell_size() -> (f64, f64) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    if !ok || size.ws_col == 0 || size.ws_row == 0 || size.ws_xpixel == 0 || size.ws_ypixel == 0 {
+        return (8.0, 16.0);
+    }
+
+    (
+        f64::from(size.ws_xpixel) / f64::from(size.ws_col),
+        f64::from(size.ws_ypixel) / f64::from(size.ws_row),
+    )
+}
+
+#[cfg(not(unix))]
+fn cell_size() -> (f64, f64) {
+    (8.0, 16.0)
+}
+
+fn crossterm_err(err: crossterm::ErrorKind) -> io::Error {
+    match err {
+        crossterm::ErrorKind::IoError(io) => io,
+        other => io::Error::new(io::ErrorKind::Other, other),
+    }
+}
@@ -0,0 +1,104 @@
+//! Progress of the long running work on a project, for showing it while waiting.
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Shared between the work and those watching it.
+#[derive(Default)]
+pub struct Progress {
+    stage: AtomicUsize,
+    done: AtomicUsize,
+    total: AtomicUsize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Idle,
+    /// Converting the pages of the pdf.
+    Explode,
+    /// Converting the slides to pixmaps.
+    Render,
+    /// Encoding each slide to a segment of the video.
+    Encode,
+    /// Waiting for other projects, `total` is the position in the queue.
+    Queued,
+}
+
+/// The progress at one point in time.
+#[derive(Clone, Copy, Debug)]
+pub struct Snapshot {
+    pub stage: Stage,
+    pub done: usize,
+    /// The number of steps in this stage, `0` if not known.
+    pub total: usize,
+}
+
+impl Progress {
+    /// Begin a stage with a number of steps.
+    pub fn start(&self, stage: Stage, total: usize) {
+        self.done.store(0, Ordering::SeqCst);
+        self.total.store(total, Ordering::SeqCst);
+        self.stage.store(stage as usize, Ordering::SeqCst);
+    }
+
+    /// One more step of the current stage is done.
+    pub fn advance(&self) {
+        self.done.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Waiting for other jobs before a stage can start.
+    pub fn queued(&self, position: usize) {
+        self.start(Stage::Queued, position);
+    }
+
+    pub fn finish(&self) {
+        self.start(Stage::Idle, 0);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let stage = match self.stage.load(Ordering::SeqCst) {
+            1 => Stage::Explode,
+            2 => Stage::Render,
+            3 => Stage::Encode,
+            4 => Stage::Queued,
+            _ => Stage::Idle,
+        };
+
+        Snapshot {
+            stage,
+            done: self.done.load(Ordering::SeqCst),
+            total: self.total.load(Ordering::SeqCst),
+        }
+    }
+}
+
+impl Snapshot {
+    /// The completed part of the stage, between `0.0` and `1.0`.
+    pub fn ratio(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        (self.done as f64 / self.total as f64).min(1.0)
+    }
+}
+
+impl Stage {
+    /// The name for machines, such as in the events of `--json`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Idle => "idle",
+            Stage::Explode => "explode",
+            Stage::Render => "render",
+            Stage::Encode => "encode",
+            Stage::Queued => "queued",
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Stage::Idle => "Waiting",
+            Stage::Explode => "Converting pages",
+            Stage::Render => "Rendering slides",
+            Stage::Encode => "Encoding video",
+            Stage::Queued => "Waiting for other projects",
+        }
+    }
+}
@@ -1,11 +1,15 @@
-use std::{io, fs, path::PathBuf};
+use std::{cmp::Ordering, io, fs, mem, path::{Path, PathBuf}, sync::Arc, time::SystemTime};
 use index_ext::Int;
 use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
 
-use crate::FatalError;
-use crate::app::App;
-use crate::ffmpeg::Assembly;
+use crate::{ErrorClass, FatalError};
+use crate::app::{App, Frame, LimitExceeded, SlideSeconds};
+use crate::explode;
+use crate::ffmpeg::{Assembly, Silences, Timing};
+use crate::progress::{Progress, Stage};
 use crate::sink::{FileSource, Identifier, Sink, Source};
+use crate::watchdog;
 
 /// A video project.
 ///
@@ -14,6 +18,8 @@ pub struct Project {
     pub dir: Sink,
     pub project_id: Identifier,
     pub meta: Meta,
+    /// How far `explode` or `assemble` have come, watched from other threads.
+    pub progress: Arc<Progress>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -23,6 +29,108 @@ pub struct Meta {
     pub ffcontrol: Option<PathBuf>,
     pub output: Option<PathBuf>,
     pub replacement: Replacement,
+    /// The slides and audio of the output as an OpenTimelineIO timeline.
+    #[serde(default)]
+    pub timeline: Option<PathBuf>,
+    /// The url of the output in object storage, once uploaded.
+    #[serde(default)]
+    pub uploaded: Option<String>,
+    /// Secret granting access to the project without a session.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// The name of the pdf, if known.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Where the pdf was chosen from, to notice when it changes.
+    #[serde(default)]
+    pub origin: Option<PathBuf>,
+    /// Applied to all slides when rasterizing them.
+    #[serde(default)]
+    pub filter: Filter,
+    /// Width by height of the first page, which chooses the shape of the video.
+    #[serde(default)]
+    pub aspect: Option<f32>,
+    /// Slides with shorter audio are shown this long, instead of the configured minimum.
+    #[serde(default)]
+    pub min_slide_seconds: Option<f32>,
+    /// Slides without audio are shown this long, instead of the configured default.
+    #[serde(default)]
+    pub default_slide_seconds: Option<f32>,
+    /// How the overlay text of all slides is drawn.
+    #[serde(default)]
+    pub overlay_style: OverlayStyle,
+    /// Draw `Slide i/N` in this corner of each slide, such as for a copy to review.
+    #[serde(default)]
+    pub slide_numbers: Option<Position>,
+    /// Slides which share the narration of the first of them.
+    #[serde(default)]
+    pub groups: Vec<SlideGroup>,
+    /// Counts the stores, to notice those of others such as the web server since we read it.
+    #[serde(default)]
+    pub revision: u64,
+}
+
+/// Consecutive slides that show the steps of one narration, such as a build-up of a diagram.
+///
+/// The narration and timing are those of the first slide, even if it is disabled. Each enabled
+/// slide of the group is shown for its share of the narration.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SlideGroup {
+    /// The index of the first slide.
+    pub first: usize,
+    /// The number of slides in the group.
+    pub len: usize,
+    /// The relative time of each slide, the same for all unless there is one for each.
+    #[serde(default)]
+    pub shares: Vec<f32>,
+}
+
+/// The part of the narration of its group that a slide plays, as fractions of it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Portion {
+    /// The slide whose narration is played.
+    pub lead: usize,
+    pub start: f32,
+    pub end: f32,
+}
+
+/// A change of the colors of slides, such as for a dark video of a deck with a white background.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    None,
+    /// Invert the lightness but keep the hue, so that white becomes black but red stays red.
+    Dark,
+    /// Invert all colors.
+    Negate,
+}
+
+/// Where one recording of all slides is split into the narration of each.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Split {
+    /// The seconds at which the second, third and following slides begin.
+    Markers(Vec<f32>),
+    /// At the longest pauses, one fewer than the slides.
+    Silence,
+}
+
+/// Problems encountered while assembling the video that did not stop it.
+#[derive(Default)]
+pub struct AssembleReport {
+    /// Slides that are shown as a placeholder, with the reason.
+    pub failed: Vec<(usize, String)>,
+    /// The files ffmpeg was given, such as the pages and pixmaps of slides and the list of
+    /// segments, without audio.
+    pub intermediates: Vec<PathBuf>,
+}
+
+/// What is shown of a project when choosing one to continue.
+pub struct Summary {
+    pub project_id: Identifier,
+    pub title: Option<String>,
+    pub pages: usize,
+    /// When the meta data was last stored.
+    pub modified: Option<SystemTime>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -33,6 +141,103 @@ pub struct Slide {
     pub png: Option<PathBuf>,
     /// The visual, converted to SVG.
     pub svg: Option<PathBuf>,
+    /// Why the page could not be converted, the visual is a placeholder then.
+    #[serde(default)]
+    pub error: Option<String>,
+    /// The `error` is of the last render, not of the explode, and gone once a render succeeds.
+    #[serde(default)]
+    pub render_failed: bool,
+    /// Show the slide this many seconds, trimming or padding its audio.
+    #[serde(default)]
+    pub duration: Option<f32>,
+    /// Start the audio this much later, or earlier to skip a lead-in when negative.
+    #[serde(default)]
+    pub sync_offset_ms: i32,
+    /// Play the audio faster or slower by this factor, keeping the pitch.
+    #[serde(default)]
+    pub tempo: Option<f32>,
+    /// Play only this part of the audio file.
+    #[serde(default)]
+    pub trim: Option<Trim>,
+    /// Disabled slides are left out of the video but keep their audio.
+    #[serde(default = "Slide::default_enabled")]
+    pub enabled: bool,
+    /// Caption text of the slide.
+    #[serde(default)]
+    pub subtitle: Option<String>,
+    /// The silence and speech in the audio file, once analyzed.
+    #[serde(default)]
+    pub speech: Option<Speech>,
+    /// Text drawn over the slide in the video.
+    #[serde(default)]
+    pub overlay: Option<Overlay>,
+    /// Show the slide with its audio more than once, or loop the audio while it is shown.
+    #[serde(default)]
+    pub repeat: Option<Repeat>,
+}
+
+/// How a slide is played more than once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Repeat {
+    /// The slide with its audio, this many times in a row.
+    Times(u32),
+    /// The audio again and again, for as long as the slide is shown.
+    Loop,
+}
+
+/// Text drawn over a slide, such as the name of its section or of the speaker.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Overlay {
+    pub text: String,
+    #[serde(default)]
+    pub position: Position,
+}
+
+/// The corner or edge of the video where overlay text is drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Position {
+    BottomLeft,
+    Bottom,
+    BottomRight,
+    TopLeft,
+    Top,
+    TopRight,
+}
+
+/// How overlay text looks, the same for all slides of a project.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OverlayStyle {
+    /// The height of the text in pixels, of a video that is 1080 pixels high.
+    pub size: u32,
+    /// The color of the text, such as `white` or `0x1e1e1e`.
+    pub color: String,
+    /// The color of a box behind the text, such as `black@0.6`, or no box.
+    pub background: Option<String>,
+    /// A font file, instead of the default font found by fontconfig.
+    pub font: Option<PathBuf>,
+}
+
+/// The part of an audio file that is played, the file itself is kept as it is.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Trim {
+    /// Seconds left out at the start.
+    pub start: f32,
+    /// Seconds from the start of the file where playing stops, or at the end of the file.
+    pub end: Option<f32>,
+}
+
+/// How much of a narration is spoken, to notice clips which are probably empty or cut off.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Speech {
+    /// Seconds of silence before speaking.
+    pub leading: f32,
+    /// Seconds of silence after speaking, none may mean that the recording was cut off.
+    pub trailing: f32,
+    /// Seconds that are not silent.
+    pub spoken: f32,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -40,6 +245,11 @@ pub struct Replacement {
     pub path: Option<PathBuf>,
 }
 
+/// An advisory lock of the meta data of a project, shared by all processes. Released on drop.
+struct MetaLock {
+    _file: fs::File,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Visual {
     /// A particular slide.
@@ -65,97 +275,440 @@ pub enum Audio {
 }
 
 impl Project {
-    /// FIXME: async.
     pub fn new(
         in_dir: &mut Sink,
         from: &mut dyn io::BufRead,
     ) -> Result<Self, FatalError> {
+        let (mut sink, project_id) = Self::create_dir(in_dir)?;
+        let source = sink.store_to_file(from)?;
+        Self::with_source(sink, project_id, source)
+    }
+
+    /// Create the directory for a new project.
+    ///
+    /// The source pdf should be stored into the returned sink, then passed to `with_source`.
+    pub fn create_dir(in_dir: &mut Sink) -> Result<(Sink, Identifier), FatalError> {
         let unique = in_dir.unique_mkdir()?;
-        let mut sink = Sink::new(unique.path)?;
+        let sink = in_dir.nested(unique.path)?;
+        Ok((sink, unique.identifier))
+    }
 
+    pub fn with_source(
+        sink: Sink,
+        project_id: Identifier,
+        source: PathBuf,
+    ) -> Result<Self, FatalError> {
         let meta = Meta {
-            source: sink.store_to_file(from)?,
+            source,
             slides: vec![],
             ffcontrol: None,
             output: None,
             replacement: Replacement::default(),
+            timeline: None,
+            uploaded: None,
+            token: None,
+            title: None,
+            origin: None,
+            filter: Filter::None,
+            aspect: None,
+            min_slide_seconds: None,
+            default_slide_seconds: None,
+            overlay_style: OverlayStyle::default(),
+            slide_numbers: None,
+            groups: vec![],
+            revision: 0,
         };
 
-        let project = Project {
+        let mut project = Project {
             dir: sink,
-            project_id: unique.identifier,
+            project_id,
             meta,
+            progress: Arc::default(),
         };
 
         project.store()?;
         Ok(project)
     }
 
-    /// Open an existing directory as a project.
+    /// Open an existing directory in `in_dir` as a project.
     pub fn load(
         app: &App,
+        in_dir: &Sink,
         project_id: Identifier,
     ) -> Result<Option<Self>, FatalError> {
-        let sink = app.sink.as_sink();
-        let unique_path = sink.path_of(project_id);
+        let unique_path = in_dir.path_of(project_id);
 
         if !unique_path.exists() {
             return Ok(None);
         }
 
-        let sink = Sink::new(unique_path)?;
-        let meta = {
-            use io::Read;
-            // TODO: cap read at some limit here?
-            let file = sink.work_dir().join(Self::PROJECT_META);
-            let meta = fs::File::open(file)?;
-            let mut data = vec![];
-            let max_len = app.limits.meta_size();
-            meta.take(max_len).read_to_end(&mut data)?;
-
-            if data.get_int(..max_len).is_some() {
-                Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "excessive project meta data file",
-                ))?;
-            }
-
-            serde_json::from_slice(data.as_slice())
-                .map_err(FatalError::Corrupt)?
-        };
+        let sink = in_dir.nested(unique_path)?;
+        let meta = Self::read_meta(app, &sink)?;
 
         Ok(Some(Project {
             dir: sink,
             project_id,
             meta,
+            progress: Arc::default(),
         }))
     }
 
+    /// Read the meta data again, as someone else such as the web server may have changed it.
+    ///
+    /// Returns if anything changed.
+    pub fn reload(&mut self, app: &App) -> Result<bool, FatalError> {
+        let meta = Self::read_meta(app, &self.dir)?;
+        // Compare as stored, the meta data itself can not be compared.
+        let stored = serde_json::to_vec(&meta).map_err(FatalError::Corrupt)?;
+        let ours = serde_json::to_vec(&self.meta).map_err(FatalError::Corrupt)?;
+        if stored == ours {
+            return Ok(false);
+        }
+
+        self.meta = meta;
+        Ok(true)
+    }
+
+    /// When the meta data was last stored.
+    pub fn modified(&self) -> Option<SystemTime> {
+        let file = self.dir.work_dir().join(Self::PROJECT_META);
+        fs::metadata(file).ok()?.modified().ok()
+    }
+
+    fn read_meta(app: &App, dir: &Sink) -> Result<Meta, FatalError> {
+        use io::Read;
+        // TODO: cap read at some limit here?
+        let file = dir.work_dir().join(Self::PROJECT_META);
+        let meta = fs::File::open(file)?;
+        let mut data = vec![];
+        let max_len = app.limits.meta_size();
+        meta.take(max_len).read_to_end(&mut data)?;
+
+        if data.get_int(..max_len).is_some() {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "excessive project meta data file",
+            ))?;
+        }
+
+        let meta = serde_json::from_slice(data.as_slice())
+            .map_err(FatalError::Corrupt)?;
+        Ok(meta)
+    }
+
     pub fn import_audio(&mut self, idx: usize, file: &mut impl Source) -> Result<(), FatalError> {
         let src = self.dir.store_to_file(file.as_buf_read())?;
-        self.meta.slides[idx].audio = Audio::File { src };
+        self.attach_audio(idx, src);
         Ok(())
     }
 
-    // FIXME: not fatal errors, such as missing information.
-    pub fn assemble(&mut self, app: &App) -> Result<(), FatalError> {
-        let mut assembly = Assembly::new(&mut self.dir)?;
+    /// Use a file already stored in the project directory as audio of a slide.
+    pub fn attach_audio(&mut self, idx: usize, src: PathBuf) {
+        let slide = &mut self.meta.slides[idx];
+        slide.audio = Audio::File { src };
+        slide.speech = None;
+        slide.trim = None;
+    }
 
-        for slide in &mut self.meta.slides {
-            let audio = match &slide.audio {
+    /// Find the silence in audio files that were not analyzed yet.
+    ///
+    /// Audio that ffmpeg does not understand is left for the render to complain about.
+    pub fn analyze_audio(&mut self, app: &App) -> Result<(), FatalError> {
+        let dir = &mut self.dir;
+        for (idx, slide) in self.meta.slides.iter_mut().enumerate() {
+            let src = match &slide.audio {
+                Audio::File { src } if slide.speech.is_none() => src,
+                _ => continue,
+            };
+
+            let source = FileSource::new_from_existing(src.clone())?;
+            let analyzed = app.ffmpeg
+                .audio_duration(&source, dir)
+                .and_then(|duration| {
+                    let silences = app.ffmpeg.silences(src, dir)?;
+                    Ok(Speech::new(duration, &silences))
+                });
+
+            match analyzed {
+                Ok(speech) => slide.speech = Some(speech),
+                Err(err) if err.class() == ErrorClass::Environment => return Err(err),
+                Err(err) => tracing::warn!(page = idx, "Audio could not be analyzed: {:?}", err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Attach the files of a zip archive to the pages their names end with.
+    ///
+    /// Pages are counted from one, as in `slide-01.wav`, `slide-02.wav`. Nothing is attached unless
+    /// all files match a page and their unpacked size stays below `limit`. Returns the indices of
+    /// the pages that received audio.
+    pub fn attach_audio_archive(&mut self, archive: &Path, limit: u64)
+        -> Result<Vec<usize>, FatalError>
+    {
+        use std::io::Read as _;
+
+        let bad_archive = |err: zip::result::ZipError| FatalError::BadArchive(err.to_string());
+        let file = io::BufReader::new(fs::File::open(archive)?);
+        let mut zip = zip::ZipArchive::new(file).map_err(bad_archive)?;
+
+        let mut matched: Vec<(usize, usize)> = vec![];
+        for entry_idx in 0..zip.len() {
+            let entry = zip.by_index(entry_idx).map_err(bad_archive)?;
+            let name = Path::new(entry.name());
+            // Also skip the metadata that some archivers add.
+            let skip = entry.is_dir()
+                || name.components().any(|part| part.as_os_str() == "__MACOSX")
+                || name.file_name().map_or(true, |name| name.to_string_lossy().starts_with('.'));
+            if skip {
+                continue;
+            }
+
+            let page = name
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(Self::page_number)
+                .filter(|&page| page >= 1 && page <= self.meta.slides.len())
+                .ok_or_else(|| FatalError::BadArchive(format!(
+                    "`{}` does not end with the number of a page, 1 to {}.",
+                    entry.name(),
+                    self.meta.slides.len(),
+                )))?;
+
+            if matched.iter().any(|&(_, other)| other == page - 1) {
+                return Err(FatalError::BadArchive(format!(
+                    "More than one file for page {}.",
+                    page,
+                )));
+            }
+
+            matched.push((entry_idx, page - 1));
+        }
+
+        let mut stored = vec![];
+        let mut remaining = limit;
+        let result = (|| {
+            for &(entry_idx, idx) in &matched {
+                let entry = zip.by_index(entry_idx).map_err(bad_archive)?;
+                let mut reader = io::BufReader::new(entry.take(remaining + 1));
+                let path = self.dir.store_to_file(&mut reader)?;
+                let size = fs::metadata(&path)?.len();
+                stored.push((idx, path));
+
+                remaining = remaining
+                    .checked_sub(size)
+                    .ok_or(LimitExceeded::UploadSize { limit })?;
+            }
+            Ok(())
+        })();
+
+        if let Err(err) = result {
+            for (_, path) in stored {
+                let _ = fs::remove_file(path);
+            }
+            return Err(err);
+        }
+
+        Ok(stored
+            .into_iter()
+            .map(|(idx, path)| {
+                self.attach_audio(idx, path);
+                idx
+            })
+            .collect())
+    }
+
+    /// Attach the files of a directory to the pages their names end with, as in an archive.
+    ///
+    /// Other files are ignored. Returns the indices of the pages that received audio.
+    pub fn attach_audio_dir(&mut self, dir: &Path) -> Result<Vec<usize>, FatalError> {
+        let mut attached = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let page = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .filter(|stem| !stem.starts_with('.'))
+                .and_then(Self::page_number)
+                .filter(|&page| page >= 1 && page <= self.meta.slides.len());
+
+            let idx = match page {
+                Some(page) if path.is_file() => page - 1,
+                _ => continue,
+            };
+
+            if attached.contains(&idx) {
+                return Err(FatalError::BadArchive(format!(
+                    "More than one file for page {}.",
+                    idx + 1,
+                )));
+            }
+
+            self.import_audio(idx, &mut FileSource::new_from_existing(path)?)?;
+            attached.push(idx);
+        }
+
+        attached.sort();
+        Ok(attached)
+    }
+
+    /// Split one recording and attach the parts to the enabled slides, in order.
+    ///
+    /// Returns the indices of the slides that received audio. Without enough markers or pauses the
+    /// last slides keep their audio.
+    pub fn attach_recording(&mut self, recording: &Path, split: &Split, app: &App)
+        -> Result<Vec<usize>, FatalError>
+    {
+        let slides: Vec<_> = self.meta.slides
+            .iter()
+            .enumerate()
+            .filter(|(_, slide)| slide.enabled)
+            .map(|(idx, _)| idx)
+            .collect();
+
+        let cuts = match split {
+            Split::Markers(markers) => {
+                if markers.len() >= slides.len() {
+                    return Err(FatalError::BadAudio {
+                        page: None,
+                        detail: format!(
+                            "{} markers for {} slides, at most one fewer is expected.",
+                            markers.len(),
+                            slides.len(),
+                        ),
+                    });
+                }
+                markers.clone()
+            }
+            Split::Silence => {
+                let source = FileSource::new_from_existing(recording.to_owned())?;
+                let duration = app.ffmpeg.audio_duration(&source, &mut self.dir)?;
+                let silences = app.ffmpeg.silences(recording, &self.dir)?;
+                Split::cuts_at_pauses(&silences, duration, slides.len().saturating_sub(1))
+            }
+        };
+
+        let starts = std::iter::once(0.0).chain(cuts.iter().copied());
+        let ends = cuts.iter().copied().map(Some).chain(std::iter::once(None));
+        let mut attached = vec![];
+        for (&idx, (start, end)) in slides.iter().zip(starts.zip(ends)) {
+            let clip = app.ffmpeg.cut(recording, start, end, &mut self.dir)?;
+            self.attach_audio(idx, clip);
+            attached.push(idx);
+        }
+
+        Ok(attached)
+    }
+
+    /// The number at the end of a file name.
+    fn page_number(stem: &str) -> Option<usize> {
+        let digits = stem.len() - stem.trim_end_matches(|ch: char| ch.is_ascii_digit()).len();
+        stem[stem.len() - digits..].parse().ok()
+    }
+
+    /// Render all enabled slides and encode the video.
+    ///
+    /// A slide that can not be rendered, even when retried, is shown as a placeholder and its
+    /// `error` set. Only problems of the environment, such as a full disk, stop the video.
+    pub fn assemble(&mut self, app: &App) -> Result<AssembleReport, FatalError> {
+        let span = tracing::info_span!("assemble", dir = %self.dir.work_dir().display());
+        let _enter = span.enter();
+
+        let progress = &self.progress;
+        let _slot = app.limits.renders.acquire(|position| {
+            tracing::info!(position, "Waiting for other renders");
+            progress.queued(position);
+        });
+
+        let mut assembly = Assembly::new();
+        let mut report = AssembleReport::default();
+
+        // Slides of a group play the narration of its first.
+        let portions = self.meta.group_portions();
+        let lead = |idx: usize| portions[idx].map_or(idx, |portion| portion.lead);
+        let slides = &self.meta.slides;
+        let total = (0..slides.len())
+            .filter(|&idx| slides[idx].enabled && !matches!(slides[lead(idx)].audio, Audio::Skip))
+            .count();
+        self.progress.start(Stage::Render, total);
+
+        let seconds = self.slide_seconds(app);
+        for idx in 0..self.meta.slides.len() {
+            if !self.meta.slides[idx].enabled {
+                continue;
+            }
+
+            let portion = portions[idx];
+            let narrated = &self.meta.slides[lead(idx)];
+            let mut timing = Timing {
+                shown: narrated.duration,
+                offset_ms: narrated.sync_offset_ms,
+                tempo: narrated.tempo.filter(|&tempo| Slide::is_valid_tempo(tempo)).unwrap_or(1.0),
+                trim: narrated.trim,
+                // Repeating is for whole narrations, not the steps of a group.
+                repeat: narrated.repeat.filter(|_| portion.is_none()),
+                portion: portion.map(|portion| (portion.start, portion.end)),
+                min: seconds.min.unwrap_or(0.0),
+            };
+            let audio = match &narrated.audio {
                 Audio::Skip => continue,
                 Audio::File { src } => FileSource::new_from_existing(src.clone())?,
                 Audio::Silent => {
+                    timing.shown = timing.shown.or(seconds.default);
                     let path = self.meta.replacement.silent_audio(&mut self.dir, app)?;
                     FileSource::new_from_existing(path.clone())?
                 },
             };
-            let visual = slide.render_visual(&mut self.dir, app)?;
-            assembly.add_linked(&app.ffmpeg, &visual, &audio, &mut self.dir)?;
+            let slide = &mut self.meta.slides[idx];
+            let visual = match slide.render_retried(&mut self.dir, app, self.meta.filter) {
+                Ok(visual) => {
+                    if mem::take(&mut slide.render_failed) {
+                        slide.error = None;
+                    }
+                    visual
+                }
+                Err(err) if err.class() == ErrorClass::Environment => return Err(err),
+                Err(err) => {
+                    tracing::warn!(page = idx, "Slide replaced by placeholder: {:?}", err);
+                    let reason = match err.detail() {
+                        Some(detail) => format!("{} {}", err, detail.lines().last().unwrap_or("")),
+                        None => err.to_string(),
+                    };
+                    slide.error = Some(reason.clone());
+                    slide.render_failed = true;
+                    report.failed.push((idx, reason));
+                    self.meta.replacement.placeholder(&mut self.dir)?
+                }
+            };
+            assembly.add_linked(&app.ffmpeg, &visual, &audio, timing, &mut self.dir)
+                .map_err(|err| err.at_page(idx))?;
+            if let Some(overlay) = &slide.overlay {
+                assembly.overlay(overlay, &self.meta.overlay_style);
+            }
+            if let Some(position) = self.meta.slide_numbers {
+                let text = format!("Slide {}/{}", assembly.segments(), total);
+                assembly.overlay(&Overlay { text, position }, &self.meta.overlay_style);
+            }
+            report.intermediates.extend(slide.svg.clone());
+            report.intermediates.push(visual.as_path().to_owned());
+            self.progress.advance();
         }
 
+        assembly.end(app.ending);
+        let seconds = assembly.duration().ceil() as u64;
+        let limit = app.limits.video_duration();
+        if seconds > limit {
+            return Err(LimitExceeded::VideoDuration { seconds, limit }.into());
+        }
+
+        tracing::info!(seconds, "Encoding video");
+        self.progress.start(Stage::Encode, assembly.segments());
+
+        let frame = self.frame(app);
         let mut outsink = &mut self.dir;
-        assembly.finalize(&app.ffmpeg, &mut outsink)?;
+        let inputs = assembly.finalize(&app.ffmpeg, frame, &mut outsink, &|| progress.advance())?;
+        report.intermediates.extend(inputs);
 
         let output = outsink
             .imported()
@@ -165,13 +718,49 @@ impl Project {
                 "Apparently no output was produced",
             )))?;
 
+        // Next to the video, referring to the files of the project.
+        let timeline = output.with_extension("otio");
+        let title = self.meta.title.as_deref().unwrap_or("vid-from-pdf");
+        let document = assembly.timeline(title, &self.dir);
+        fs::write(&timeline, serde_json::to_vec_pretty(&document).map_err(io::Error::from)?)?;
+
+        // Named by the project, so that rendering it again replaces the earlier video.
+        self.meta.uploaded = match &app.upload {
+            Some(upload) => {
+                let id = base64::encode_config(&self.project_id, base64::URL_SAFE);
+                Some(upload.put(&output, &format!("{}.mp4", id), &mut self.dir)?)
+            }
+            None => None,
+        };
+
+        tracing::info!(output = %output.display(), failed = report.failed.len(), "Rendered");
         self.meta.output = Some(output);
-        Ok(())
+        self.meta.timeline = Some(timeline);
+        self.progress.finish();
+        Ok(report)
+    }
+
+    /// How long slides with short or missing audio are shown, as configured unless set here.
+    pub fn slide_seconds(&self, app: &App) -> SlideSeconds {
+        let own = SlideSeconds {
+            min: self.meta.min_slide_seconds,
+            default: self.meta.default_slide_seconds,
+        };
+        own.or(app.slide_seconds)
+    }
+
+    /// How the slides are placed in the video, in a shape fitting them unless configured.
+    pub fn frame(&self, app: &App) -> Frame {
+        app.frame.for_slides(self.meta.aspect)
     }
 
     /// Convert all visuals to png versions.
     pub fn thumbnail(&mut self) -> Result<(), FatalError> {
         for slide in &mut self.meta.slides {
+            if slide.png.is_some() {
+                continue;
+            }
+
             match slide.visual {
                 Visual::Slide { ref src, .. } => {
                     let mut path = src.clone();
@@ -184,38 +773,355 @@ impl Project {
         Ok(())
     }
 
-    pub fn store(&self) -> Result<(), FatalError> {
+    /// A downscaled jpeg of a page, created on first request and kept in the project.
+    pub fn page_thumbnail(&mut self, idx: usize, width: u32, app: &App)
+        -> Result<PathBuf, FatalError>
+    {
+        use image::GenericImageView as _;
+
+        // Only a few distinct sizes, so that clients can not fill the disk with them.
+        let width = width.max(Self::THUMBNAIL_WIDTH.0).min(Self::THUMBNAIL_WIDTH.1);
+        let width = (width + 31) / 32 * 32;
+        let filter = self.meta.filter;
+        let name = format!("{}{}-{}{}.jpg", Self::THUMBNAIL_PREFIX, idx, width, filter.suffix());
+        let path = self.dir.work_dir().join(name);
+        if path.exists() {
+            return Ok(path);
+        }
+
+        let slide = &mut self.meta.slides[idx];
+        let png = slide.render_visual(&mut self.dir, app, filter)?;
+        let image = image::open(png.as_path())?;
+
+        let height = u64::from(width) * u64::from(image.height()) / u64::from(image.width().max(1));
+        let height = height.max(1).min(u64::from(u32::MAX)) as u32;
+        let thumbnail = image.thumbnail_exact(width, height).to_rgb8();
+
+        // Written under another name first, a concurrent request must not serve a partial file.
+        let unique = self.dir.unique_path()?;
+        thumbnail.save_with_format(&unique.path, image::ImageFormat::Jpeg)?;
+        fs::rename(&unique.path, &path)?;
+        Ok(path)
+    }
+
+    /// All projects in `in_dir`, the most recently changed first.
+    ///
+    /// Directories that are not projects or can not be loaded are left out.
+    pub fn list(app: &App, in_dir: &Sink) -> Result<Vec<Summary>, FatalError> {
+        let mut projects = vec![];
+        for entry in fs::read_dir(in_dir.work_dir())? {
+            let entry = entry?;
+            let project_id = match entry.file_name().to_str().and_then(Sink::identifier_of) {
+                Some(project_id) => project_id,
+                None => continue,
+            };
+
+            let meta = entry.path().join(Self::PROJECT_META);
+            let modified = match fs::metadata(&meta) {
+                Ok(meta) => meta.modified().ok(),
+                Err(_) => continue,
+            };
+
+            if let Ok(Some(project)) = Self::load(app, in_dir, project_id) {
+                projects.push(Summary {
+                    project_id,
+                    title: project.meta.title,
+                    pages: project.meta.slides.len(),
+                    modified,
+                });
+            }
+        }
+
+        projects.sort_by(|a, b| b.modified.cmp(&a.modified));
+        Ok(projects)
+    }
+
+    /// Write the meta data at once, unless it was stored by someone else since we read it.
+    ///
+    /// An interrupted write, such as when we are killed, leaves the previous version intact. The
+    /// terminal interface and the web server may both change a project, the one storing later
+    /// gets `FatalError::Conflict` instead of undoing the changes of the other.
+    pub fn store(&mut self) -> Result<(), FatalError> {
+        self.store_merged(|_, _| Err(FatalError::Conflict))
+    }
+
+    /// Store the results of `assemble`, keeping what others stored meanwhile.
+    ///
+    /// Narration may be uploaded or slides trimmed while rendering, failing afterwards would
+    /// throw away the video. Only the video with its timeline and upload, the pixmaps and errors
+    /// of the slides, and the replacement audio are taken over into the stored meta data.
+    pub fn store_rendered(&mut self) -> Result<(), FatalError> {
+        self.store_merged(|stored, ours| {
+            stored.take_rendered(ours);
+            Ok(())
+        })
+    }
+
+    /// Store the pixmaps converted for thumbnails, keeping what others stored meanwhile.
+    pub fn store_pixmaps(&mut self) -> Result<(), FatalError> {
+        self.store_merged(|stored, ours| {
+            stored.take_pixmaps(ours);
+            Ok(())
+        })
+    }
+
+    /// Write the meta data, `merge` ours into the stored one if that changed since we read it.
+    fn store_merged(&mut self, merge: impl FnOnce(&mut Meta, &Meta) -> Result<(), FatalError>)
+        -> Result<(), FatalError>
+    {
+        #[derive(Deserialize)]
+        struct Stored {
+            #[serde(default)]
+            revision: u64,
+        }
+
         let file = self.dir.work_dir().join(Self::PROJECT_META);
-        let meta = fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(file)?;
-        serde_json::to_writer(meta, &self.meta).map_err(io::Error::from)?;
+        // Held until replaced, such that no one stores in between.
+        let _lock = MetaLock::acquire(&self.dir.work_dir().join(Self::PROJECT_LOCK))?;
+        match fs::read(&file) {
+            Ok(content) => {
+                let stored: Stored = serde_json::from_slice(&content).map_err(FatalError::Corrupt)?;
+                if stored.revision != self.meta.revision {
+                    let mut stored: Meta = serde_json::from_slice(&content)
+                        .map_err(FatalError::Corrupt)?;
+                    merge(&mut stored, &self.meta)?;
+                    self.meta = stored;
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        self.meta.revision += 1;
+        let partial = file.with_extension("partial");
+        let mut meta = fs::File::create(&partial)?;
+        serde_json::to_writer(&mut meta, &self.meta).map_err(io::Error::from)?;
+        meta.sync_all()?;
+        fs::rename(partial, file)?;
         Ok(())
     }
 
     pub fn explode(&mut self, app: &App) -> Result<(), FatalError> {
+        let span = tracing::info_span!("explode", dir = %self.dir.work_dir().display());
+        let _enter = span.enter();
+
+        // Before waiting and converting, for all ways a pdf enters a project.
+        explode::validate(&*app.explode, &self.meta.source, &app.limits)?;
+
+        let progress = &self.progress;
+        let _slot = app.limits.explodes.acquire(|position| {
+            tracing::info!(position, "Waiting for other explodes");
+            progress.queued(position);
+        });
+
         let mut source = FileSource::new_from_existing(self.meta.source.clone())?;
-        app.explode.explode(&mut source, &mut self.dir)?;
+        let exploded = app.explode.explode(&mut source, &mut self.dir, &self.progress);
+        self.progress.finish();
+        let mut report = match exploded {
+            Ok(report) => report,
+            Err(err) => {
+                self.remove_imported();
+                return Err(err);
+            }
+        };
+
+        let count = self.dir.imported_len() as u64;
+        tracing::info!(pages = count, failed = report.failed.len(), "Exploded pdf");
+        for (idx, reason) in &report.failed {
+            tracing::warn!(page = idx, %reason, "Page replaced by placeholder");
+        }
+
+        // Not every pdf can be counted before converting it.
+        let limit = app.limits.page_count();
+        if count > limit {
+            self.remove_imported();
+            return Err(LimitExceeded::PageCount { count, limit }.into());
+        }
+
+        if !report.rotated.is_empty() {
+            tracing::info!(pages = ?report.rotated, "Turned pages in portrait");
+        }
+
+        self.meta.aspect = report.aspect;
+        if let (Some(aspect), Some(geometry)) = (report.aspect, self.frame(app).geometry) {
+            if !geometry.fits(aspect) {
+                let video = geometry.name();
+                tracing::warn!(aspect, video, "Slides do not fit the shape of the video, bars are added");
+            }
+        }
 
         self.meta.slides.clear();
         for (idx, src) in self.dir.imported().enumerate() {
+            // Raster pages, such as scans, are already converted.
+            let png = match src.extension() {
+                Some(ext) if ext == "png" => Some(src.clone()),
+                _ => None,
+            };
+
+            let error = report.failed
+                .iter()
+                .position(|&(failed, _)| failed == idx)
+                .map(|pos| report.failed.swap_remove(pos).1);
+
             self.meta.slides.push(Slide {
                 visual: Visual::Slide { src, idx, },
                 audio: Audio::Skip,
-                png: None,
+                png,
                 svg: None,
+                error,
+                render_failed: false,
+                duration: None,
+                sync_offset_ms: 0,
+                tempo: None,
+                trim: None,
+                enabled: true,
+                subtitle: None,
+                speech: None,
+                overlay: None,
+                repeat: None,
             })
         }
 
         Ok(())
     }
 
+    /// Remove the pages of an explode that failed.
+    fn remove_imported(&mut self) {
+        for path in self.dir.imported() {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Use a changed version of the pdf, keeping the audio and settings of matching slides.
+    ///
+    /// Returns the number of slides that were matched to an earlier one.
+    pub fn replace_source(&mut self, from: &mut dyn io::BufRead, app: &App)
+        -> Result<usize, FatalError>
+    {
+        let source = self.dir.store_to_file(from)?;
+        let old_source = mem::replace(&mut self.meta.source, source);
+        let old = mem::take(&mut self.meta.slides);
+
+        if let Err(err) = self.explode(app) {
+            self.meta.source = old_source;
+            self.meta.slides = old;
+            return Err(err);
+        }
+
+        let before: Vec<_> = old.iter().map(Slide::page_digest).collect();
+        let after: Vec<_> = self.meta.slides.iter().map(Slide::page_digest).collect();
+        let mut old: Vec<_> = old.into_iter().map(Some).collect();
+
+        let mut matched = 0;
+        for (slide, pair) in self.meta.slides.iter_mut().zip(pair_pages(&before, &after)) {
+            if let Some(earlier) = pair.and_then(|idx| old[idx].take()) {
+                slide.audio = earlier.audio;
+                slide.duration = earlier.duration;
+                slide.sync_offset_ms = earlier.sync_offset_ms;
+                slide.tempo = earlier.tempo;
+                slide.trim = earlier.trim;
+                slide.enabled = earlier.enabled;
+                slide.subtitle = earlier.subtitle;
+                slide.overlay = earlier.overlay;
+                slide.repeat = earlier.repeat;
+                slide.speech = earlier.speech;
+                matched += 1;
+            }
+        }
+
+        // The video and thumbnails show the old slides.
+        self.meta.output = None;
+        self.meta.timeline = None;
+        self.meta.uploaded = None;
+        self.remove_thumbnails();
+        Ok(matched)
+    }
+
+    /// Thumbnails are named by page, not by its content.
+    fn remove_thumbnails(&self) {
+        let entries = match fs::read_dir(self.dir.work_dir()) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for entry in entries.filter_map(Result::ok) {
+            let is_thumbnail = entry
+                .file_name()
+                .to_str()
+                .map_or(false, |name| name.starts_with(Self::THUMBNAIL_PREFIX));
+            if is_thumbnail {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
     const PROJECT_META: &'static str = ".project";
+    const PROJECT_LOCK: &'static str = ".project.lock";
+    const THUMBNAIL_PREFIX: &'static str = "thumb-";
+    /// Bounds of the thumbnail width.
+    pub const THUMBNAIL_WIDTH: (u32, u32) = (32, 1920);
 }
 
 impl Slide {
-    fn render_visual(&mut self, sink: &mut Sink, app: &App) -> Result<FileSource, FatalError> {
+    /// Shows the same page, the pdf may have been replaced in between.
+    fn same_page(&self, other: &Slide) -> bool {
+        match (&self.visual, &other.visual) {
+            (Visual::Slide { src, .. }, Visual::Slide { src: other, .. }) => src == other,
+        }
+    }
+
+    fn default_enabled() -> bool {
+        true
+    }
+
+    /// Faster or slower than this is noticeably distorted.
+    pub fn is_valid_tempo(tempo: f32) -> bool {
+        (0.75..=1.5).contains(&tempo)
+    }
+
+    /// Identifies the content of the page, `None` if it can not be read.
+    fn page_digest(&self) -> Option<Vec<u8>> {
+        let Visual::Slide { src, .. } = &self.visual;
+        let data = fs::read(src).ok()?;
+        Some(Sha256::digest(&data).to_vec())
+    }
+
+    /// Render the visual, trying once more if that fails, such as when magick crashed.
+    fn render_retried(&mut self, sink: &mut Sink, app: &App, filter: Filter)
+        -> Result<FileSource, FatalError>
+    {
+        match self.render_visual(sink, app, filter) {
+            Err(err) if err.class() != ErrorClass::Environment => {
+                tracing::debug!("Retrying to render slide: {:?}", err);
+                self.render_visual(sink, app, filter)
+            }
+            other => other,
+        }
+    }
+
+    /// The pixmap of the visual with the filter applied.
+    fn render_visual(&mut self, sink: &mut Sink, app: &App, filter: Filter)
+        -> Result<FileSource, FatalError>
+    {
+        let png = self.rasterize(sink, app)?;
+        // There is no pixmap to filter.
+        if filter == Filter::None || watchdog::is_dry_run() {
+            return Ok(png);
+        }
+
+        // Kept next to the unfiltered pixmap, so that changing the filter back is free.
+        let stem = png.as_path().file_stem().unwrap_or_default().to_string_lossy();
+        let path = png.as_path().with_file_name(format!("{}{}.png", stem, filter.suffix()));
+        if !path.exists() {
+            let mut image = image::open(png.as_path())?.to_rgba8();
+            filter.apply(&mut image);
+            image.save_with_format(&path, image::ImageFormat::Png)?;
+        }
+
+        Ok(FileSource::new_from_existing(path)?)
+    }
+
+    fn rasterize(&mut self, sink: &mut Sink, app: &App) -> Result<FileSource, FatalError> {
         // Shortcut, if we already have a pixmap.
         if let Some(src) = &self.png {
             let file_source = FileSource::new_from_existing(src.clone())?;
@@ -223,7 +1129,10 @@ impl Slide {
         }
 
         match &self.visual {
-            Visual::Slide { src, .. } => {
+            Visual::Slide { src, idx } => {
+                let span = tracing::debug_span!("render_slide", page = idx);
+                let _enter = span.enter();
+
                 let mut path = src.clone();
                 // usvg is picky about file endings. GEEEEEEEZ.
                 path.set_extension("svg");
@@ -232,12 +1141,28 @@ impl Slide {
                 let path = self.svg.as_ref().unwrap();
 
                 let svg = app.magick.open(path)?;
-                let image = svg.render()?;
-                let unique = sink.unique_path()?;
-                let image = image.to_rgba8();
-
-                image.save_with_format(&unique.path, image::ImageFormat::Png)?;
-                self.png = Some(unique.path);
+                // Named by page, so that renders of the same input can be compared.
+                let png = sink.work_dir().join(format!("slide-{:03}.png", idx + 1));
+                // Filtered from the pixmap of an earlier conversion of the page.
+                for filter in &[Filter::Dark, Filter::Negate] {
+                    let filtered = format!("slide-{:03}{}.png", idx + 1, filter.suffix());
+                    match fs::remove_file(sink.work_dir().join(filtered)) {
+                        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                        _ => {}
+                    }
+                }
+                match svg.command_line() {
+                    Some(command) if watchdog::is_dry_run() => {
+                        watchdog::print_command(None, command, Some(path));
+                        // Only listed for ffmpeg, which does not run either.
+                        fs::File::create(&png)?;
+                    }
+                    _ => {
+                        let image = svg.render()?.to_rgba8();
+                        image.save_with_format(&png, image::ImageFormat::Png)?;
+                    }
+                }
+                self.png = Some(png);
             },
         }
 
@@ -250,18 +1175,256 @@ impl Slide {
     }
 }
 
+impl Meta {
+    /// Take the pixmaps of the slides from `ours`, for the pages that are still the same.
+    fn take_pixmaps(&mut self, ours: &Meta) {
+        for (slide, rendered) in self.slides.iter_mut().zip(&ours.slides) {
+            if slide.same_page(rendered) && rendered.png.is_some() {
+                slide.png = rendered.png.clone();
+                slide.svg = rendered.svg.clone();
+            }
+        }
+    }
+
+    /// Take everything `assemble` produced from `ours`.
+    fn take_rendered(&mut self, ours: &Meta) {
+        self.take_pixmaps(ours);
+        for (slide, rendered) in self.slides.iter_mut().zip(&ours.slides) {
+            if slide.same_page(rendered) {
+                slide.error = rendered.error.clone();
+                slide.render_failed = rendered.render_failed;
+            }
+        }
+
+        self.output = ours.output.clone();
+        self.timeline = ours.timeline.clone();
+        self.uploaded = ours.uploaded.clone();
+        if self.replacement.path.is_none() {
+            self.replacement.path = ours.replacement.path.clone();
+        }
+    }
+
+    /// The part of the narration each slide plays, `None` for slides in no group.
+    ///
+    /// Groups past the last slide or overlapping an earlier group are ignored.
+    pub fn group_portions(&self) -> Vec<Option<Portion>> {
+        let mut portions = vec![None; self.slides.len()];
+        for group in &self.groups {
+            let members = group.first..group.first.saturating_add(group.len);
+            let taken = |portions: &[Option<Portion>]| portions.iter().any(Option::is_some);
+            if members.end > self.slides.len() || taken(&portions[members.clone()]) {
+                continue;
+            }
+
+            let own_shares = group.shares.len() == group.len
+                && group.shares.iter().all(|&share| share > 0.0 && share.is_finite());
+            let shares: Vec<f32> = members
+                .clone()
+                .map(|idx| match self.slides[idx].enabled {
+                    false => 0.0,
+                    true if own_shares => group.shares[idx - group.first],
+                    true => 1.0,
+                })
+                .collect();
+
+            let sum: f32 = shares.iter().sum();
+            if !(sum > 0.0) {
+                continue;
+            }
+
+            let mut start = 0.0;
+            for (idx, share) in members.zip(shares) {
+                let end = (start + share / sum).min(1.0);
+                portions[idx] = Some(Portion { lead: group.first, start, end });
+                start = end;
+            }
+        }
+
+        portions
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position::BottomLeft
+    }
+}
+
+impl Default for OverlayStyle {
+    fn default() -> Self {
+        OverlayStyle {
+            size: 48,
+            color: "white".to_owned(),
+            background: Some("black@0.6".to_owned()),
+            font: None,
+        }
+    }
+}
+
+impl Trim {
+    /// Trim points that leave some audio, `None` otherwise.
+    pub fn new(start: f32, end: Option<f32>) -> Option<Self> {
+        let valid_end = end.map_or(true, |end| end.is_finite() && end > start);
+        if !(start >= 0.0 && start.is_finite() && valid_end) {
+            return None;
+        }
+        Some(Trim { start, end })
+    }
+
+    /// The seconds played of audio of this length.
+    pub fn length(self, duration: f32) -> f32 {
+        let end = self.end.map_or(duration, |end| end.min(duration));
+        (end - self.start).max(0.0)
+    }
+}
+
+impl Speech {
+    /// Silence at the very start, within this many seconds, is leading.
+    const START: f32 = 0.05;
+
+    pub(crate) fn new(duration: f32, silences: &Silences) -> Self {
+        let silences = &silences.ending_at(duration);
+        let leading = silences.pauses
+            .first()
+            .filter(|&&(start, _)| start < Self::START)
+            .map_or(0.0, |&(_, end)| end);
+        let trailing = silences.trailing.map_or(0.0, |start| (duration - start).max(0.0));
+        let paused: f32 = silences.pauses.iter().map(|&(start, end)| end - start).sum();
+
+        // All of it is silent.
+        if silences.trailing.map_or(false, |start| start < Self::START) {
+            return Speech { leading: duration, trailing: duration, spoken: 0.0 };
+        }
+
+        Speech {
+            leading,
+            trailing,
+            spoken: (duration - paused - trailing).max(0.0),
+        }
+    }
+}
+
+impl Split {
+    /// Where to cut a recording of `duration` into at most `count + 1` parts, at its longest
+    /// pauses.
+    pub(crate) fn cuts_at_pauses(silences: &Silences, duration: f32, count: usize) -> Vec<f32> {
+        // Quiet before the first or after the last words is between no slides.
+        let mut pauses: Vec<_> = silences
+            .ending_at(duration)
+            .pauses
+            .into_iter()
+            .filter(|&(start, _)| start >= Speech::START)
+            .collect();
+        // The longest pauses are the most likely to be between slides.
+        let length = |&(start, end): &(f32, f32)| end - start;
+        pauses.sort_by(|a, b| length(b).partial_cmp(&length(a)).unwrap_or(Ordering::Equal));
+        pauses.truncate(count);
+        // Cut in the middle, so that each part begins and ends with some quiet.
+        let mut cuts: Vec<_> = pauses
+            .iter()
+            .map(|(start, end)| (start + end) / 2.0)
+            .collect();
+        cuts.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        cuts
+    }
+
+    /// Markers separated by commas, each in seconds or as `minutes:seconds`.
+    ///
+    /// The markers must increase, each is where the next slide begins.
+    pub fn from_markers(list: &str) -> Option<Self> {
+        let mut markers = vec![];
+        for marker in list.split(',') {
+            let mut seconds = 0.0f32;
+            for part in marker.trim().split(':') {
+                seconds = seconds * 60.0 + part.parse::<f32>().ok()?;
+            }
+
+            let after_last = markers.last().map_or(seconds > 0.0, |&last| seconds > last);
+            if !seconds.is_finite() || !after_last {
+                return None;
+            }
+            markers.push(seconds);
+        }
+        Some(Split::Markers(markers))
+    }
+}
+
+impl Filter {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Filter::None),
+            "dark" => Some(Filter::Dark),
+            "negate" => Some(Filter::Negate),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Filter::None => "none",
+            Filter::Dark => "dark",
+            Filter::Negate => "negate",
+        }
+    }
+
+    /// The filter after this one, for switching through all of them.
+    pub fn next(self) -> Self {
+        match self {
+            Filter::None => Filter::Dark,
+            Filter::Dark => Filter::Negate,
+            Filter::Negate => Filter::None,
+        }
+    }
+
+    /// Appended to the names of filtered files.
+    fn suffix(self) -> String {
+        match self {
+            Filter::None => String::new(),
+            other => format!("-{}", other.name()),
+        }
+    }
+
+    pub fn apply(self, image: &mut image::RgbaImage) {
+        for pixel in image.pixels_mut() {
+            let [r, g, b, _] = &mut pixel.0;
+            match self {
+                Filter::None => {}
+                Filter::Dark => {
+                    // Moving all channels by the same amount keeps hue and saturation in HSL,
+                    // and turns the lightness `(max + min) / 2` into its inverse.
+                    let shift = 255 - i16::from((*r).max(*g).max(*b)) - i16::from((*r).min(*g).min(*b));
+                    for channel in [r, g, b] {
+                        *channel = (i16::from(*channel) + shift) as u8;
+                    }
+                }
+                Filter::Negate => {
+                    for channel in [r, g, b] {
+                        *channel = 255 - *channel;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::None
+    }
+}
+
 impl Replacement {
+    /// A gray frame in place of a slide that could not be rendered.
+    fn placeholder(&self, sink: &mut Sink) -> Result<FileSource, FatalError> {
+        let unique = sink.unique_path()?;
+        let image = image::RgbImage::from_pixel(1920, 1080, image::Rgb([0x30, 0x30, 0x30]));
+        image.save_with_format(&unique.path, image::ImageFormat::Png)?;
+        Ok(FileSource::new_from_existing(unique.path)?)
+    }
+
     fn silent_audio(&mut self, sink: &mut Sink, app: &App) -> Result<&PathBuf, FatalError> {
         if self.path.is_none() {
-            app.ffmpeg.replacement_audio(10.0f32, sink)?;
-            let file = sink
-                .imported()
-                .next()
-                .ok_or_else(|| io::Error::new(
-                    io::ErrorKind::NotFound,
-                    "ffmpeg failed to produce replacement audio",
-                ))?;
-            self.path = Some(file);
+            self.path = Some(app.ffmpeg.silence(10.0f32, sink)?);
         }
 
         Ok(self.path.as_ref().unwrap())
@@ -280,8 +1443,55 @@ impl Replacement {
     }
 }
 
+impl MetaLock {
+    #[cfg(unix)]
+    fn acquire(path: &Path) -> io::Result<Self> {
+        use std::os::unix::io::AsRawFd as _;
+        let file = fs::OpenOptions::new().create(true).write(true).truncate(false).open(path)?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(MetaLock { _file: file })
+    }
+
+    /// Without advisory locks, stores can only be noticed by their revision.
+    #[cfg(not(unix))]
+    fn acquire(path: &Path) -> io::Result<Self> {
+        let file = fs::OpenOptions::new().create(true).write(true).truncate(false).open(path)?;
+        Ok(MetaLock { _file: file })
+    }
+}
+
 impl Default for Audio {
     fn default() -> Self {
         Audio::Skip
     }
 }
+
+/// Pair each page of a new version with one of the earlier version.
+///
+/// Identical pages are paired first, in order, so that inserted or removed pages do not shift the
+/// pairs. The remaining pages are paired with an unpaired page at the same position, as edits
+/// usually change a page in place.
+pub fn pair_pages<T: PartialEq>(before: &[Option<T>], after: &[Option<T>]) -> Vec<Option<usize>> {
+    let mut used = vec![false; before.len()];
+    let mut pairs: Vec<_> = after
+        .iter()
+        .map(|page| {
+            let page = page.as_ref()?;
+            let idx = (0..before.len())
+                .find(|&idx| !used[idx] && before[idx].as_ref() == Some(page))?;
+            used[idx] = true;
+            Some(idx)
+        })
+        .collect();
+
+    for (idx, pair) in pairs.iter_mut().enumerate() {
+        if pair.is_none() && idx < before.len() && !used[idx] {
+            used[idx] = true;
+            *pair = Some(idx);
+        }
+    }
+
+    pairs
+}
@@ -0,0 +1,87 @@
+//! Bound how many jobs of one kind run at once, such as encodes which each use all cores.
+//!
+//! Excess jobs wait in the order they arrived and are told their position while waiting.
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub struct Queue {
+    /// How many jobs may run at once, `0` for no limit.
+    pub capacity: AtomicU64,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    running: u64,
+    /// The ticket handed to the next job that arrives.
+    next: u64,
+    /// The ticket of the first job still waiting.
+    serving: u64,
+}
+
+/// The permission to run, until dropped.
+pub struct Slot<'a> {
+    queue: &'a Queue,
+}
+
+impl Queue {
+    pub fn new(capacity: u64) -> Self {
+        Queue {
+            capacity: AtomicU64::new(capacity),
+            state: Mutex::default(),
+            changed: Condvar::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// Wait until the job may run.
+    ///
+    /// While waiting, `position` is called with the place in the queue whenever it changes,
+    /// starting at `1` for the job that runs next.
+    pub fn acquire(&self, mut position: impl FnMut(usize)) -> Slot<'_> {
+        let mut state = self.lock();
+        let ticket = state.next;
+        state.next += 1;
+
+        let mut reported = None;
+        loop {
+            let ahead = (ticket - state.serving) as usize;
+            let capacity = self.capacity();
+            if ahead == 0 && (capacity == 0 || state.running < capacity) {
+                state.serving += 1;
+                state.running += 1;
+                // The job behind us may fit as well.
+                self.changed.notify_all();
+                return Slot { queue: self };
+            }
+
+            if reported != Some(ahead) {
+                position(ahead + 1);
+                reported = Some(ahead);
+            }
+
+            state = self.changed.wait(state).unwrap_or_else(|err| err.into_inner());
+        }
+    }
+
+    /// The number of jobs currently waiting.
+    pub fn waiting(&self) -> usize {
+        let state = self.lock();
+        (state.next - state.serving) as usize
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.queue.lock().running -= 1;
+        self.queue.changed.notify_all();
+    }
+}
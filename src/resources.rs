@@ -1,12 +1,23 @@
-use std::{env, fmt, ffi::OsString, io::Write as _, path::Path};
+use std::{env, fmt, fs, io, ffi::OsStr, ffi::OsString, io::Write as _, path::Path, path::PathBuf, sync::atomic::Ordering};
+use rand::Rng as _;
+use serde::Deserialize;
 use svg_to_image::MagickConvert;
 use tempfile::TempDir;
 use which::CanonicalPath;
 
 use crate::FatalError;
+use crate::app::{Ending, Frame, Geometry, Limits, SlideSeconds, Watch};
+use crate::auth::Users;
+use crate::cache::RenderCache;
 use crate::explode::ExplodePdf;
-use crate::ffmpeg::Ffmpeg;
-use crate::sink::Sink;
+use crate::ffmpeg::{AudioCodec, AudioEncoding, Channels, Cue, Ffmpeg, VideoRate};
+use crate::logging::LogFormat;
+use crate::preview::Graphics;
+use crate::project::{Filter, Split};
+use crate::sandbox::{Sandbox, SandboxKind};
+use crate::sink::{ContentStore, Sink};
+use crate::upload::{Upload, UploadTarget};
+use crate::watchdog;
 
 /// Command line and environment provided configuration.
 pub struct Configuration {
@@ -15,14 +26,149 @@ pub struct Configuration {
     pub this: Option<OsString>,
     pub verbose: bool,
     pub force_web: bool,
+    /// Run the terminal interface even with `--web`, both working on the same projects.
+    pub force_tui: bool,
+    /// Store files with identical content only once.
+    pub deduplicate: bool,
+    pub limits: Limits,
+    /// Where projects are kept across restarts, instead of the temporary directory.
+    pub data_dir: Option<PathBuf>,
+    /// File with the users allowed to access the web server.
+    pub users: Option<PathBuf>,
+    pub log_format: LogFormat,
+    /// Filter of log messages, such as `debug`, instead of the default.
+    pub log_level: Option<String>,
+    /// Address of the web server.
+    pub listen: String,
+    /// The path suggested when saving the video from the terminal interface.
+    pub output: PathBuf,
+    /// How the terminal interface shows slides, detected from the terminal if `None`.
+    pub preview: Option<Graphics>,
+    /// Convert the pdf of the project again when it changes, in the terminal interface.
+    pub watch: Option<Watch>,
+    /// Played before the audio of slides in the terminal interface, never in the video.
+    pub cue: Option<Cue>,
+    /// Convert this pdf to a video at `output`, without any interface.
+    pub render: Option<PathBuf>,
+    /// The narration of `render`, as a zip archive or directory of files such as `slide-01.wav`.
+    pub audio: Option<PathBuf>,
+    /// The narration of `render` as one recording of all slides, instead of `audio`.
+    pub recording: Option<PathBuf>,
+    /// Where `recording` is split.
+    pub split: Split,
+    /// The filter of the slides of `render`.
+    pub filter: Filter,
+    /// Report the progress of `render` as lines of json on stdout.
+    pub json: bool,
+    /// Print the commands of `render` to stdout, instead of running them.
+    pub dry_run: bool,
+    /// Copy the files given to ffmpeg by `render` into this directory.
+    pub keep_intermediates: Option<PathBuf>,
+    /// Write the timeline of `render` to this file, for opening it in a video editor.
+    pub timeline: Option<PathBuf>,
+    /// Convert as described by this json file, instead of `render` and its flags.
+    pub render_spec: Option<PathBuf>,
+    /// Render each pdf put into this directory, without any interface.
+    pub drop_folder: Option<PathBuf>,
+    /// Where the videos of `drop_folder` go, the same directory if `None`.
+    pub drop_output: Option<PathBuf>,
+    /// Upload each video to object storage.
+    pub upload: UploadTarget,
+    /// Name files deterministically and encode bit exact, for comparing renders.
+    pub seed: Option<u64>,
+    /// Tools to use instead of those found in `PATH`.
+    pub tools: ToolPaths,
+    /// Run the tools parsing uploaded files in this sandbox.
+    pub sandbox: Option<SandboxKind>,
+    /// Convert the colors of rendered slides with this ICC profile.
+    pub icc_profile: Option<PathBuf>,
+    /// The padding and background around slides in the video.
+    pub frame: Frame,
+    /// How long slides with short or missing audio are shown.
+    pub slide_seconds: SlideSeconds,
+    /// The hold of the last slide and the fade out.
+    pub ending: Ending,
+    /// The codec, bitrate and channels of the audio in videos.
+    pub audio_encoding: AudioEncoding,
+    /// The bitrate or size of videos, the quality of the encoder by default.
+    pub video_rate: VideoRate,
+}
+
+/// Explicit paths of external tools.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolPaths {
+    pub ffmpeg: Option<PathBuf>,
+    pub ffprobe: Option<PathBuf>,
+    pub magick: Option<PathBuf>,
+    pub curl: Option<PathBuf>,
+}
+
+/// The configuration file, all settings are optional.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    data_dir: Option<PathBuf>,
+    users: Option<PathBuf>,
+    listen: Option<String>,
+    output: Option<PathBuf>,
+    drop_folder: Option<PathBuf>,
+    drop_output: Option<PathBuf>,
+    preview: Option<String>,
+    cue: Option<String>,
+    sandbox: Option<String>,
+    icc_profile: Option<PathBuf>,
+    aspect: Option<String>,
+    orientation: Option<String>,
+    padding: Option<u32>,
+    background_color: Option<String>,
+    min_slide_seconds: Option<f32>,
+    default_slide_seconds: Option<f32>,
+    end_hold: Option<f32>,
+    fade_out: Option<f32>,
+    audio_codec: Option<String>,
+    audio_bitrate: Option<u32>,
+    audio_channels: Option<String>,
+    video_bitrate: Option<u32>,
+    target_size: Option<f64>,
+    seed: Option<u64>,
+    dedup: Option<bool>,
+    log_format: Option<String>,
+    log_level: Option<String>,
+    limits: ConfigLimits,
+    tools: ToolPaths,
+    upload: UploadTarget,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigLimits {
+    max_upload_size: Option<u64>,
+    max_pdf_size: Option<u64>,
+    max_pages: Option<u64>,
+    max_duration: Option<u64>,
+    max_renders: Option<u64>,
+    max_explodes: Option<u64>,
+    max_cache_size: Option<u64>,
 }
 
 pub struct Resources {
     pub ffmpeg: Ffmpeg,
     pub magick: svg_to_image::MagickConvert,
+    /// The sandbox of the tools, also installed for all of them.
+    pub sandbox: Option<Sandbox>,
     pub tempdir: TempDir,
     pub dir_as_sink: Sink,
     pub explode: Box<dyn ExplodePdf>,
+    pub limits: Limits,
+    pub frame: Frame,
+    pub slide_seconds: SlideSeconds,
+    pub ending: Ending,
+    /// Stable key for signing session cookies, if sessions should be persisted.
+    pub session_key: Option<Vec<u8>>,
+    /// Users of the web server, if it requires authentication.
+    pub users: Option<Users>,
+    pub upload: Option<Upload>,
 }
 
 pub struct RequiredToolError {
@@ -40,10 +186,15 @@ impl Resources {
     /// possible to arrive at a suitable configuration.
     pub fn force(cfg: &Configuration) -> Result<Self, FatalError> {
         // First, try and load all parts. Then give a condensed message with all missing parts.
-        let ffmpeg = Ffmpeg::new();
-        let magick = require_tool(MagickConvert::MAGICK);
+        let ffmpeg = Ffmpeg::new(&cfg.tools, cfg.audio_encoding, cfg.video_rate);
+        let magick = require_tool(MagickConvert::MAGICK, cfg.tools.magick.as_deref());
         let tempdir = cfg.new_tempdir();
-        let explode = ExplodePdf::new();
+        let explode = ExplodePdf::new(cfg.frame);
+        let sandbox = cfg.sandbox.map(|kind| (kind, require_tool(kind.name(), None)));
+        let curl = match cfg.upload.is_configured() {
+            true => Some(require_tool(Upload::CURL, cfg.tools.curl.as_deref())),
+            false => None,
+        };
 
         let mut report = cfg.error_reporter();
         if let Err(err) = &ffmpeg {
@@ -58,49 +209,177 @@ impl Resources {
         if let Err(err) = &explode {
             report.eat_err(err);
         }
+        if let Some((_, Err(err))) = &sandbox {
+            report.eat_err(err);
+        }
+        if let Some(Err(err)) = &curl {
+            report.eat_err(err);
+        }
         report.assert()?;
 
         let ffmpeg = ffmpeg.unwrap_or_else(|_| unreachable!());
         let magick = magick.unwrap_or_else(|_| unreachable!());
         let tempdir = tempdir.unwrap_or_else(|_| unreachable!());
-        let sink = Sink::new(tempdir.path().to_owned())?;
+        let (data_dir, session_key) = match &cfg.data_dir {
+            Some(data_dir) => {
+                fs::create_dir_all(data_dir)?;
+                (data_dir.clone(), Some(cfg.session_key(data_dir)?))
+            }
+            None => (tempdir.path().to_owned(), None),
+        };
+
+        let users = match &cfg.users {
+            Some(path) => Some(Users::from_file(path)?),
+            None => None,
+        };
+
+        let mut sink = Sink::new(data_dir.clone())?;
+        if cfg.deduplicate {
+            let store = ContentStore::new(data_dir.join(Self::CONTENT_STORE))?;
+            sink.with_content_store(store);
+        }
+        let cache_size = cfg.limits.cache_size();
+        if cache_size > 0 {
+            let cache = RenderCache::new(data_dir.join(Self::RENDER_CACHE), cache_size)?;
+            sink.with_render_cache(cache);
+        }
+        if let Some(seed) = cfg.seed {
+            sink.with_seed(seed);
+        }
         let explode = explode.unwrap_or_else(|_| unreachable!());
+        let upload = curl
+            .map(|curl| curl.unwrap_or_else(|_| unreachable!()))
+            .and_then(|curl| Upload::new(&cfg.upload, curl));
+
+        let sandbox = match sandbox {
+            Some((kind, exe)) => {
+                let exe = exe.unwrap_or_else(|_| unreachable!());
+                let writable = vec![tempdir.path().canonicalize()?, data_dir.canonicalize()?];
+                let sandbox = Sandbox::new(kind, exe, writable);
+                sandbox.clone().install();
+                Some(sandbox)
+            }
+            None => None,
+        };
+
+        let mut magick = MagickConvert::new(magick)?;
+        if let Some(sandbox) = &sandbox {
+            // It only reads from stdin and writes to stdout.
+            magick = magick.with_wrapper(sandbox.prefix(None));
+        }
+        if let Some(profile) = &cfg.icc_profile {
+            magick = magick.with_profile(profile.canonicalize()?);
+        }
+
+        // Only now, all probes above must run.
+        if cfg.dry_run {
+            watchdog::dry_run();
+        }
 
         Ok(Resources {
             ffmpeg,
-            magick: MagickConvert::new(magick)?,
+            magick,
+            sandbox,
             tempdir,
             dir_as_sink: sink,
             explode,
+            limits: cfg.limits.clone(),
+            frame: cfg.frame,
+            slide_seconds: cfg.slide_seconds,
+            ending: cfg.ending,
+            session_key,
+            users,
+            upload,
         })
     }
+
+    /// Not a valid project identifier so it can not collide.
+    const CONTENT_STORE: &'static str = ".content";
+    /// Not a valid project identifier either.
+    const RENDER_CACHE: &'static str = ".cache";
 }
 
 impl Configuration {
+    /// The defaults, without reading the configuration file or flags.
+    pub fn new() -> Self {
+        Configuration {
+            stdout: std::io::stdout(),
+            stderr: std::io::stderr(),
+            this: None,
+            verbose: false,
+            force_web: false,
+            force_tui: false,
+            deduplicate: false,
+            limits: Limits::default(),
+            data_dir: None,
+            users: None,
+            log_format: LogFormat::Text,
+            log_level: None,
+            listen: "localhost:8051".into(),
+            output: "/tmp/output.mp4".into(),
+            preview: None,
+            watch: None,
+            cue: None,
+            render: None,
+            audio: None,
+            recording: None,
+            split: Split::Silence,
+            filter: Filter::None,
+            json: false,
+            dry_run: false,
+            keep_intermediates: None,
+            timeline: None,
+            render_spec: None,
+            drop_folder: None,
+            drop_output: None,
+            upload: UploadTarget::default(),
+            seed: None,
+            tools: ToolPaths::default(),
+            sandbox: None,
+            icc_profile: None,
+            frame: Frame::default(),
+            slide_seconds: SlideSeconds::default(),
+            ending: Ending::default(),
+            audio_encoding: AudioEncoding::default(),
+            video_rate: VideoRate::Quality,
+        }
+    }
+
     pub fn from_env() -> Result<Self, FatalError> {
         enum HowToParse {
             CurrentProgram,
             ExpectArg,
         }
 
-        let mut cfg = Configuration {
-            stdout: std::io::stdout(),
-            stderr: std::io::stderr(),
-            this: None,
-            verbose: false,
-            force_web: false,
-        };
+        let mut cfg = Configuration::new();
 
+        // Flags take precedence, so apply the file first.
+        let explicit = env::args_os().skip(1).find_map(|arg| Self::config_argument(&arg));
+        match explicit {
+            Some(path) => cfg.load_file(&path)?,
+            None => if let Some(path) = Self::default_file() {
+                if path.exists() {
+                    cfg.load_file(&path)?;
+                }
+            },
+        }
 
         let mut how = HowToParse::CurrentProgram;
         for arg in env::args_os() {
             match how {
                 HowToParse::CurrentProgram => cfg.this = Some(arg),
+                // Already loaded, the path need not be unicode.
+                HowToParse::ExpectArg if Self::config_argument(&arg).is_some() => {}
                 HowToParse::ExpectArg => match arg.to_str() {
                     Some("-v") | Some("-verbose") => cfg.verbose = true,
                     Some("-h") | Some("-help") | Some("--help") => cfg.bail_help()?,
                     Some("--web") => cfg.force_web = true,
-                    Some(other) => cfg.bail_unknown_argument(other)?,
+                    Some("--tui") => cfg.force_tui = true,
+                    Some("--watch") => cfg.watch = Some(Watch::Pages),
+                    Some("--json") => cfg.json = true,
+                    Some("--dry-run") => cfg.dry_run = true,
+                    Some("--dedup") => cfg.deduplicate = true,
+                    Some(other) => cfg.parse_value_argument(other)?,
                     None => cfg.bail_bad_argument(arg)?,
                 }
             }
@@ -111,10 +390,506 @@ impl Configuration {
         Ok(cfg)
     }
 
+    /// The path of a `--config=PATH` argument.
+    fn config_argument(arg: &OsStr) -> Option<PathBuf> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt as _;
+            let path = arg.as_bytes().strip_prefix(b"--config=")?;
+            Some(PathBuf::from(OsStr::from_bytes(path)))
+        }
+        #[cfg(not(unix))]
+        {
+            arg.to_str()?.strip_prefix("--config=").map(PathBuf::from)
+        }
+    }
+
+    /// `$XDG_CONFIG_HOME/vid-from-pdf/config.toml`, by default in `~/.config`.
+    fn default_file() -> Option<PathBuf> {
+        let config = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config.join("vid-from-pdf").join("config.toml"))
+    }
+
+    fn load_file(&mut self, path: &Path) -> Result<(), FatalError> {
+        let content = fs::read_to_string(path)?;
+        let file: ConfigFile = match toml::from_str(&content) {
+            Ok(file) => file,
+            Err(err) => {
+                writeln!(&mut self.stderr, "Invalid configuration `{}`: {}", path.display(), err)?;
+                std::process::exit(1);
+            }
+        };
+
+        if let Some(format) = file.log_format {
+            match LogFormat::from_name(&format) {
+                Some(format) => self.log_format = format,
+                None => self.bail_bad_value("log_format", &format)?,
+            }
+        }
+
+        if let Some(preview) = file.preview {
+            self.set_preview("preview", &preview)?;
+        }
+
+        if let Some(cue) = file.cue {
+            self.set_cue("cue", &cue)?;
+        }
+
+        if let Some(sandbox) = file.sandbox {
+            self.set_sandbox("sandbox", &sandbox)?;
+        }
+
+        self.icc_profile = file.icc_profile.or(self.icc_profile.take());
+
+        if let Some(aspect) = file.aspect {
+            self.set_aspect("aspect", &aspect)?;
+        }
+
+        if let Some(orientation) = file.orientation {
+            self.set_orientation("orientation", &orientation)?;
+        }
+
+        if let Some(padding) = file.padding {
+            self.set_padding("padding", &padding.to_string())?;
+        }
+
+        if let Some(color) = file.background_color {
+            self.set_background("background_color", &color)?;
+        }
+
+        if let Some(seconds) = file.min_slide_seconds {
+            let name = "min_slide_seconds";
+            self.slide_seconds.min = self.parse_slide_seconds(name, &seconds.to_string())?;
+        }
+
+        if let Some(seconds) = file.default_slide_seconds {
+            let name = "default_slide_seconds";
+            self.slide_seconds.default = self.parse_slide_seconds(name, &seconds.to_string())?;
+        }
+
+        if let Some(seconds) = file.end_hold {
+            self.ending.hold = self.parse_end_seconds("end_hold", &seconds.to_string())?;
+        }
+
+        if let Some(seconds) = file.fade_out {
+            self.ending.fade = self.parse_end_seconds("fade_out", &seconds.to_string())?;
+        }
+
+        if let Some(codec) = file.audio_codec {
+            self.set_audio_codec("audio_codec", &codec)?;
+        }
+
+        if let Some(bitrate) = file.audio_bitrate {
+            self.set_audio_bitrate("audio_bitrate", &bitrate.to_string())?;
+        }
+
+        if let Some(channels) = file.audio_channels {
+            self.set_audio_channels("audio_channels", &channels)?;
+        }
+
+        if let Some(bitrate) = file.video_bitrate {
+            self.set_video_bitrate("video_bitrate", &bitrate.to_string())?;
+        }
+
+        if let Some(megabytes) = file.target_size {
+            self.set_target_size("target_size", &megabytes.to_string())?;
+        }
+
+        let limits = [
+            (file.limits.max_upload_size, &self.limits.upload_size),
+            (file.limits.max_pdf_size, &self.limits.pdf_size),
+            (file.limits.max_pages, &self.limits.page_count),
+            (file.limits.max_duration, &self.limits.video_duration),
+            (file.limits.max_renders, &self.limits.renders.capacity),
+            (file.limits.max_explodes, &self.limits.explodes.capacity),
+            (file.limits.max_cache_size, &self.limits.cache_size),
+        ];
+        for (value, limit) in limits.iter() {
+            if let Some(value) = value {
+                limit.store(*value, Ordering::Relaxed);
+            }
+        }
+
+        self.data_dir = file.data_dir.or(self.data_dir.take());
+        self.users = file.users.or(self.users.take());
+        self.log_level = file.log_level.or(self.log_level.take());
+        self.deduplicate = file.dedup.unwrap_or(self.deduplicate);
+        self.seed = file.seed.or(self.seed);
+        if let Some(listen) = file.listen {
+            self.listen = listen;
+        }
+        if let Some(output) = file.output {
+            self.output = output;
+        }
+        self.drop_folder = file.drop_folder.or(self.drop_folder.take());
+        self.drop_output = file.drop_output.or(self.drop_output.take());
+        self.tools = file.tools;
+        self.upload = file.upload;
+        Ok(())
+    }
+
     fn new_tempdir(&self) -> Result<TempDir, std::io::Error> {
         TempDir::new_in(".")
     }
 
+    /// Load the key for signing session cookies, creating it on first use.
+    fn session_key(&self, data_dir: &Path) -> Result<Vec<u8>, FatalError> {
+        let path = data_dir.join(".session-key");
+        match fs::read(&path) {
+            Ok(key) if key.len() >= Self::SESSION_KEY_LEN => {
+                Self::restrict_to_owner(&path)?;
+                return Ok(key);
+            }
+            // Too short to be used, replace it.
+            Ok(_) => fs::remove_file(&path)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {},
+            Err(err) => return Err(err.into()),
+        }
+
+        let mut key = vec![0; Self::SESSION_KEY_LEN];
+        rand::thread_rng().fill(&mut key[..]);
+
+        // Whoever reads the key can forge sessions and share links.
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt as _;
+            options.mode(0o600);
+        }
+
+        options.open(&path)?.write_all(&key)?;
+        Ok(key)
+    }
+
+    /// Keep a key created by an earlier version, readable by everyone, from others.
+    #[cfg(unix)]
+    fn restrict_to_owner(path: &Path) -> Result<(), FatalError> {
+        use std::os::unix::fs::PermissionsExt as _;
+        let mut permissions = fs::metadata(path)?.permissions();
+        if permissions.mode() & 0o077 != 0 {
+            permissions.set_mode(0o600);
+            fs::set_permissions(path, permissions)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn restrict_to_owner(_: &Path) -> Result<(), FatalError> {
+        Ok(())
+    }
+
+    const SESSION_KEY_LEN: usize = 64;
+
+    /// Choose the sandbox of tools, `none` to run them directly.
+    fn set_sandbox(&mut self, name: &str, value: &str) -> Result<(), FatalError> {
+        match (value, SandboxKind::from_name(value)) {
+            ("none", _) => self.sandbox = None,
+            (_, Some(kind)) => self.sandbox = Some(kind),
+            (_, None) => self.bail_bad_value(name, value)?,
+        }
+        Ok(())
+    }
+
+    /// Choose the graphics of slide previews, `auto` to detect them.
+    fn set_preview(&mut self, name: &str, value: &str) -> Result<(), FatalError> {
+        match (value, Graphics::from_name(value)) {
+            ("auto", _) => self.preview = None,
+            (_, Some(graphics)) => self.preview = Some(graphics),
+            (_, None) => self.bail_bad_value(name, value)?,
+        }
+        Ok(())
+    }
+
+    /// Choose the sound before playing audio, `none` to play it right away.
+    fn set_cue(&mut self, name: &str, value: &str) -> Result<(), FatalError> {
+        match (value, Cue::from_name(value)) {
+            ("none", _) => self.cue = None,
+            (_, Some(cue)) => self.cue = Some(cue),
+            (_, None) => self.bail_bad_value(name, value)?,
+        }
+        Ok(())
+    }
+
+    /// Choose the shape of the video, `auto` for the one closest to the slides.
+    fn set_aspect(&mut self, name: &str, value: &str) -> Result<(), FatalError> {
+        match (value, Geometry::from_name(value)) {
+            ("auto", _) => self.frame.geometry = None,
+            (_, Some(geometry)) => self.frame.geometry = Some(geometry),
+            (_, None) => self.bail_bad_value(name, value)?,
+        }
+        Ok(())
+    }
+
+    fn set_orientation(&mut self, name: &str, value: &str) -> Result<(), FatalError> {
+        match value {
+            "fit" => self.frame.rotate = false,
+            "rotate" => self.frame.rotate = true,
+            _ => self.bail_bad_value(name, value)?,
+        }
+        Ok(())
+    }
+
+    fn set_padding(&mut self, name: &str, value: &str) -> Result<(), FatalError> {
+        match value.parse() {
+            Ok(padding) if Frame::is_valid_padding(padding) => self.frame.padding = padding,
+            _ => self.bail_bad_value(name, value)?,
+        }
+        Ok(())
+    }
+
+    fn set_audio_codec(&mut self, name: &str, value: &str) -> Result<(), FatalError> {
+        match AudioCodec::from_name(value) {
+            Some(codec) => self.audio_encoding.codec = codec,
+            None => self.bail_bad_value(name, value)?,
+        }
+        Ok(())
+    }
+
+    /// In kbit/s, `default` for the one of the encoder.
+    fn set_audio_bitrate(&mut self, name: &str, value: &str) -> Result<(), FatalError> {
+        match (value, value.parse::<u32>()) {
+            ("default", _) => self.audio_encoding.bitrate = None,
+            (_, Ok(bitrate)) if (8..=512).contains(&bitrate) => {
+                self.audio_encoding.bitrate = Some(bitrate);
+            }
+            _ => self.bail_bad_value(name, value)?,
+        }
+        Ok(())
+    }
+
+    fn set_audio_channels(&mut self, name: &str, value: &str) -> Result<(), FatalError> {
+        match Channels::from_name(value) {
+            Some(channels) => self.audio_encoding.channels = channels,
+            None => self.bail_bad_value(name, value)?,
+        }
+        Ok(())
+    }
+
+    /// In kbit/s, `default` for the quality of the encoder.
+    fn set_video_bitrate(&mut self, name: &str, value: &str) -> Result<(), FatalError> {
+        match (value, value.parse::<u32>()) {
+            ("default", _) => self.video_rate = VideoRate::Quality,
+            (_, Ok(bitrate)) if bitrate > 0 => self.video_rate = VideoRate::Bitrate(bitrate),
+            _ => self.bail_bad_value(name, value)?,
+        }
+        Ok(())
+    }
+
+    /// In megabytes, as upload limits are usually given.
+    fn set_target_size(&mut self, name: &str, value: &str) -> Result<(), FatalError> {
+        match value.parse::<f64>() {
+            Ok(megabytes) if megabytes > 0.0 && megabytes.is_finite() => {
+                self.video_rate = VideoRate::Size((megabytes * 1e6) as u64);
+            }
+            _ => self.bail_bad_value(name, value)?,
+        }
+        Ok(())
+    }
+
+    fn set_background(&mut self, name: &str, value: &str) -> Result<(), FatalError> {
+        match Frame::parse_color(value) {
+            Some(color) => self.frame.background = color,
+            None => self.bail_bad_value(name, value)?,
+        }
+        Ok(())
+    }
+
+    /// A duration of slides, such as the minimum one.
+    fn parse_slide_seconds(&mut self, name: &str, value: &str) -> Result<Option<f32>, FatalError> {
+        match value.parse() {
+            Ok(seconds) if SlideSeconds::is_valid(seconds) => Ok(Some(seconds)),
+            _ => self.bail_bad_value(name, value).map(|()| None),
+        }
+    }
+
+    /// A duration of the end of the video, `0` for none.
+    fn parse_end_seconds(&mut self, name: &str, value: &str) -> Result<f32, FatalError> {
+        match value.parse() {
+            Ok(seconds) if Ending::is_valid(seconds) => Ok(seconds),
+            _ => self.bail_bad_value(name, value).map(|()| 0.0),
+        }
+    }
+
+    /// Parse an argument of the form `--name=value`.
+    fn parse_value_argument(&mut self, arg: &str) -> Result<(), FatalError> {
+        let (name, value) = match arg.find('=') {
+            Some(pos) => (&arg[..pos], &arg[pos+1..]),
+            None => return self.bail_unknown_argument(arg),
+        };
+
+        let limit = match name {
+            "--data-dir" => {
+                self.data_dir = Some(value.into());
+                return Ok(());
+            }
+            "--users" => {
+                self.users = Some(value.into());
+                return Ok(());
+            }
+            "--log-format" => {
+                match LogFormat::from_name(value) {
+                    Some(format) => self.log_format = format,
+                    None => self.bail_bad_value(name, value)?,
+                }
+                return Ok(());
+            }
+            "--log-level" => {
+                self.log_level = Some(value.into());
+                return Ok(());
+            }
+            "--listen" => {
+                self.listen = value.into();
+                return Ok(());
+            }
+            "--output" => {
+                self.output = value.into();
+                return Ok(());
+            }
+            "--preview" => return self.set_preview(name, value),
+            "--cue" => return self.set_cue(name, value),
+            "--sandbox" => return self.set_sandbox(name, value),
+            "--aspect" => return self.set_aspect(name, value),
+            "--orientation" => return self.set_orientation(name, value),
+            "--padding" => return self.set_padding(name, value),
+            "--background-color" => return self.set_background(name, value),
+            "--audio-codec" => return self.set_audio_codec(name, value),
+            "--audio-bitrate" => return self.set_audio_bitrate(name, value),
+            "--audio-channels" => return self.set_audio_channels(name, value),
+            "--video-bitrate" => return self.set_video_bitrate(name, value),
+            "--target-size" => return self.set_target_size(name, value),
+            "--min-slide-seconds" => {
+                self.slide_seconds.min = self.parse_slide_seconds(name, value)?;
+                return Ok(());
+            }
+            "--default-slide-seconds" => {
+                self.slide_seconds.default = self.parse_slide_seconds(name, value)?;
+                return Ok(());
+            }
+            "--end-hold" => {
+                self.ending.hold = self.parse_end_seconds(name, value)?;
+                return Ok(());
+            }
+            "--fade-out" => {
+                self.ending.fade = self.parse_end_seconds(name, value)?;
+                return Ok(());
+            }
+            "--seed" => {
+                match value.parse() {
+                    Ok(seed) => self.seed = Some(seed),
+                    Err(_) => self.bail_bad_value(name, value)?,
+                }
+                return Ok(());
+            }
+            "--render" => {
+                self.render = Some(value.into());
+                return Ok(());
+            }
+            "--audio" => {
+                self.audio = Some(value.into());
+                return Ok(());
+            }
+            "--recording" => {
+                self.recording = Some(value.into());
+                return Ok(());
+            }
+            "--markers" => {
+                match Split::from_markers(value) {
+                    Some(split) => self.split = split,
+                    None => self.bail_bad_value(name, value)?,
+                }
+                return Ok(());
+            }
+            "--keep-intermediates" => {
+                self.keep_intermediates = Some(value.into());
+                return Ok(());
+            }
+            "--timeline" => {
+                self.timeline = Some(value.into());
+                return Ok(());
+            }
+            "--render-spec" => {
+                self.render_spec = Some(value.into());
+                return Ok(());
+            }
+            "--drop-folder" => {
+                self.drop_folder = Some(value.into());
+                return Ok(());
+            }
+            "--drop-output" => {
+                self.drop_output = Some(value.into());
+                return Ok(());
+            }
+            "--slide-filter" => {
+                match Filter::from_name(value) {
+                    Some(filter) => self.filter = filter,
+                    None => self.bail_bad_value(name, value)?,
+                }
+                return Ok(());
+            }
+            "--watch" => {
+                match Watch::from_name(value) {
+                    Some(watch) => self.watch = Some(watch),
+                    None => self.bail_bad_value(name, value)?,
+                }
+                return Ok(());
+            }
+            "--ffmpeg" => {
+                self.tools.ffmpeg = Some(value.into());
+                return Ok(());
+            }
+            "--ffprobe" => {
+                self.tools.ffprobe = Some(value.into());
+                return Ok(());
+            }
+            "--magick" => {
+                self.tools.magick = Some(value.into());
+                return Ok(());
+            }
+            "--curl" => {
+                self.tools.curl = Some(value.into());
+                return Ok(());
+            }
+            "--upload-endpoint" => {
+                self.upload.endpoint = Some(value.into());
+                return Ok(());
+            }
+            "--upload-bucket" => {
+                self.upload.bucket = Some(value.into());
+                return Ok(());
+            }
+            "--upload-region" => {
+                self.upload.region = Some(value.into());
+                return Ok(());
+            }
+            "--upload-prefix" => {
+                self.upload.prefix = Some(value.into());
+                return Ok(());
+            }
+            "--icc-profile" => {
+                self.icc_profile = Some(value.into());
+                return Ok(());
+            }
+            "--max-upload-size" => &self.limits.upload_size,
+            "--max-pdf-size" => &self.limits.pdf_size,
+            "--max-pages" => &self.limits.page_count,
+            "--max-duration" => &self.limits.video_duration,
+            "--max-renders" => &self.limits.renders.capacity,
+            "--max-explodes" => &self.limits.explodes.capacity,
+            "--max-cache-size" => &self.limits.cache_size,
+            _ => return self.bail_unknown_argument(arg),
+        };
+
+        match value.parse::<u64>() {
+            Ok(value) => limit.store(value, Ordering::Relaxed),
+            Err(_) => self.bail_bad_value(name, value)?,
+        }
+
+        Ok(())
+    }
+
     // TODO: want to use `Result<!, FatalError>` here.
     fn bail_unknown_argument(&mut self, arg: &str) -> Result<(), FatalError> {
         writeln!(&mut self.stderr, "Unknown argument `{}`", arg)?;
@@ -127,6 +902,11 @@ impl Configuration {
         std::process::exit(1);
     }
 
+    fn bail_bad_value(&mut self, name: &str, value: &str) -> Result<(), FatalError> {
+        writeln!(&mut self.stderr, "Invalid value `{}` for argument `{}`", value, name)?;
+        std::process::exit(1);
+    }
+
     fn bail_help(&mut self) -> Result<(), FatalError> {
         self.print_help()?;
         std::process::exit(2);
@@ -149,6 +929,116 @@ impl Configuration {
         writeln!(&mut self.stderr, "")?;
         writeln!(&mut self.stderr, "Options:\n\
             \t-verbose  \tPrint debug information\n\
+            \t--web     \tServe the web interface, also from a terminal\n\
+            \t--tui     \tRun the terminal interface, with `--web` beside it\n\
+            \t--render=PDF\n\
+            \t          \tConvert the pdf to a video at `--output`, without interface\n\
+            \t--audio=PATH\n\
+            \t          \tZip archive or directory with the narration of `--render`,\n\
+            \t          \tfiles named by page such as `slide-01.wav`\n\
+            \t--recording=PATH\n\
+            \t          \tOne recording narrating all slides of `--render`, instead of `--audio`,\n\
+            \t          \tsplit at the longest pauses\n\
+            \t--markers=TIME,...\n\
+            \t          \tSplit `--recording` where each following slide begins instead,\n\
+            \t          \tin seconds or as minutes:seconds\n\
+            \t--slide-filter=none|dark|negate\n\
+            \t          \tChange the colors of the slides of `--render`, `dark` keeps the hue\n\
+            \t--json    \tReport the progress of `--render` as lines of json on stdout\n\
+            \t--dry-run \tPrint the commands of `--render` instead of running them\n\
+            \t--keep-intermediates=DIR\n\
+            \t          \tCopy the slides, segments and lists given to ffmpeg by `--render`\n\
+            \t          \tinto DIR\n\
+            \t--upload-endpoint=URL, --upload-bucket=NAME\n\
+            \t          \tUpload each video to this S3 compatible storage, with the\n\
+            \t          \tcredentials in AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY\n\
+            \t--upload-region=NAME, --upload-prefix=PREFIX\n\
+            \t          \tSign the upload for this region, and name the video PREFIX<project>.mp4\n\
+            \t--timeline=PATH\n\
+            \t          \tWrite the slides and audio of `--render` as an OpenTimelineIO\n\
+            \t          \ttimeline, which video editors can import\n\
+            \t--render-spec=FILE\n\
+            \t          \tConvert as described by a json file: the pdf, its narration, the\n\
+            \t          \tsettings of each slide and the outputs, see the Readme\n\
+            \t--drop-folder=DIR\n\
+            \t          \tRender each `deck.pdf` put into DIR, narrated by `deck/slide-01.wav`\n\
+            \t          \tand so on, to `deck.mp4` until interrupted\n\
+            \t--drop-output=DIR\n\
+            \t          \tWrite the videos of `--drop-folder` here instead, with its state\n\
+            \t--seed=NUMBER\n\
+            \t          \tName files the same in each run and encode bit exact, for tests\n\
+            \t--config=PATH\n\
+            \t          \tRead settings from this file instead of\n\
+            \t          \t~/.config/vid-from-pdf/config.toml, flags take precedence\n\
+            \t--listen=ADDR\n\
+            \t          \tAddress of the web server, by default localhost:8051\n\
+            \t--output=PATH\n\
+            \t          \tSuggested path when saving from the terminal interface\n\
+            \t--preview=auto|kitty|iterm|sixel|blocks|none\n\
+            \t          \tHow the terminal interface shows slides\n\
+            \t--cue=none|beep|countdown\n\
+            \t          \tPlay this before the audio of a slide in the terminal interface,\n\
+            \t          \tto practice starting the narration, never part of the video\n\
+            \t--watch, --watch=pages|render\n\
+            \t          \tConvert the pdf again when it changes, and generate the video\n\
+            \t--ffmpeg=PATH, --ffprobe=PATH, --magick=PATH, --curl=PATH\n\
+            \t          \tUse these tools instead of searching them\n\
+            \t--sandbox=none|bwrap|firejail\n\
+            \t          \tRun the tools reading uploaded files in this sandbox. Not mupdf,\n\
+            \t          \twhich parses pdfs within this process and is not confined\n\
+            \t--aspect=auto|16:9|4:3\n\
+            \t          \tShape of the video, by default the closest to the first slide\n\
+            \t--orientation=fit|rotate\n\
+            \t          \tFit pages in portrait among landscape ones upright, or turn them\n\
+            \t--padding=PIXELS\n\
+            \t          \tSpace around each slide in the video\n\
+            \t--background-color=#RRGGBB\n\
+            \t          \tColor of the padding and of the bars beside slides of another shape\n\
+            \t--min-slide-seconds=SECONDS\n\
+            \t          \tShow slides with shorter audio this long, followed by silence\n\
+            \t--default-slide-seconds=SECONDS\n\
+            \t          \tShow slides without audio this long, instead of 10 seconds\n\
+            \t--end-hold=SECONDS\n\
+            \t          \tShow the last slide this much longer, after its audio\n\
+            \t--fade-out=SECONDS\n\
+            \t          \tFade the audio out to silence at the end of the video\n\
+            \t--audio-codec=aac|opus|mp3\n\
+            \t          \tCodec of the audio in the video, if ffmpeg has its encoder\n\
+            \t--audio-bitrate=KBPS|default\n\
+            \t          \tBitrate of the audio in kbit/s, from 8 to 512\n\
+            \t--audio-channels=mono|stereo\n\
+            \t          \tChannels of the audio, mono for narration recorded with one microphone\n\
+            \t--video-bitrate=KBPS|default\n\
+            \t          \tBitrate of the video in kbit/s, encoded in two passes without\n\
+            \t          \thardware acceleration\n\
+            \t--target-size=MB\n\
+            \t          \tChoose the bitrate such that the video fits in this many megabytes,\n\
+            \t          \tsuch as the upload limit of a learning platform\n\
+            \t--icc-profile=PATH\n\
+            \t          \tConvert the colors of slides with this profile, after converting to sRGB\n\
+            \t--dedup   \tStore files with identical content only once\n\
+            \t--data-dir=PATH\n\
+            \t          \tKeep projects and sessions in this directory across restarts\n\
+            \t--users=PATH\n\
+            \t          \tRequire a login from the `name:password` lines of this file\n\
+            \t--max-upload-size=BYTES\n\
+            \t          \tLargest accepted upload\n\
+            \t--max-pdf-size=BYTES\n\
+            \t          \tLargest accepted pdf\n\
+            \t--max-pages=COUNT\n\
+            \t          \tMost pages accepted in a pdf\n\
+            \t--max-duration=SECONDS\n\
+            \t          \tLongest video that will be rendered\n\
+            \t--max-renders=COUNT\n\
+            \t          \tVideos encoded at once, others wait, 0 for no limit\n\
+            \t--max-explodes=COUNT\n\
+            \t          \tPdfs converted at once, others wait, 0 for no limit\n\
+            \t--max-cache-size=BYTES\n\
+            \t          \tConverted pages kept for converting the same pdf again, 0 for none\n\
+            \t--log-format=text|json\n\
+            \t          \tFormat of the log on stderr\n\
+            \t--log-level=FILTER\n\
+            \t          \tLog messages to show, e.g. `debug` or `vid_from_pdf=trace`\n\
             \t-h\n\
             \t-help\n\
             \t--help    \tPrint this help"
@@ -183,8 +1073,16 @@ impl<'dis> ErrorReporter<'dis> {
     }
 }
 
-pub fn require_tool(tool: &'static str) -> Result<CanonicalPath, RequiredToolError> {
-    match CanonicalPath::new(tool) {
+/// Find a tool by name, or check the configured path.
+pub fn require_tool(tool: &'static str, path: Option<&Path>)
+    -> Result<CanonicalPath, RequiredToolError>
+{
+    let found = match path {
+        Some(path) => CanonicalPath::new(path),
+        None => CanonicalPath::new(tool),
+    };
+
+    match found {
         Ok(path) => Ok(path),
         Err(error) => Err(RequiredToolError {
             tool,
@@ -0,0 +1,140 @@
+//! Confine the tools that parse untrusted input, such as uploaded pdfs and audio.
+//!
+//! Tools run with a read-only view of the system and without network. They may only write to the
+//! directories of projects. The sandbox is another tool, `bwrap` or `firejail`, which runs the
+//! actual command.
+//!
+//! Only separate programs are confined. When built with `mupdf`, pdfs are parsed within our own
+//! process, including the page count of `explode::validate`, and are not confined at all.
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::RwLock;
+
+use which::CanonicalPath;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SandboxKind {
+    /// Bubblewrap, with new namespaces for everything.
+    Bwrap,
+    Firejail,
+}
+
+#[derive(Clone, Debug)]
+pub struct Sandbox {
+    kind: SandboxKind,
+    exe: CanonicalPath,
+    /// Where the tools may write, all absolute.
+    writable: Vec<PathBuf>,
+}
+
+/// The sandbox used for all tools, if any.
+static ACTIVE: RwLock<Option<Sandbox>> = RwLock::new(None);
+
+impl SandboxKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bwrap" => Some(SandboxKind::Bwrap),
+            "firejail" => Some(SandboxKind::Firejail),
+            _ => None,
+        }
+    }
+
+    /// The name of the program.
+    pub fn name(self) -> &'static str {
+        match self {
+            SandboxKind::Bwrap => "bwrap",
+            SandboxKind::Firejail => "firejail",
+        }
+    }
+}
+
+impl Sandbox {
+    pub fn new(kind: SandboxKind, exe: CanonicalPath, writable: Vec<PathBuf>) -> Self {
+        Sandbox { kind, exe, writable }
+    }
+
+    pub fn kind(&self) -> SandboxKind {
+        self.kind
+    }
+
+    pub fn path(&self) -> &Path {
+        self.exe.as_path()
+    }
+
+    /// Run all tools in this sandbox from now on.
+    pub fn install(self) {
+        *ACTIVE.write().unwrap_or_else(|err| err.into_inner()) = Some(self);
+    }
+
+    /// The active sandbox.
+    pub fn active() -> Option<Self> {
+        ACTIVE.read().unwrap_or_else(|err| err.into_inner()).clone()
+    }
+
+    /// The program and arguments that run a command inside the sandbox, when followed by it.
+    pub fn prefix(&self, cwd: Option<&Path>) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec![self.exe.as_path().into()];
+        match self.kind {
+            SandboxKind::Bwrap => {
+                args.extend(["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc"]
+                    .iter()
+                    .map(OsString::from));
+                // Bound after the new `/tmp`, such that projects below it stay visible.
+                args.extend(["--tmpfs", "/tmp"].iter().map(OsString::from));
+                for dir in &self.writable {
+                    args.push("--bind".into());
+                    args.push(dir.into());
+                    args.push(dir.into());
+                }
+                if let Some(cwd) = cwd {
+                    args.push("--chdir".into());
+                    args.push(cwd.into());
+                }
+                args.extend(["--unshare-all", "--die-with-parent", "--new-session"]
+                    .iter()
+                    .map(OsString::from));
+            }
+            SandboxKind::Firejail => {
+                args.extend(["--quiet", "--noprofile", "--net=none", "--private-dev", "--read-only=/"]
+                    .iter()
+                    .map(OsString::from));
+                for dir in &self.writable {
+                    let mut arg = OsString::from("--read-write=");
+                    arg.push(dir);
+                    args.push(arg);
+                }
+            }
+        }
+
+        args.push("--".into());
+        args
+    }
+
+    /// The same command, running inside the sandbox.
+    pub fn wrap(&self, command: &Command) -> Command {
+        let cwd = command.get_current_dir();
+        // The sandbox may resolve a relative directory differently.
+        let absolute = cwd.and_then(|cwd| cwd.canonicalize().ok());
+        let mut prefix = self.prefix(absolute.as_deref().or(cwd)).into_iter();
+        let mut wrapped = Command::new(prefix.next().expect("The sandbox program"));
+        wrapped
+            .args(prefix)
+            .arg(command.get_program())
+            .args(command.get_args());
+
+        if let Some(cwd) = cwd {
+            wrapped.current_dir(cwd);
+        }
+
+        // The sandboxes pass on the environment.
+        for (key, value) in command.get_envs() {
+            match value {
+                Some(value) => wrapped.env(key, value),
+                None => wrapped.env_remove(key),
+            };
+        }
+
+        wrapped
+    }
+}
@@ -0,0 +1,65 @@
+//! Sessions that survive a restart of the server.
+use std::{fs, io, path::PathBuf};
+use async_session::{async_trait, Session, SessionStore};
+
+/// Stores each session as a json file in a directory.
+#[derive(Clone, Debug)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(dir: PathBuf) -> Result<Self, io::Error> {
+        fs::create_dir_all(&dir)?;
+        Ok(FileStore { dir })
+    }
+
+    fn path_of(&self, id: &str) -> PathBuf {
+        // Ids are standard base64 and may contain a path separator.
+        let name: String = id
+            .chars()
+            .map(|ch| match ch {
+                '/' => '_',
+                '+' => '-',
+                other => other,
+            })
+            .collect();
+        self.dir.join(name)
+    }
+}
+
+#[async_trait]
+impl SessionStore for FileStore {
+    async fn load_session(&self, cookie_value: String) -> async_session::Result<Option<Session>> {
+        let id = Session::id_from_cookie_value(&cookie_value)?;
+        let data = match fs::read(self.path_of(&id)) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let session: Session = serde_json::from_slice(&data)?;
+        Ok(session.validate())
+    }
+
+    async fn store_session(&self, session: Session) -> async_session::Result<Option<String>> {
+        let data = serde_json::to_vec(&session)?;
+        fs::write(self.path_of(session.id()), data)?;
+        session.reset_data_changed();
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(&self, session: Session) -> async_session::Result {
+        match fs::remove_file(self.path_of(session.id())) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn clear_store(&self) -> async_session::Result {
+        for entry in fs::read_dir(&self.dir)? {
+            fs::remove_file(entry?.path())?;
+        }
+        Ok(())
+    }
+}
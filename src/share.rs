@@ -0,0 +1,102 @@
+//! Read-only links to the rendered video of a project.
+//!
+//! A link names the project and its expiry and is signed with a key of the server, so nothing
+//! needs to be stored for it. Consequently a link can not be revoked before it expires, except by
+//! changing the key or deleting the project.
+use std::convert::TryInto;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+use crate::sink::Identifier;
+
+/// Signs and checks share links.
+pub struct ShareKey {
+    key: Vec<u8>,
+}
+
+/// The project a link grants access to.
+pub struct Shared {
+    pub project_id: Identifier,
+    /// The user owning the project, if the server has users.
+    pub user: Option<String>,
+}
+
+impl ShareKey {
+    pub const DEFAULT_VALIDITY: Duration = Duration::from_secs(7*24*60*60);
+    pub const MAX_VALIDITY: Duration = Duration::from_secs(90*24*60*60);
+
+    /// Separates our signatures from other uses of the same key, such as session cookies.
+    const CONTEXT: &'static [u8] = b"vid-from-pdf share\0";
+
+    pub fn new(key: &[u8]) -> Self {
+        ShareKey { key: key.to_owned() }
+    }
+
+    /// Create a link valid for the given duration, capped at `MAX_VALIDITY`.
+    ///
+    /// Returns the encoded link and the time at which it expires in seconds since the epoch.
+    pub fn mint(&self, shared: &Shared, valid_for: Duration) -> (String, u64) {
+        self.mint_at(shared, SystemTime::now(), valid_for)
+    }
+
+    /// Create a link as `mint` does, valid from `now` on.
+    pub fn mint_at(&self, shared: &Shared, now: SystemTime, valid_for: Duration) -> (String, u64) {
+        let valid_for = valid_for.min(Self::MAX_VALIDITY);
+        let expires = (now + valid_for)
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+
+        let mut payload = expires.to_be_bytes().to_vec();
+        payload.extend_from_slice(&shared.project_id);
+        if let Some(user) = &shared.user {
+            payload.extend_from_slice(user.as_bytes());
+        }
+
+        let signature = self.mac(&payload).finalize().into_bytes();
+        let link = format!(
+            "{}.{}",
+            base64::encode_config(&payload, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(&signature, base64::URL_SAFE_NO_PAD),
+        );
+
+        (link, expires)
+    }
+
+    /// Check the signature and expiry of a link.
+    pub fn verify(&self, link: &str) -> Option<Shared> {
+        let dot = link.find('.')?;
+        let payload = base64::decode_config(&link[..dot], base64::URL_SAFE_NO_PAD).ok()?;
+        let signature = base64::decode_config(&link[dot+1..], base64::URL_SAFE_NO_PAD).ok()?;
+        self.mac(&payload).verify(&signature).ok()?;
+
+        const HEADER: usize = 8 + std::mem::size_of::<Identifier>();
+        if payload.len() < HEADER {
+            return None;
+        }
+
+        let expires = u64::from_be_bytes(payload[..8].try_into().unwrap());
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+        if expires < now {
+            return None;
+        }
+
+        let mut project_id = Identifier::default();
+        project_id.copy_from_slice(&payload[8..HEADER]);
+        let user = match &payload[HEADER..] {
+            [] => None,
+            name => Some(String::from_utf8(name.to_vec()).ok()?),
+        };
+
+        Some(Shared { project_id, user })
+    }
+
+    fn mac(&self, payload: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_varkey(&self.key)
+            .expect("HMAC can take key of any size");
+        mac.update(Self::CONTEXT);
+        mac.update(payload);
+        mac
+    }
+}
@@ -4,9 +4,13 @@
 /// requesting that particular paths may be kept freed for us. Provide a collector for collecting
 /// output as files or in memory and transparently paged?
 use std::{fs, io, path::Path, path::PathBuf};
-use rand::{rngs::ThreadRng, Rng as _};
+use std::io::Write as _;
+use futures_lite::{AsyncRead, AsyncReadExt as _};
+use rand::{rngs::StdRng, Rng as _, SeedableRng as _};
+use sha2::{Digest as _, Sha256};
 
 use crate::FatalError;
+use crate::cache::RenderCache;
 
 /// See module description.
 ///
@@ -14,14 +18,32 @@ use crate::FatalError;
 /// TODO: suffix control for store_to_file.
 pub struct Sink {
     tempdir: PathBuf,
-    trng: ThreadRng,
+    rng: StdRng,
+    /// The seed of `rng`, if paths should be the same in each run.
+    seed: Option<u64>,
     /// A temporary storage for outputs of intermediate steps.
     imported: Vec<PathBuf>,
+    /// Where stored files are deduplicated, if enabled.
+    content: Option<ContentStore>,
+    /// Pages converted earlier, if enabled.
+    cache: Option<RenderCache>,
 }
 
 #[derive(Clone)]
 pub struct SyncSink {
     path: PathBuf,
+    content: Option<ContentStore>,
+    cache: Option<RenderCache>,
+    seed: Option<u64>,
+}
+
+/// Files stored under the hash of their contents.
+///
+/// Files stored into a sink are hard links to an entry of this store. The link count of an entry
+/// is then its reference count, and entries only linked by the store itself are garbage.
+#[derive(Clone)]
+pub struct ContentStore {
+    path: PathBuf,
 }
 
 /// A path and its unique identifier.
@@ -53,10 +75,6 @@ pub struct FileSource {
     path: PathBuf,
 }
 
-pub struct BufSource<'lt> {
-    from: &'lt mut dyn io::BufRead,
-}
-
 impl Sink {
     pub fn new(path: PathBuf) -> Result<Self, FatalError> {
         if {
@@ -71,24 +89,85 @@ impl Sink {
 
         Ok(Sink {
             tempdir: path,
-            trng: rand::thread_rng(),
+            rng: StdRng::from_entropy(),
+            seed: None,
             imported: vec![],
+            content: None,
+            cache: None,
         })
     }
 
+    /// Open a directory within this sink, sharing the content store, cache and seed.
+    pub fn nested(&self, path: PathBuf) -> Result<Self, FatalError> {
+        let mut sink = Sink::new(path)?;
+        sink.content = self.content.clone();
+        sink.cache = self.cache.clone();
+        if let Some(seed) = self.seed {
+            sink.with_seed(seed);
+        }
+        Ok(sink)
+    }
+
+    /// Choose paths deterministically, such that repeated runs on the same input are comparable.
+    ///
+    /// Makes the names of projects predictable, only use this for testing.
+    pub fn with_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.seed = Some(seed);
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Deduplicate all files stored from now on.
+    pub fn with_content_store(&mut self, store: ContentStore) {
+        self.content = Some(store);
+    }
+
+    pub fn content_store(&self) -> Option<&ContentStore> {
+        self.content.as_ref()
+    }
+
+    /// Keep converted pages, for converting the same pdf again.
+    pub fn with_render_cache(&mut self, cache: RenderCache) {
+        self.cache = Some(cache);
+    }
+
+    pub fn render_cache(&self) -> Option<&RenderCache> {
+        self.cache.as_ref()
+    }
+
     pub fn path_of(&self, id: Identifier) -> PathBuf {
-        const SRC: &'static str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-_";
-        assert_eq!(SRC.len(), 64);
+        assert_eq!(Self::PATH_CHARS.len(), 64);
 
         let mut path = String::new();
         for &b in &id {
-            let ch = SRC.chars().nth(usize::from(b & 63)).unwrap();
+            let ch = Self::PATH_CHARS.chars().nth(usize::from(b & 63)).unwrap();
             path.push(ch);
         }
 
         self.tempdir.join(&path)
     }
 
+    /// The identifier of a file name created by `path_of`.
+    ///
+    /// Only the bits used for the name are recovered, which is enough to find the path again.
+    pub fn identifier_of(name: &str) -> Option<Identifier> {
+        let mut id = Identifier::default();
+        if name.len() != id.len() {
+            return None;
+        }
+
+        for (b, ch) in id.iter_mut().zip(name.chars()) {
+            *b = Self::PATH_CHARS.find(ch)? as u8;
+        }
+
+        Some(id)
+    }
+
+    const PATH_CHARS: &'static str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-_";
+
     pub fn unique_path(&mut self) -> Result<UniquePath, FatalError> {
         let (path, identifier) = self.random_path_in();
 
@@ -135,10 +214,74 @@ impl Sink {
             .create_new(true)
             .write(true)
             .open(&path)?;
-        io::copy(from, &mut file)?;
+
+        match &self.content {
+            None => { io::copy(from, &mut file)?; },
+            Some(store) => store.deduplicate(from, &mut file, &path)?,
+        }
+
         Ok(path)
     }
 
+    /// Store data from an asynchronous reader, such as a request body.
+    ///
+    /// Returns `None` if the data exceeded `limit` bytes. Nothing is kept in that case or on
+    /// errors.
+    pub async fn store_from_reader(
+        &mut self,
+        mut from: impl AsyncRead + Unpin,
+        limit: u64,
+    ) -> Result<Option<PathBuf>, io::Error> {
+        let (path, _) = self.random_path_in();
+        let mut file = fs::OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(&path)?;
+
+        let within_limit = async {
+            let mut hasher = Sha256::new();
+            let mut buffer = vec![0; 1 << 16];
+            let mut total = 0u64;
+            loop {
+                let len = from.read(&mut buffer).await?;
+                if len == 0 {
+                    break;
+                }
+
+                total += len as u64;
+                if total > limit {
+                    return Ok(false);
+                }
+
+                let data = &buffer[..len];
+                if self.content.is_some() {
+                    hasher.update(data);
+                }
+                file.write_all(data)?;
+            }
+
+            if let Some(store) = &self.content {
+                store.link(hasher, &path)?;
+            }
+
+            Ok::<_, io::Error>(true)
+        }.await;
+
+        drop(file);
+        match within_limit {
+            Ok(true) => Ok(Some(path)),
+            Ok(false) => {
+                fs::remove_file(&path)?;
+                Ok(None)
+            }
+            // Do not leave a partial upload behind, the error is what matters.
+            Err(err) => {
+                let _ = fs::remove_file(&path);
+                Err(err)
+            }
+        }
+    }
+
     pub fn work_dir(&self) -> &Path {
         &self.tempdir
     }
@@ -151,20 +294,38 @@ impl Sink {
         self.imported.drain(..)
     }
 
+    /// The number of imported paths, without taking them.
+    pub fn imported_len(&self) -> usize {
+        self.imported.len()
+    }
+
     fn random_path_in(&mut self) -> (PathBuf, Identifier) {
-        let mut id = [0u8; 16];
-        self.trng.fill(&mut id);
-        (self.path_of(id), id)
+        loop {
+            let mut id = [0u8; 16];
+            self.rng.fill(&mut id);
+            let path = self.path_of(id);
+            // A seeded sequence starts over each time the directory is opened.
+            if self.seed.is_none() || !path.exists() {
+                return (path, id);
+            }
+        }
     }
 }
 
 impl SyncSink {
     pub fn as_sink(&self) -> Sink {
-        Sink {
+        let mut sink = Sink {
             tempdir: self.path.clone(),
-            trng: rand::thread_rng(),
+            rng: StdRng::from_entropy(),
+            seed: None,
             imported: vec![],
+            content: self.content.clone(),
+            cache: self.cache.clone(),
+        };
+        if let Some(seed) = self.seed {
+            sink.with_seed(seed);
         }
+        sink
     }
 
     pub fn work_dir(&self) -> &Path {
@@ -174,7 +335,97 @@ impl SyncSink {
 
 impl From<Sink> for SyncSink {
     fn from(sink: Sink) -> SyncSink {
-        SyncSink { path: sink.tempdir }
+        SyncSink { path: sink.tempdir, content: sink.content, cache: sink.cache, seed: sink.seed }
+    }
+}
+
+impl ContentStore {
+    /// Use a directory as content store, creating it if necessary.
+    pub fn new(path: PathBuf) -> Result<Self, io::Error> {
+        fs::create_dir_all(&path)?;
+        Ok(ContentStore { path })
+    }
+
+    /// Write all data into `file` at `path`, then replace it with the stored copy if any.
+    fn deduplicate(&self, from: &mut dyn io::BufRead, file: &mut fs::File, path: &Path)
+        -> Result<(), io::Error>
+    {
+        let mut hasher = Sha256::new();
+        loop {
+            let buf = from.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            hasher.update(buf);
+            file.write_all(buf)?;
+            let len = buf.len();
+            from.consume(len);
+        }
+
+        self.link(hasher, path)
+    }
+
+    /// Replace the file at `path` with a link to the stored copy of its content.
+    fn link(&self, hasher: Sha256, path: &Path) -> Result<(), io::Error> {
+        let mut name = String::new();
+        for byte in hasher.finalize() {
+            name.push_str(&format!("{:02x}", byte));
+        }
+
+        let content = self.path.join(name);
+        let mut shared = path.as_os_str().to_owned();
+        shared.push(".shared");
+        let shared = PathBuf::from(shared);
+
+        loop {
+            match fs::hard_link(path, &content) {
+                // We're the first with that content.
+                Ok(()) => return Ok(()),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {},
+                Err(err) => return Err(err),
+            }
+
+            // Keep our file until the stored copy replaces it, the copy may be collected meanwhile.
+            match fs::remove_file(&shared) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {},
+            }
+
+            match fs::hard_link(&content, &shared) {
+                Ok(()) => return fs::rename(&shared, path),
+                // Collected since we tried, so store ours instead.
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Remove all entries that are no longer referenced by any sink.
+    #[cfg(unix)]
+    pub fn collect_garbage(&self) -> Result<(), io::Error> {
+        use std::os::unix::fs::MetadataExt as _;
+        // Another collection may have been faster.
+        let gone = |err: &io::Error| err.kind() == io::ErrorKind::NotFound;
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            let meta = match entry.metadata() {
+                Err(err) if gone(&err) => continue,
+                meta => meta?,
+            };
+            if meta.nlink() <= 1 {
+                match fs::remove_file(entry.path()) {
+                    Err(err) if !gone(&err) => return Err(err),
+                    _ => {},
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Without link counts we can not tell if entries are unused, keep them.
+    #[cfg(not(unix))]
+    pub fn collect_garbage(&self) -> Result<(), io::Error> {
+        Ok(())
     }
 }
 
@@ -203,19 +454,3 @@ impl Source for FileSource {
         Some(&self.path)
     }
 }
-
-impl<'lt, T: io::BufRead> From<&'lt mut T> for BufSource<'lt> {
-    fn from(buf: &'lt mut T) -> Self {
-        BufSource { from: buf }
-    }
-}
-
-impl Source for BufSource<'_> {
-    fn as_buf_read(&mut self) -> &mut dyn io::BufRead {
-        self.from
-    }
-
-    fn as_path(&self) -> Option<&Path> {
-        None
-    }
-}
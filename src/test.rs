@@ -1,5 +1,5 @@
 use std::{fs, io, path};
-use crate::{app, project, resources, sink};
+use crate::{api, app, cache, conditional, dropfolder, explode, ffmpeg, headless, pipeline, project, queue, resources, share, sink, timeline, web};
 
 const PDF: &'static str = concat!(env!("CARGO_MANIFEST_DIR"), "/test-data/test.pdf");
 const WAV0: &'static str = concat!(env!("CARGO_MANIFEST_DIR"), "/test-data/espeak-0.wav");
@@ -26,7 +26,7 @@ fn assemble() {
             println!("{:?}", entry);
         }
 
-        match project::Project::load(&app, project.project_id) {
+        match project::Project::load(&app, &app.sink.as_sink(), project.project_id) {
             Ok(Some(_)) => {}
             Ok(None) => panic!("Unexpectedly didn't find the project in {}", project.dir.work_dir().display()),
             Err(err) => panic!("Unexpectedly didn't load the project {:?}", err),
@@ -52,3 +52,598 @@ fn assemble() {
         .expect("One output file");
     assert!(output.exists());
 }
+
+#[test]
+fn render_cache() {
+    let tempdir = tempfile::TempDir::new().expect("Temporary directory");
+    let cache = cache::RenderCache::new(tempdir.path().join(".cache"), 20)
+        .expect("Cache created");
+    let mut sink = sink::Sink::new(tempdir.path().to_owned())
+        .expect("Sink in temporary directory");
+
+    let page = tempdir.path().join("page.png");
+    fs::write(&page, b"sixteen bytes...").unwrap();
+    assert!(cache.get("page 0", &mut sink).is_none());
+    cache.put("page 0", &page).unwrap();
+
+    let cached = cache.get("page 0", &mut sink).expect("Page was cached");
+    assert_eq!(cached.extension().unwrap(), "png");
+    assert_eq!(fs::read(&cached).unwrap(), b"sixteen bytes...");
+
+    // Over the limit, the oldest entry goes.
+    cache.put("page 1", &page).unwrap();
+    cache.evict().unwrap();
+    let entries = fs::read_dir(tempdir.path().join(".cache")).unwrap().count();
+    assert_eq!(entries, 1);
+}
+
+#[test]
+fn deduplicate() {
+    let tempdir = tempfile::TempDir::new().expect("Temporary directory");
+    let store = sink::ContentStore::new(tempdir.path().join(".content"))
+        .expect("Content store created");
+    let mut sink = sink::Sink::new(tempdir.path().to_owned())
+        .expect("Sink in temporary directory");
+    sink.with_content_store(store);
+
+    let first = sink.store_to_file(&mut &b"same content"[..]).unwrap();
+    let second = sink.store_to_file(&mut &b"same content"[..]).unwrap();
+    let other = sink.store_to_file(&mut &b"other content"[..]).unwrap();
+    assert_ne!(first, second);
+    assert_eq!(fs::read(&second).unwrap(), b"same content");
+    assert_eq!(fs::read(&other).unwrap(), b"other content");
+    // The stored copy replaced the second file, nothing else remains.
+    assert!(!fs::read_dir(tempdir.path()).unwrap()
+        .any(|entry| entry.unwrap().path().extension().map_or(false, |ext| ext == "shared")));
+
+    let entries = || fs::read_dir(tempdir.path().join(".content")).unwrap().count();
+    assert_eq!(entries(), 2);
+
+    fs::remove_file(first).unwrap();
+    fs::remove_file(second).unwrap();
+    sink.content_store().unwrap().collect_garbage().unwrap();
+    assert_eq!(entries(), if cfg!(unix) { 1 } else { 2 });
+
+    // Collecting again finds nothing to do, storing again works as well.
+    sink.content_store().unwrap().collect_garbage().unwrap();
+    let again = sink.store_to_file(&mut &b"same content"[..]).unwrap();
+    assert_eq!(fs::read(&again).unwrap(), b"same content");
+}
+
+#[test]
+fn seeded_paths() {
+    let tempdir = tempfile::TempDir::new().expect("Temporary directory");
+    let seeded = || {
+        let mut sink = sink::Sink::new(tempdir.path().to_owned())
+            .expect("Sink in temporary directory");
+        sink.with_seed(7);
+        sink
+    };
+
+    let first = seeded().unique_mkdir().unwrap().path;
+    // The same sequence, skipping what the first run created.
+    let again = seeded().unique_path().unwrap().path;
+    assert_ne!(again, first);
+    assert_eq!(seeded().unique_path().unwrap().path, again);
+}
+
+#[test]
+fn share_link() {
+    let key = share::ShareKey::new(b"secret");
+    let shared = share::Shared {
+        project_id: [7; 16],
+        user: Some("alice".into()),
+    };
+
+    let (link, _) = key.mint(&shared, std::time::Duration::from_secs(60));
+    let verified = key.verify(&link).expect("Valid link");
+    assert_eq!(verified.project_id, shared.project_id);
+    assert_eq!(verified.user, shared.user);
+
+    assert!(share::ShareKey::new(b"other").verify(&link).is_none());
+    let minute = std::time::Duration::from_secs(60);
+    let earlier = std::time::SystemTime::now() - 2*minute;
+    let (expired, _) = key.mint_at(&shared, earlier, minute);
+    assert!(key.verify(&expired).is_none());
+}
+
+#[test]
+fn openapi() {
+    let document = api::openapi();
+    let paths = &document["paths"];
+    assert!(paths["/api/project/new"]["put"].is_object());
+    assert!(paths["/api/project/{project}/page/{num}"]["put"]["requestBody"].is_object());
+    assert!(paths["/project/new"].is_null(), "Session routes are not documented");
+}
+
+#[test]
+fn byte_range() {
+    use web::ByteRange;
+    assert_eq!(ByteRange::parse("bytes=0-99", 1000), ByteRange::Partial { start: 0, end: 99 });
+    assert_eq!(ByteRange::parse("bytes=500-", 1000), ByteRange::Partial { start: 500, end: 999 });
+    assert_eq!(ByteRange::parse("bytes=-100", 1000), ByteRange::Partial { start: 900, end: 999 });
+    assert_eq!(ByteRange::parse("bytes=900-2000", 1000), ByteRange::Partial { start: 900, end: 999 });
+    assert_eq!(ByteRange::parse("bytes=1000-", 1000), ByteRange::Unsatisfiable);
+    assert_eq!(ByteRange::parse("bytes=0-1,5-6", 1000), ByteRange::Full);
+    assert_eq!(ByteRange::parse("lines=0-1", 1000), ByteRange::Full);
+}
+
+#[test]
+fn pdf_header() {
+    assert!(explode::has_pdf_header(&fs::read(PDF).expect("Pdf file")));
+    assert!(explode::has_pdf_header(b"\xef\xbb\xbf%PDF-1.7"));
+    assert!(!explode::has_pdf_header(b"PK\x03\x04"));
+    assert!(!explode::has_pdf_header(b"%PD"));
+}
+
+#[test]
+fn ffmpeg_missing_codec() {
+    let failed = |stderr: &str| ffmpeg::FfmpegError {
+        args: vec!["-i".into(), "in.wav".into()],
+        exit: Some(1),
+        stderr_tail: stderr.into(),
+    };
+    let err = failed("Unknown encoder 'libx264'\nConversion failed!");
+    assert_eq!(err.missing_codec(), Some("Unknown encoder 'libx264'"));
+    assert!(failed("in.wav: Invalid data found when processing input").missing_codec().is_none());
+    assert_eq!(crate::FatalError::from(err).class(), crate::ErrorClass::Environment);
+
+    let err = failed("[AVFilterGraph @ 0x5581] No such filter: 'drawtext'\nError initializing complex filters.");
+    assert_eq!(err.missing_codec(), Some("[AVFilterGraph @ 0x5581] No such filter: 'drawtext'"));
+    assert_eq!(crate::FatalError::from(err).class(), crate::ErrorClass::Environment);
+}
+
+#[test]
+fn queue() {
+    use std::sync::{mpsc, Arc};
+    let queue = Arc::new(queue::Queue::new(1));
+    let first = queue.acquire(|_| panic!("Nothing is running yet"));
+
+    let (positions, waited) = mpsc::channel();
+    let second = std::thread::spawn({
+        let queue = queue.clone();
+        move || drop(queue.acquire(|position| positions.send(position).unwrap()))
+    });
+
+    assert_eq!(waited.recv().unwrap(), 1);
+    assert_eq!(queue.waiting(), 1);
+    drop(first);
+    second.join().unwrap();
+    assert_eq!(queue.waiting(), 0);
+}
+
+#[test]
+fn pair_pages() {
+    let (a, b, c, x) = (Some('a'), Some('b'), Some('c'), Some('x'));
+    // A page inserted, the others keep their pairs.
+    assert_eq!(project::pair_pages(&[a, b, c], &[a, x, b, c]), [Some(0), None, Some(1), Some(2)]);
+    // A page edited in place.
+    assert_eq!(project::pair_pages(&[a, b, c], &[a, x, c]), [Some(0), Some(1), Some(2)]);
+    // A page removed.
+    assert_eq!(project::pair_pages(&[a, b, c], &[a, c]), [Some(0), Some(2)]);
+    // Unreadable pages are only paired by position, if that page is not taken.
+    assert_eq!(project::pair_pages(&[None, b], &[b, None]), [Some(1), None]);
+}
+
+#[test]
+fn http_date() {
+    use std::time::{Duration, UNIX_EPOCH};
+    let time = UNIX_EPOCH + Duration::from_secs(784111777);
+    assert_eq!(conditional::fmt_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+    assert_eq!(conditional::parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(time));
+    let leap = UNIX_EPOCH + Duration::from_secs(951782400);
+    assert_eq!(conditional::fmt_http_date(leap), "Tue, 29 Feb 2000 00:00:00 GMT");
+}
+
+#[test]
+fn dark_filter() {
+    use image::{Rgba, RgbaImage};
+    let mut image = RgbaImage::from_fn(3, 1, |x, _| match x {
+        0 => Rgba([255, 255, 255, 255]),
+        1 => Rgba([255, 0, 0, 255]),
+        _ => Rgba([200, 220, 255, 128]),
+    });
+    project::Filter::Dark.apply(&mut image);
+    assert_eq!(image.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+    assert_eq!(image.get_pixel(1, 0), &Rgba([255, 0, 0, 255]));
+    // A light blue becomes a dark blue, of the same alpha.
+    assert_eq!(image.get_pixel(2, 0), &Rgba([0, 20, 55, 128]));
+}
+
+#[test]
+fn frame_color() {
+    use crate::app::Frame;
+    assert_eq!(Frame::parse_color("#1e1e1e"), Some(0x1e1e1e));
+    assert_eq!(Frame::parse_color("FFFFFF"), Some(0xffffff));
+    assert_eq!(Frame::parse_color("+12345"), None);
+    assert_eq!(Frame::parse_color("#fff"), None);
+}
+
+#[test]
+fn geometry() {
+    use crate::app::Geometry;
+    assert_eq!(Geometry::closest(16.0 / 9.0), Geometry::Wide);
+    assert_eq!(Geometry::closest(4.0 / 3.0), Geometry::Standard);
+    // A4 in portrait, which fits neither.
+    assert_eq!(Geometry::closest(0.707), Geometry::Standard);
+    assert!(!Geometry::Standard.fits(0.707));
+    assert!(Geometry::Wide.fits(1.75));
+}
+
+#[test]
+fn slide_seconds() {
+    use crate::app::SlideSeconds;
+    let configured = SlideSeconds { min: Some(2.0), default: Some(5.0) };
+    let project = SlideSeconds { min: Some(3.0), default: None };
+    let seconds = project.or(configured);
+    assert_eq!(seconds.min, Some(3.0));
+    assert_eq!(seconds.default, Some(5.0));
+    assert!(!SlideSeconds::is_valid(0.0));
+    assert!(!SlideSeconds::is_valid(f32::NAN));
+}
+
+#[test]
+fn recording_markers() {
+    use project::Split;
+    assert_eq!(Split::from_markers("75, 2:05.5"), Some(Split::Markers(vec![75.0, 125.5])));
+    assert_eq!(Split::from_markers("1:00,59"), None, "Markers must increase");
+    assert_eq!(Split::from_markers("0"), None);
+    assert_eq!(Split::from_markers("1,,2"), None);
+}
+
+#[test]
+fn speech_silence() {
+    use ffmpeg::Silences;
+    use project::Speech;
+    let silences = Silences { pauses: vec![(0.0, 0.5), (2.0, 3.0)], trailing: Some(9.0) };
+    let speech = Speech::new(10.0, &silences);
+    assert_eq!(speech, Speech { leading: 0.5, trailing: 1.0, spoken: 7.5 });
+
+    let empty = Silences { pauses: vec![], trailing: Some(0.0) };
+    assert_eq!(Speech::new(3.0, &empty).spoken, 0.0);
+
+    // Closed at the end of the stream, by ffmpeg 4.2 and later.
+    let closed = Silences { pauses: vec![(0.0, 0.5), (2.0, 3.0), (9.0, 9.98)], trailing: None };
+    assert_eq!(Speech::new(10.0, &closed), Speech { leading: 0.5, trailing: 1.0, spoken: 7.5 });
+}
+
+#[test]
+fn recording_pauses() {
+    use ffmpeg::Silences;
+    use project::Split;
+    // Silence at both ends is the longest, but separates no slides.
+    let silences = Silences {
+        pauses: vec![(0.0, 3.0), (10.0, 11.0), (20.0, 20.5), (30.0, 33.0)],
+        trailing: None,
+    };
+    assert_eq!(Split::cuts_at_pauses(&silences, 33.0, 2), [10.5, 20.25]);
+}
+
+#[test]
+fn trim_points() {
+    use project::Trim;
+    assert!(Trim::new(-1.0, None).is_none());
+    assert!(Trim::new(2.0, Some(2.0)).is_none());
+
+    let trim = Trim::new(1.0, Some(4.0)).unwrap();
+    assert_eq!(trim.length(10.0), 3.0);
+    assert_eq!(trim.length(2.5), 1.5);
+    assert_eq!(Trim::new(1.0, None).unwrap().length(10.0), 9.0);
+}
+
+#[test]
+fn filter_escape() {
+    assert_eq!(ffmpeg::escape_filter_value("Intro"), "Intro");
+    assert_eq!(ffmpeg::escape_filter_value("Part 2: Setup"), "Part 2\\\\: Setup");
+    assert_eq!(ffmpeg::escape_filter_value("it's, [1]"), "it\\\\\\'s\\, \\[1\\]");
+}
+
+#[test]
+fn repeat_meta() {
+    use project::Repeat;
+    assert_eq!(serde_json::from_str::<Repeat>(r#"{"times":3}"#).unwrap(), Repeat::Times(3));
+    assert_eq!(serde_json::from_str::<Repeat>(r#""loop""#).unwrap(), Repeat::Loop);
+}
+
+#[test]
+fn timeline_gaps() {
+    let clip = timeline::Clip {
+        visual: "/work/slide 1.png".into(),
+        audio: "/work/audio.wav".into(),
+        seconds: 5.0,
+        delay: 1.0,
+        audio_start: 0.5,
+        audible: 3.0,
+        tempo: 1.0,
+    };
+    let otio = timeline::otio("Talk", &[clip], 2);
+    let narration = &otio["tracks"]["children"][1]["children"];
+    let schemas: Vec<_> = narration
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| &item["OTIO_SCHEMA"])
+        .collect();
+    assert_eq!(schemas, ["Gap.1", "Clip.1", "Gap.1"]);
+    assert_eq!(narration[1]["source_range"]["start_time"]["value"], 1.0);
+
+    let slide = &otio["tracks"]["children"][0]["children"][0];
+    assert_eq!(slide["media_reference"]["target_url"], "file:///work/slide%201.png");
+}
+
+#[test]
+fn drop_folder_settles() {
+    let tempdir = tempfile::TempDir::new().expect("Temporary directory");
+    let (input, output) = (tempdir.path().join("in"), tempdir.path().join("out"));
+    fs::create_dir_all(input.join("talk")).unwrap();
+    fs::write(input.join("talk.pdf"), b"%PDF-1.4").unwrap();
+    fs::write(input.join("talk/slide-01.wav"), b"RIFF").unwrap();
+
+    let mut folder = dropfolder::DropFolder::new(&input, &output).unwrap();
+    assert!(folder.ready().unwrap().is_empty(), "Rendered before it settled");
+    let deck = folder.ready().unwrap().pop().expect("Settled deck");
+    assert_eq!(deck.name, "talk");
+    assert_eq!(deck.audio.as_deref(), Some(input.join("talk").as_path()));
+    folder.finish(deck, Ok(output.join("talk.mp4"))).unwrap();
+
+    // The state is kept, also by the next run.
+    let mut folder = dropfolder::DropFolder::new(&input, &output).unwrap();
+    assert!(folder.ready().unwrap().is_empty());
+    assert!(folder.ready().unwrap().is_empty());
+
+    fs::write(input.join("talk/slide-02.wav"), b"RIFF").unwrap();
+    assert!(folder.ready().unwrap().is_empty());
+    assert_eq!(folder.ready().unwrap().len(), 1);
+}
+
+#[test]
+fn render_spec_paths() {
+    let tempdir = tempfile::TempDir::new().expect("Temporary directory");
+    let path = tempdir.path().join("job.json");
+    fs::write(&path, r#"{
+        "pdf": "talk.pdf",
+        "output": "/videos/talk.mp4",
+        "slides": [{ "page": 2, "audio": "two.wav", "repeat": { "times": 2 } }]
+    }"#).unwrap();
+
+    let spec = headless::RenderSpec::read(&path).unwrap();
+    assert_eq!(spec.pdf, tempdir.path().join("talk.pdf"));
+    assert_eq!(spec.output, path::Path::new("/videos/talk.mp4"));
+    assert_eq!(spec.slides[0].audio.as_deref(), Some(tempdir.path().join("two.wav").as_path()));
+    assert_eq!(spec.slides[0].repeat, Some(project::Repeat::Times(2)));
+
+    fs::write(&path, r#"{ "pdf": "talk.pdf", "output": "talk.mp4", "speed": 2 }"#).unwrap();
+    assert!(headless::RenderSpec::read(&path).is_err(), "Unknown keys are mistakes");
+}
+
+#[test]
+fn audio_encoding_args() {
+    let default = ffmpeg::AudioEncoding::default();
+    assert_eq!(default.args(), ["-c:a", "aac", "-ac", "2"]);
+
+    let opus = ffmpeg::AudioEncoding {
+        codec: ffmpeg::AudioCodec::Opus,
+        bitrate: Some(64),
+        channels: ffmpeg::Channels::Mono,
+    };
+    assert_eq!(opus.args(), ["-c:a", "libopus", "-strict", "experimental", "-b:a", "64k", "-ac", "1"]);
+}
+
+#[test]
+fn video_rate_fits_size() {
+    let audio = ffmpeg::AudioEncoding::default();
+    assert!(ffmpeg::VideoRate::Quality.args(60.0, audio).is_empty());
+    assert_eq!(ffmpeg::VideoRate::Bitrate(800).args(60.0, audio), ["-b:v", "800k"]);
+    // 784 kbit/s after the container, of which the audio takes 128.
+    assert_eq!(ffmpeg::VideoRate::Size(100_000_000).args(1000.0, audio), ["-b:v", "656k"]);
+    assert_eq!(ffmpeg::VideoRate::Size(1000).args(1000.0, audio), ["-b:v", "50k"]);
+}
+
+#[test]
+fn frames_do_not_drift() {
+    let mut assembly = ffmpeg::Assembly::new();
+    // A third of a second is not a whole number of frames, each slide rounds differently.
+    let frames: u64 = (0..900).map(|_| assembly.count_frames(1.0 / 3.0 + 1.0, 1)).sum();
+    assert_eq!(frames, 2400);
+
+    let mut assembly = ffmpeg::Assembly::new();
+    let first = assembly.count_frames(1.25, 3);
+    let second = assembly.count_frames(1.25, 1);
+    // The copies round alike, the slide after them makes up for it.
+    assert_eq!((first, second), (3, 1));
+    assert_eq!(first * 3 + second, 10);
+
+    // Even a very short slide is shown.
+    assert_eq!(ffmpeg::Assembly::new().count_frames(0.01, 1), 1);
+}
+
+#[test]
+fn segment_cache_key() {
+    let segment = ffmpeg::Segment {
+        visual: PDF.into(),
+        audio: WAV0.into(),
+        tempo: 1.0,
+        frames: 4,
+        copies: 1,
+        ..Default::default()
+    };
+    let name = segment.name("settings").unwrap();
+    assert!(name.starts_with("segment-") && name.ends_with(".mp4"));
+    assert_eq!(segment.name("settings").unwrap(), name);
+
+    // Copies are played again from the list, the encoded segment is the same.
+    let copied = ffmpeg::Segment { copies: 3, continued: true, ..segment.clone() };
+    assert_eq!(copied.name("settings").unwrap(), name);
+
+    let changed = [
+        ffmpeg::Segment { audio: WAV1.into(), ..segment.clone() },
+        ffmpeg::Segment { offset_ms: 500, ..segment.clone() },
+        ffmpeg::Segment { tempo: 1.5, ..segment.clone() },
+        ffmpeg::Segment { trim: Some(project::Trim { start: 0.5, end: None }), ..segment.clone() },
+        ffmpeg::Segment { loop_samples: Some(48000), ..segment.clone() },
+        ffmpeg::Segment { frames: 5, ..segment.clone() },
+        ffmpeg::Segment { fade: 1.0, ..segment.clone() },
+        ffmpeg::Segment { overlays: vec!["drawtext=text=1".into()], ..segment.clone() },
+    ];
+    for other in &changed {
+        assert_ne!(other.name("settings").unwrap(), name, "{:?}", other);
+    }
+    assert_ne!(segment.name("other settings").unwrap(), name);
+}
+
+#[test]
+fn group_portions() {
+    use project::{Portion, SlideGroup};
+    let slide = |idx: usize, enabled: bool| serde_json::json!({
+        "visual": { "Slide": { "src": "deck.pdf", "idx": idx } },
+        "audio": "Silent",
+        "png": null,
+        "svg": null,
+        "enabled": enabled,
+    });
+    let group = |first, len, shares: &[f32]| SlideGroup { first, len, shares: shares.to_vec() };
+    let groups = vec![
+        group(0, 3, &[]),
+        // Overlaps the first group.
+        group(2, 2, &[]),
+        group(3, 2, &[1.0, 3.0]),
+        // Past the last slide.
+        group(6, 3, &[]),
+        // Not a share for each slide, they are equal instead.
+        group(5, 1, &[1.0, 2.0]),
+        // No enabled slide to show the narration.
+        group(6, 2, &[]),
+    ];
+    let meta: project::Meta = serde_json::from_value(serde_json::json!({
+        "source": "deck.pdf",
+        "slides": (0..8).map(|idx| slide(idx, idx != 1 && idx < 6)).collect::<Vec<_>>(),
+        "ffcontrol": null,
+        "output": null,
+        "replacement": { "path": null },
+        "groups": groups,
+    })).unwrap();
+
+    let portion = |lead, start, end| Some(Portion { lead, start, end });
+    assert_eq!(meta.group_portions(), [
+        portion(0, 0.0, 0.5),
+        // Disabled, it takes no time of the narration.
+        portion(0, 0.5, 0.5),
+        portion(0, 0.5, 1.0),
+        portion(3, 0.0, 0.25),
+        portion(3, 0.25, 1.0),
+        portion(5, 0.0, 1.0),
+        None,
+        None,
+    ]);
+}
+
+#[test]
+fn assembly_portion() {
+    use ffmpeg::{Assembly, Timing};
+    use project::Trim;
+    let timing = Timing { offset_ms: 3000, tempo: 1.0, ..Timing::default() };
+    // Over before the audio starts, the audio is delayed past the end of the part.
+    assert_eq!(Assembly::portion(&timing, 10.0, 10.0, (0.0, 0.25)), (2.5, None, 3500));
+    // The audio starts within the part.
+    assert_eq!(
+        Assembly::portion(&timing, 10.0, 10.0, (0.0, 0.5)),
+        (5.0, Some(Trim { start: 0.0, end: Some(2.0) }), 3000),
+    );
+    // The audio is already playing.
+    assert_eq!(
+        Assembly::portion(&timing, 10.0, 10.0, (0.5, 0.75)),
+        (2.5, Some(Trim { start: 2.0, end: Some(4.5) }), 0),
+    );
+
+    // After the end of the recording there is nothing to play.
+    let timing = Timing { tempo: 1.0, ..Timing::default() };
+    assert_eq!(Assembly::portion(&timing, 4.0, 10.0, (0.5, 1.0)), (5.0, None, 6000));
+
+    // Within the trimmed part of the file, at its tempo.
+    let trim = Some(Trim { start: 1.0, end: None });
+    let timing = Timing { tempo: 2.0, trim, ..Timing::default() };
+    assert_eq!(
+        Assembly::portion(&timing, 10.0, 4.5, (0.0, 0.5)),
+        (2.25, Some(Trim { start: 1.0, end: Some(5.5) }), 0),
+    );
+}
+
+#[test]
+fn store_conflict() {
+    let tempdir = tempfile::TempDir::new().expect("Temporary directory");
+    let mut sink = sink::Sink::new(tempdir.path().to_owned())
+        .expect("Sink in temporary directory");
+    let mut project = project::Project::new(&mut sink, &mut &b"%PDF-1.4"[..])
+        .expect("Project created");
+    project.store().expect("Stored again by its owner");
+
+    // Someone else, such as the web server, stores a change meanwhile.
+    let file = project.dir.work_dir().join(".project");
+    let mut theirs: serde_json::Value = serde_json::from_slice(&fs::read(&file).unwrap()).unwrap();
+    theirs["title"] = "Changed elsewhere".into();
+    theirs["revision"] = (project.meta.revision + 1).into();
+    fs::write(&file, serde_json::to_vec(&theirs).unwrap()).unwrap();
+
+    project.meta.title = Some("Changed here".into());
+    assert!(matches!(project.store(), Err(crate::FatalError::Conflict)));
+    let stored: serde_json::Value = serde_json::from_slice(&fs::read(&file).unwrap()).unwrap();
+    assert_eq!(stored["title"], "Changed elsewhere");
+}
+
+#[test]
+fn store_merged() {
+    let tempdir = tempfile::TempDir::new().expect("Temporary directory");
+    let mut sink = sink::Sink::new(tempdir.path().to_owned())
+        .expect("Sink in temporary directory");
+    let mut project = project::Project::new(&mut sink, &mut &b"%PDF-1.4"[..])
+        .expect("Project created");
+    let slide = |idx: usize| serde_json::json!({
+        "visual": { "Slide": { "src": format!("page-{}.svg", idx), "idx": idx } },
+        "audio": "Silent",
+        "png": null,
+        "svg": null,
+    });
+    project.meta.slides = (0..2).map(|idx| serde_json::from_value(slide(idx)).unwrap()).collect();
+    project.store().unwrap();
+
+    // As each request of the web server loads the project.
+    let dir = project.dir.work_dir().to_owned();
+    let file = dir.join(".project");
+    let open = || project::Project {
+        dir: sink.nested(dir.clone()).unwrap(),
+        project_id: project.project_id,
+        meta: serde_json::from_slice(&fs::read(&file).unwrap()).unwrap(),
+        progress: Default::default(),
+    };
+
+    // The thumbnails of both pages, requested at once.
+    let (mut first, mut second, mut render) = (open(), open(), open());
+    first.meta.slides[0].png = Some(dir.join("slide-001.png"));
+    first.store_pixmaps().expect("Stored the first pixmap");
+    second.meta.slides[1].png = Some(dir.join("slide-002.png"));
+    second.store_pixmaps().expect("Stored the second pixmap");
+
+    // A render finishing after a change of the narration.
+    let mut edit = open();
+    edit.meta.slides[1].sync_offset_ms = 500;
+    edit.store().unwrap();
+    render.meta.output = Some(dir.join("video.mp4"));
+    render.store_rendered().expect("Stored the video");
+
+    let stored = open();
+    assert!(stored.meta.slides.iter().all(|slide| slide.png.is_some()));
+    assert_eq!(stored.meta.slides[1].sync_offset_ms, 500);
+    assert_eq!(stored.meta.output, Some(dir.join("video.mp4")));
+}
+
+#[test]
+fn kept_segment_list() {
+    // A segment shown twice in a row is listed twice.
+    let list = "file '/tmp/.tmp1/ab/segment-1.mp4'\n\
+        file '/tmp/.tmp1/ab/segment-1.mp4'\n\
+        file '/tmp/.tmp1/ab/segment-2.mp4'\n";
+    assert_eq!(
+        pipeline::Pipeline::relative_list(list),
+        "file 'segment-1.mp4'\nfile 'segment-1.mp4'\nfile 'segment-2.mp4'\n",
+    );
+}
@@ -0,0 +1,119 @@
+//! The assembled slides as an OpenTimelineIO timeline, for editing the video further elsewhere.
+//!
+//! The timeline has a track of slides and one of their narration, referring to the files of the
+//! project. Editors such as Kdenlive or DaVinci Resolve import it through the adapters of
+//! OpenTimelineIO.
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::escape_url_path;
+
+/// One slide with its audio, as placed in the video.
+pub struct Clip {
+    pub visual: PathBuf,
+    pub audio: PathBuf,
+    /// Seconds the slide is shown.
+    pub seconds: f64,
+    /// Seconds of silence before the audio starts.
+    pub delay: f64,
+    /// Where the audio starts in its file, in seconds.
+    pub audio_start: f64,
+    /// Seconds of the video during which the audio plays.
+    pub audible: f64,
+    /// The speed of the audio, `1.0` as recorded.
+    pub tempo: f64,
+}
+
+/// The document of a timeline, with `rate` as the time base of all durations.
+pub fn otio(name: &str, clips: &[Clip], rate: u32) -> Value {
+    let mut slides = vec![];
+    let mut narration = vec![];
+    for (idx, clip) in clips.iter().enumerate() {
+        let slide_name = format!("Slide {}", idx + 1);
+        slides.push(json!({
+            "OTIO_SCHEMA": "Clip.1",
+            "name": slide_name,
+            "source_range": range(0.0, clip.seconds, rate),
+            "media_reference": reference(&clip.visual),
+        }));
+
+        let delay = clip.delay.min(clip.seconds);
+        let audible = clip.audible.min(clip.seconds - delay).max(0.0);
+        let rest = clip.seconds - delay - audible;
+        if delay > 0.0 {
+            narration.push(gap(delay, rate));
+        }
+
+        if audible > 0.0 {
+            let mut audio = json!({
+                "OTIO_SCHEMA": "Clip.1",
+                "name": slide_name,
+                // The range is of the file, which plays faster or slower with a time warp.
+                "source_range": range(clip.audio_start, audible * clip.tempo, rate),
+                "media_reference": reference(&clip.audio),
+            });
+            if clip.tempo != 1.0 {
+                audio["effects"] = json!([{
+                    "OTIO_SCHEMA": "LinearTimeWarp.1",
+                    "name": "tempo",
+                    "time_scalar": clip.tempo,
+                }]);
+            }
+            narration.push(audio);
+        }
+
+        if rest > 0.0 {
+            narration.push(gap(rest, rate));
+        }
+    }
+
+    json!({
+        "OTIO_SCHEMA": "Timeline.1",
+        "name": name,
+        "tracks": {
+            "OTIO_SCHEMA": "Stack.1",
+            "name": "tracks",
+            "children": [
+                { "OTIO_SCHEMA": "Track.1", "name": "Slides", "kind": "Video", "children": slides },
+                { "OTIO_SCHEMA": "Track.1", "name": "Narration", "kind": "Audio", "children": narration },
+            ],
+        },
+    })
+}
+
+fn range(start: f64, seconds: f64, rate: u32) -> Value {
+    json!({
+        "OTIO_SCHEMA": "TimeRange.1",
+        "start_time": time(start, rate),
+        "duration": time(seconds, rate),
+    })
+}
+
+fn time(seconds: f64, rate: u32) -> Value {
+    json!({
+        "OTIO_SCHEMA": "RationalTime.1",
+        "rate": f64::from(rate),
+        "value": seconds * f64::from(rate),
+    })
+}
+
+fn gap(seconds: f64, rate: u32) -> Value {
+    json!({
+        "OTIO_SCHEMA": "Gap.1",
+        "name": "",
+        "source_range": range(0.0, seconds, rate),
+    })
+}
+
+fn reference(path: &Path) -> Value {
+    json!({
+        "OTIO_SCHEMA": "ExternalReference.1",
+        "target_url": file_url(path),
+    })
+}
+
+/// A `file://` url of an absolute path.
+fn file_url(path: &Path) -> String {
+    format!("file://{}", escape_url_path(&path.to_string_lossy()))
+}
@@ -0,0 +1,128 @@
+//! Upload finished videos to S3 compatible object storage, for servers without anyone at them.
+//!
+//! `curl` sends and signs the request. The credentials are taken from the environment, as by
+//! other S3 clients: `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN` if the
+//! credentials are temporary. They are given to `curl` in a file, not on its command line.
+use std::{env, fs, io};
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use serde::Deserialize;
+use which::CanonicalPath;
+
+use crate::{escape_url_path, FatalError};
+use crate::sink::Sink;
+use crate::watchdog::Watchdog;
+
+/// Where videos are uploaded, no upload unless both `endpoint` and `bucket` are set.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UploadTarget {
+    /// The url of the service, such as `https://s3.eu-central-1.amazonaws.com`.
+    pub endpoint: Option<String>,
+    pub bucket: Option<String>,
+    /// The region requests are signed for, `us-east-1` if not set.
+    pub region: Option<String>,
+    /// Put before the name of each video, such as `videos/`.
+    pub prefix: Option<String>,
+}
+
+pub struct Upload {
+    curl: CanonicalPath,
+    /// The url of the bucket, without a trailing `/`.
+    bucket_url: String,
+    region: String,
+    prefix: String,
+    watchdog: Watchdog,
+}
+
+impl UploadTarget {
+    pub fn is_configured(&self) -> bool {
+        self.endpoint.is_some() && self.bucket.is_some()
+    }
+}
+
+impl Upload {
+    pub const CURL: &'static str = "curl";
+
+    /// Upload to a configured target with `curl`.
+    pub fn new(target: &UploadTarget, curl: CanonicalPath) -> Option<Self> {
+        let endpoint = target.endpoint.as_deref()?.trim_end_matches('/');
+        let bucket = target.bucket.as_deref()?;
+        Some(Upload {
+            curl,
+            // Addressed by path, which all S3 compatible services understand.
+            bucket_url: format!("{}/{}", endpoint, escape_url_path(bucket)),
+            region: target.region.clone().unwrap_or_else(|| "us-east-1".to_owned()),
+            prefix: target.prefix.clone().unwrap_or_default(),
+            watchdog: Watchdog {
+                heartbeat: None,
+                deadline: Duration::from_secs(60*60),
+            },
+        })
+    }
+
+    /// Upload a file as the object `name`, and return the url of the object.
+    ///
+    /// An object of the same name is replaced, such that rendering a project again updates it.
+    pub fn put(&self, file: &Path, name: &str, sink: &mut Sink) -> Result<String, FatalError> {
+        let key = format!("{}{}", self.prefix, name);
+        let url = format!("{}/{}", self.bucket_url, escape_url_path(&key));
+        let config = sink.unique_path()?.path;
+        Self::write_credentials(&config)?;
+
+        let mut command = Command::new(self.curl.as_path());
+        command
+            .current_dir(sink.work_dir())
+            .args(&["--silent", "--show-error", "--fail"])
+            .arg("--aws-sigv4")
+            .arg(format!("aws:amz:{}:s3", self.region))
+            .arg("--config")
+            .arg(&config)
+            // Signing the content would need to read it twice.
+            .args(&["--header", "x-amz-content-sha256: UNSIGNED-PAYLOAD"])
+            .arg("--upload-file")
+            .arg(file)
+            .arg(&url);
+
+        let output = self.watchdog.output_networked(Self::CURL, &mut command);
+        let _ = fs::remove_file(&config);
+        let output = output?;
+        if !output.status.success() {
+            return Err(FatalError::tool_failed(Self::CURL, &output));
+        }
+
+        tracing::info!(%url, "Uploaded");
+        Ok(url)
+    }
+
+    /// A configuration of `curl` with the credentials, readable only by us.
+    fn write_credentials(path: &Path) -> Result<(), FatalError> {
+        let var = |name: &str| env::var(name).map_err(|_| io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("The credentials of the upload are missing, `{}` is not set", name),
+        ));
+        // Values are quoted, within which `\` escapes.
+        let quote = |value: String| value.replace('\\', "\\\\").replace('"', "\\\"");
+
+        let user = format!("{}:{}", var("AWS_ACCESS_KEY_ID")?, var("AWS_SECRET_ACCESS_KEY")?);
+        let mut config = format!("user = \"{}\"\n", quote(user));
+        if let Ok(token) = env::var("AWS_SESSION_TOKEN") {
+            let header = format!("x-amz-security-token: {}", token);
+            config += &format!("header = \"{}\"\n", quote(header));
+        }
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt as _;
+            options.mode(0o600);
+        }
+
+        use std::io::Write as _;
+        options.open(path)?.write_all(config.as_bytes())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,326 @@
+//! Bound the runtime of helper processes.
+//!
+//! Tools such as `ffmpeg` may get stuck on corrupt input without ever exiting. Where a tool reports
+//! progress on its stdout every line is treated as a heartbeat and the tool is killed once these
+//! stop arriving. Independently, every tool is subject to an overall deadline.
+//!
+//! In a dry run the tools are printed instead of run, except those whose results later steps
+//! depend on.
+use std::{fmt, fs, io, thread};
+use std::ffi::OsStr;
+use std::io::{BufRead as _, Read as _, Write as _};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::sandbox::Sandbox;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Watchdog {
+    /// Maximum time between two lines on stdout before the child is considered stuck.
+    /// `None` for tools which only print their result at the very end.
+    pub heartbeat: Option<Duration>,
+    /// Maximum total runtime of the child.
+    pub deadline: Duration,
+}
+
+pub enum WatchdogError {
+    Io(io::Error),
+    /// The tool stopped reporting progress.
+    Stalled {
+        tool: &'static str,
+        silent_for: Duration,
+    },
+    /// The tool did not finish in time.
+    TimedOut {
+        tool: &'static str,
+        ran_for: Duration,
+    },
+    /// The tool was killed because we are shutting down.
+    Cancelled {
+        tool: &'static str,
+    },
+}
+
+/// Set once all running tools should be killed.
+static CANCEL: AtomicBool = AtomicBool::new(false);
+
+/// Set when tools should be printed instead of run.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Kill all running and future tools, for shutting down.
+pub fn cancel_all() {
+    CANCEL.store(true, Ordering::SeqCst);
+}
+
+/// Print the command lines of all future tools to stdout, and only run those that are required.
+pub fn dry_run() {
+    DRY_RUN.store(true, Ordering::SeqCst);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::SeqCst)
+}
+
+/// Print a command line of a dry run, as it could be pasted into a shell.
+pub fn print_command<I>(cwd: Option<&Path>, args: I, stdin: Option<&Path>)
+where
+    I: IntoIterator,
+    I::Item: AsRef<OsStr>,
+{
+    let mut line = String::new();
+    if let Some(cwd) = cwd {
+        line.push_str("cd ");
+        line.push_str(&shell_quote(cwd.as_os_str()));
+        line.push_str(" && ");
+    }
+
+    let args = args.into_iter().map(|arg| shell_quote(arg.as_ref())).collect::<Vec<_>>();
+    line.push_str(&args.join(" "));
+    if let Some(stdin) = stdin {
+        line.push_str(" < ");
+        line.push_str(&shell_quote(stdin.as_os_str()));
+    }
+
+    let stdout = io::stdout();
+    let _ = writeln!(stdout.lock(), "{}", line);
+}
+
+/// Print a file that was generated for a tool in a dry run, such as a list of inputs.
+pub fn print_file(path: &Path) {
+    let content = fs::read_to_string(path).unwrap_or_default();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let _ = writeln!(stdout, "# {}", path.display());
+    for line in content.lines() {
+        let _ = writeln!(stdout, "#   {}", line);
+    }
+}
+
+fn shell_quote(arg: &OsStr) -> String {
+    let arg = arg.to_string_lossy();
+    let plain = !arg.is_empty() && arg
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || "-_./=:,+%@".contains(ch));
+    if plain {
+        arg.into_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+impl Watchdog {
+    /// How often we check on the child when it is not sending heartbeats.
+    const POLL: Duration = Duration::from_millis(100);
+
+    /// A watchdog that only enforces a deadline.
+    pub fn deadline(deadline: Duration) -> Self {
+        Watchdog {
+            heartbeat: None,
+            deadline,
+        }
+    }
+
+    /// Run the command to completion, like `Command::output`, but kill it when it misbehaves.
+    ///
+    /// Stdin is always closed, stdout and stderr are captured. The command runs in the active
+    /// sandbox, if any. In a dry run it is only printed and succeeds without any output.
+    pub fn output(&self, tool: &'static str, command: &mut Command)
+        -> Result<Output, WatchdogError>
+    {
+        if is_dry_run() {
+            print_command(command.get_current_dir(), Self::command_line(command), None);
+            return Ok(Output {
+                status: ExitStatus::default(),
+                stdout: vec![],
+                stderr: vec![],
+            });
+        }
+
+        self.run(tool, command)
+    }
+
+    /// As `output`, but also run in a dry run since later steps need the result.
+    ///
+    /// For tools that probe their input, or quickly create files that are probed later.
+    pub fn output_required(&self, tool: &'static str, command: &mut Command)
+        -> Result<Output, WatchdogError>
+    {
+        if is_dry_run() {
+            print_command(command.get_current_dir(), Self::command_line(command), None);
+        }
+
+        self.run(tool, command)
+    }
+
+    /// As `output`, but outside of the sandbox which takes away the network.
+    ///
+    /// Only for tools that need the network and are not given any uploaded files to parse.
+    pub fn output_networked(&self, tool: &'static str, command: &mut Command)
+        -> Result<Output, WatchdogError>
+    {
+        if is_dry_run() {
+            print_command(command.get_current_dir(), Self::command_line(command), None);
+            return Ok(Output {
+                status: ExitStatus::default(),
+                stdout: vec![],
+                stderr: vec![],
+            });
+        }
+
+        self.watch(tool, command)
+    }
+
+    fn command_line(command: &Command) -> impl Iterator<Item = &OsStr> {
+        std::iter::once(command.get_program()).chain(command.get_args())
+    }
+
+    fn run(&self, tool: &'static str, command: &mut Command)
+        -> Result<Output, WatchdogError>
+    {
+        let mut sandboxed = Sandbox::active().map(|sandbox| sandbox.wrap(command));
+        let command = sandboxed.as_mut().unwrap_or(command);
+        self.watch(tool, command)
+    }
+
+    fn watch(&self, tool: &'static str, command: &mut Command)
+        -> Result<Output, WatchdogError>
+    {
+        let span = tracing::debug_span!("tool", tool);
+        let _enter = span.enter();
+        tracing::debug!(?command, "Running");
+
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let stdout = child.stdout.take().expect("Stdout was piped");
+        let mut stderr = child.stderr.take().expect("Stderr was piped");
+
+        let (beat, heartbeats) = mpsc::channel();
+        let stdout_reader = thread::spawn(move || {
+            let mut stdout = io::BufReader::new(stdout);
+            let mut data = vec![];
+            loop {
+                match stdout.read_until(b'\n', &mut data) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => { let _ = beat.send(()); },
+                }
+            }
+            data
+        });
+
+        let stderr_reader = thread::spawn(move || {
+            let mut data = vec![];
+            let _ = stderr.read_to_end(&mut data);
+            data
+        });
+
+        let start = Instant::now();
+        let mut last_beat = start;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+
+            let now = Instant::now();
+            let error = if CANCEL.load(Ordering::SeqCst) {
+                Some(WatchdogError::Cancelled { tool })
+            } else if now.duration_since(start) > self.deadline {
+                Some(WatchdogError::TimedOut {
+                    tool,
+                    ran_for: now.duration_since(start),
+                })
+            } else {
+                match self.heartbeat {
+                    Some(heartbeat) if now.duration_since(last_beat) > heartbeat => {
+                        Some(WatchdogError::Stalled {
+                            tool,
+                            silent_for: now.duration_since(last_beat),
+                        })
+                    }
+                    _ => None,
+                }
+            };
+
+            if let Some(error) = error {
+                tracing::warn!(%error, "Killing tool");
+                // Both may fail if the child exited in the meantime. We report it anyways.
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(error);
+            }
+
+            match heartbeats.recv_timeout(Self::POLL) {
+                Ok(()) => last_beat = Instant::now(),
+                Err(RecvTimeoutError::Timeout) => {},
+                // Stdout was closed but the process is still running.
+                Err(RecvTimeoutError::Disconnected) => thread::sleep(Self::POLL),
+            }
+        };
+
+        tracing::debug!(%status, seconds = start.elapsed().as_secs_f32(), "Finished");
+        Ok(Output {
+            status,
+            stdout: stdout_reader.join().unwrap_or_default(),
+            stderr: stderr_reader.join().unwrap_or_default(),
+        })
+    }
+}
+
+impl From<io::Error> for WatchdogError {
+    fn from(err: io::Error) -> Self {
+        WatchdogError::Io(err)
+    }
+}
+
+impl From<WatchdogError> for io::Error {
+    fn from(err: WatchdogError) -> Self {
+        match err {
+            WatchdogError::Io(err) => err,
+            other => io::Error::new(io::ErrorKind::TimedOut, other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for WatchdogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WatchdogError::Io(err) => write!(f, "I/O error: {}", err),
+            WatchdogError::Stalled { tool, silent_for } => write!(
+                f,
+                "The tool `{}` stopped making progress for {}s and was killed.",
+                tool,
+                silent_for.as_secs(),
+            ),
+            WatchdogError::TimedOut { tool, ran_for } => write!(
+                f,
+                "The tool `{}` did not finish within {}s and was killed.",
+                tool,
+                ran_for.as_secs(),
+            ),
+            WatchdogError::Cancelled { tool } => write!(
+                f,
+                "The tool `{}` was killed as the server is shutting down.",
+                tool,
+            ),
+        }
+    }
+}
+
+impl From<WatchdogError> for crate::FatalError {
+    fn from(err: WatchdogError) -> Self {
+        match err {
+            WatchdogError::Io(err) => crate::FatalError::Io(err),
+            WatchdogError::Stalled { tool, .. }
+            | WatchdogError::TimedOut { tool, .. }
+            | WatchdogError::Cancelled { tool } => {
+                crate::FatalError::Tool { tool, detail: err.to_string() }
+            }
+        }
+    }
+}
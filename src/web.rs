@@ -1,4 +1,5 @@
-use std::{fmt, fs, io, path, sync::Arc};
+use std::collections::HashMap;
+use std::{fmt, fs, io, path, sync::Arc, sync::Mutex, thread};
 
 use serde::Serialize;
 use tokio::runtime;
@@ -9,11 +10,18 @@ use tide::{Request, Server};
 use tide::http::mime;
 use tide::sessions::{MemoryStore, SessionMiddleware};
 
-use crate::{FatalError, sink};
+use crate::{ErrorClass, FatalError, sink};
+use crate::api::{self, Endpoint};
 use crate::app::App;
-use crate::project::{Audio, Project, Visual};
+use crate::auth::{constant_time_eq, User};
+use crate::conditional::Validators;
+use crate::health::Tools;
+use crate::progress::{Progress, Stage};
+use crate::project::{Audio, Project, Speech, Split, Trim, Visual};
+use crate::session::FileStore;
+use crate::share::{ShareKey, Shared};
 
-pub fn serve(app: App) -> Result<(), FatalError> {
+pub fn serve(app: Arc<App>, addr: &str) -> Result<(), FatalError> {
 
     let state = Web::new(app)?;
     let app = tide_app(state);
@@ -21,27 +29,84 @@ pub fn serve(app: App) -> Result<(), FatalError> {
     let static_data = app.state().arc.clone();
     // Don't care if our hook is not there for now, just missing cleanup then.
     let _ = ctrlc::set_handler(move || {
-        let _ = fs::remove_dir_all(static_data.app.tempdir.path());
+        shutdown(&static_data.app);
         std::process::exit(0);
     });
 
+    listen(app, addr)
+}
+
+/// Serve on another thread, beside the terminal interface.
+///
+/// The terminal interface handles the keys for quitting, it must call `shutdown` afterwards.
+pub fn spawn(app: Arc<App>, addr: &str) -> Result<(), FatalError> {
+    let state = Web::new(app)?;
+    let app = tide_app(state);
+    let addr = addr.to_owned();
+
+    thread::spawn(move || {
+        if let Err(err) = listen(app, &addr) {
+            tracing::error!(?err, "The web server stopped");
+        }
+    });
+
+    Ok(())
+}
+
+fn listen(app: Server<Web>, addr: &str) -> Result<(), FatalError> {
     let rt = runtime::Builder::new_current_thread().build()?;
 
-    let addr = "localhost:8051";
-    eprintln!("Serving web server on `{}`", addr);
+    tracing::info!(%addr, "Serving web server");
     rt.block_on(app.listen(addr))?;
 
     Ok(())
 }
 
+/// Stop accepting jobs, give running ones time to finish, then clean up.
+pub fn shutdown(app: &App) {
+    let poll = std::time::Duration::from_millis(100);
+    let wait_for_jobs = |timeout| {
+        let deadline = std::time::Instant::now() + timeout;
+        while app.jobs.active() > 0 && std::time::Instant::now() < deadline {
+            std::thread::sleep(poll);
+        }
+    };
+
+    app.jobs.drain();
+    if app.jobs.active() > 0 {
+        tracing::info!(jobs = app.jobs.active(), "Shutting down, waiting for running jobs");
+        wait_for_jobs(Web::SHUTDOWN_GRACE);
+    }
+
+    if app.jobs.active() > 0 {
+        tracing::warn!(jobs = app.jobs.active(), "Cancelling jobs");
+        crate::watchdog::cancel_all();
+        // Let the jobs notice, so they do not write into the removed temporary directory.
+        wait_for_jobs(Web::SHUTDOWN_CANCEL);
+    }
+
+    let _ = fs::remove_dir_all(app.tempdir.path());
+}
+
 #[derive(Clone)]
 pub struct Web {
     arc: Arc<Static>,
 }
 
 struct Static {
-    app: App,
+    /// Shared with the terminal interface, when both run.
+    app: Arc<App>,
     index: String,
+    share: ShareKey,
+    tools: Tools,
+    /// The progress of the projects being rendered, observed by other requests.
+    rendering: Mutex<HashMap<sink::Identifier, Arc<Progress>>>,
+}
+
+/// A project registered as being rendered, until dropped.
+struct Rendering<'a> {
+    arc: &'a Static,
+    identifier: sink::Identifier,
 }
 
 #[derive(RustEmbed)]
@@ -49,7 +114,7 @@ struct Static {
 struct Asset;
 
 impl Web {
-    pub fn new(app: App) -> Result<Self, FatalError> {
+    pub fn new(app: Arc<App>) -> Result<Self, FatalError> {
         let index = Asset::get("index.html")
             .ok_or_else(|| {
                 FatalError::Io(io::Error::new(
@@ -65,40 +130,85 @@ impl Web {
                     err
                 ))
             })?;
+        // Links shared before a restart stay valid only if we have a stable key.
+        let share = match &app.session_key {
+            Some(key) => ShareKey::new(key),
+            None => {
+                let ephemeral: [u8; 32] = rand::thread_rng().gen();
+                ShareKey::new(&ephemeral)
+            }
+        };
+        let tools = Tools::detect(&app);
         Ok(Web {
             arc: Arc::new(Static {
                 app,
                 index,
+                share,
+                tools,
+                rendering: Mutex::default(),
             }),
         })
     }
 
     const PROJECT_ID: &'static str = "project-id";
+    const THUMBNAIL_WIDTH: u32 = 320;
+    /// How long running jobs may take to finish on shutdown.
+    const SHUTDOWN_GRACE: std::time::Duration = std::time::Duration::from_secs(30);
+    /// How long cancelled jobs may take to clean up.
+    const SHUTDOWN_CANCEL: std::time::Duration = std::time::Duration::from_secs(5);
+}
+
+impl Static {
+    fn start_rendering(&self, project: &Project) -> Rendering<'_> {
+        let identifier = project.project_id;
+        self.rendering.lock().unwrap().insert(identifier, project.progress.clone());
+        Rendering { arc: self, identifier }
+    }
+
+    /// The progress of a render of the project, if one is running.
+    fn progress(&self, identifier: &sink::Identifier) -> Option<Arc<Progress>> {
+        self.rendering.lock().unwrap().get(identifier).cloned()
+    }
 }
 
-fn serialize_project(project: &Project) -> impl Serialize {
+impl Drop for Rendering<'_> {
+    fn drop(&mut self) {
+        self.arc.rendering.lock().unwrap().remove(&self.identifier);
+    }
+}
+
+fn serialize_project(project: &Project, base: &str) -> impl Serialize {
     #[derive(Serialize)]
     struct Pages {
         identifier: String,
         pages: Vec<Page>,
+        /// Indices of pages that could not be converted.
+        failed: Vec<usize>,
         output: Option<String>,
+        /// The position in the queue of renders, while waiting for others.
+        queued: Option<usize>,
     }
 
     #[derive(Serialize)]
     struct Page {
         img_url: Option<String>,
+        thumb_url: String,
         audio_url: Option<String>,
+        error: Option<String>,
+        speech: Option<Speech>,
+        trim: Option<Trim>,
     }
 
-    fn project_asset_url(path: &path::Path) -> String {
+    let project_asset_url = |path: &path::Path| -> String {
         // FIXME: review. Or turn into static invariant.
         let name = path.file_name().unwrap();
         let name = std::path::Path::new(name);
-        format!("/project/asset/{}", name.display())
-    }
+        format!("{}/asset/{}", base, name.display())
+    };
 
-    fn slide_to_page(slide: &crate::project::Slide) -> Page {
+    let slide_to_page = |(idx, slide): (usize, &crate::project::Slide)| -> Page {
         Page {
+            thumb_url: format!("{}/page/{}/thumb", base, idx),
             img_url: match slide.visual {
                 Visual::Slide { ref src, .. } => {
                     Some(if let Some(ref svg) = slide.svg {
@@ -115,44 +225,99 @@ fn serialize_project(project: &Project) -> impl Serialize {
                 Audio::Silent | Audio::Skip => None,
                 Audio::File { ref src } => Some(project_asset_url(src)),
             },
+            error: slide.error.clone(),
+            speech: slide.speech,
+            trim: slide.trim,
         }
-    }
+    };
 
     Pages {
         identifier: base64::encode_config(&project.project_id, base64::URL_SAFE),
         pages: project.meta.slides
             .iter()
+            .enumerate()
             .map(slide_to_page)
             .collect(),
+        failed: project.meta.slides
+            .iter()
+            .enumerate()
+            .filter(|(_, slide)| slide.error.is_some())
+            .map(|(idx, _)| idx)
+            .collect(),
         output: match project.meta.output {
             None => None,
             Some(ref path) => Some(project_asset_url(path)),
-        }
+        },
+        queued: match project.progress.snapshot() {
+            snapshot if snapshot.stage == Stage::Queued => Some(snapshot.total),
+            _ => None,
+        },
     }
 }
 
 fn tide_app(state: Web) -> Server<Web> {
     let mut app = tide::with_state(state);
 
-    // We don't intend to store sessions, just use them to identify a user.
-    let mut rng = rand::thread_rng();
-    let ephemeral: [u8; 32] = rng.gen();
-    app.with(SessionMiddleware::new(
-        MemoryStore::new(),
-        &ephemeral[..]
-    ));
+    // Sessions identify the project of a user. With a data directory they are stored beside the
+    // projects so that they survive a restart. Otherwise nothing is kept across runs anyways.
+    let static_data = app.state().arc.clone();
+    let persistent = static_data.app.session_key.as_ref().and_then(|key| {
+        let dir = static_data.app.sink.work_dir().join(".sessions");
+        match FileStore::new(dir) {
+            Ok(store) => Some((store, key)),
+            Err(err) => {
+                tracing::warn!(%err, "Sessions will not be persisted");
+                None
+            }
+        }
+    });
+
+    if let Some((store, key)) = persistent {
+        app.with(SessionMiddleware::new(store, key));
+    } else {
+        let mut rng = rand::thread_rng();
+        let ephemeral: [u8; 32] = rng.gen();
+        app.with(SessionMiddleware::new(
+            MemoryStore::new(),
+            &ephemeral[..]
+        ));
+    }
 
-    app.at("/").get(tide_index);
-    // FIXME: restore the session state to that id.
-    app.at("/project/edit/:id").get(tide_index);
+    // Tell the client why a request failed, in a form that scripts can react to.
+    app.with(tide::utils::After(|mut response: tide::Response| async move {
+        if let Some(err) = response.error() {
+            let body = tide::Body::from_json(&ApiError::from_tide(err))?;
+            response.set_body(body);
+            response.set_content_type(mime::JSON);
+        }
+        Ok(response)
+    }));
 
-    app.at("/project/new").put(tide_create);
-    app.at("/project/get").get(tide_introspect);
-    app.at("/project/asset/:id").get(tide_project_asset);
-    app.at("/project/render").post(tide_render);
+    app.with(Authenticate);
 
-    app.at("/project/page/:num").put(tide_set_audio);
-    app.at("/static/*").get(tide_static);
+    for route in api::SESSION.iter().chain(api::API) {
+        let mut at = app.at(route.path);
+        let method = route.method;
+        match route.endpoint {
+            Endpoint::Index => at.method(method, tide_index),
+            Endpoint::Static => at.method(method, tide_static),
+            Endpoint::Create => at.method(method, tide_create),
+            Endpoint::ApiCreate => at.method(method, api_create),
+            Endpoint::Introspect => at.method(method, tide_introspect),
+            Endpoint::Asset => at.method(method, tide_project_asset),
+            Endpoint::Render => at.method(method, tide_render),
+            Endpoint::SetAudio => at.method(method, tide_set_audio),
+            Endpoint::AudioBatch => at.method(method, tide_audio_batch),
+            Endpoint::Recording => at.method(method, tide_recording),
+            Endpoint::Trim => at.method(method, tide_trim),
+            Endpoint::Thumbnail => at.method(method, tide_thumbnail),
+            Endpoint::Share => at.method(method, tide_share),
+            Endpoint::Shared => at.method(method, tide_shared),
+            Endpoint::OpenApi => at.method(method, tide_openapi),
+            Endpoint::Health => at.method(method, tide_health),
+            Endpoint::Ready => at.method(method, tide_health),
+        };
+    }
 
     app
 }
@@ -180,9 +345,9 @@ async fn tide_introspect(request: Request<Web>)
     -> tide::Result<tide::Response>
 {
     if let Some(project) = request.project()? {
-        tide_project_state(&project)
+        tide_project_state(&request, &project)
     } else {
-        Ok(tide::Response::builder(404).build())
+        Err(tide::Error::new(404, Error::NoSuchProject))
     }
 }
 
@@ -192,36 +357,276 @@ async fn tide_project_asset(request: Request<Web>)
     let path = {
         let project = match request.project()? {
             Some(project) => project,
-            None => return Ok(tide::Response::builder(404).build()),
+            None => return Err(tide::Error::new(404, Error::NoSuchProject)),
         };
 
-        let path = request.url().path();
-        let relative = path
-            .strip_prefix("/project/asset/")
-            .ok_or_else(|| tide::Error::new(400, Error::AssetNotFound))?;
+        let relative = request.param("asset")
+            .map_err(|_| tide::Error::new(400, Error::AssetNotFound))?;
 
         project.dir.work_dir().join(relative)
     };
 
-    let body = tide::Body::from_file(path).await?;
-    let response = tide::Response::builder(200)
-        .body(body)
-        .build();
-    Ok(response)
+    serve_file(&request, &path).await
 }
 
 async fn tide_render(request: Request<Web>)
     -> tide::Result<tide::Response>
 {
+    let state = request.state().clone();
     let mut project = match request.project()? {
         Some(project) => project,
-        None => return Ok(tide::Response::builder(404).build()),
+        None => return Err(tide::Error::new(404, Error::NoSuchProject)),
     };
 
-    project.assemble(&request.state().arc.app)?;
-    project.store()?;
+    // Rendering takes minutes, meanwhile other requests are served and see the queue position.
+    // The render continues when the client goes away, so its thread counts the job.
+    let project = async_std::task::spawn_blocking(move || -> tide::Result<Project> {
+        let _job = state.arc.app.jobs.start()
+            .ok_or_else(|| tide::Error::new(503, Error::ShuttingDown))?;
+        let _rendering = state.arc.start_rendering(&project);
+
+        // Failed slides are part of the state.
+        project.assemble(&state.arc.app)?;
+        project.store_rendered()?;
+        Ok(project)
+    }).await?;
+
+    tide_project_state(&request, &project)
+}
+
+async fn tide_share(request: Request<Web>)
+    -> tide::Result<tide::Response>
+{
+    #[derive(serde::Deserialize)]
+    struct Query {
+        hours: Option<u64>,
+    }
+
+    #[derive(Serialize)]
+    struct Link {
+        url: String,
+        /// Seconds since the epoch.
+        expires: u64,
+    }
+
+    let project = request.require_project()?;
+    if project.meta.output.is_none() {
+        return Err(tide::Error::new(409, Error::NotRendered));
+    }
+
+    let query: Query = request.query()
+        .map_err(|_| tide::Error::new(400, Error::InvalidQuery))?;
+    let valid_for = match query.hours {
+        Some(hours) => std::time::Duration::from_secs(hours.saturating_mul(60*60)),
+        None => ShareKey::DEFAULT_VALIDITY,
+    };
+
+    let shared = Shared {
+        project_id: project.project_id,
+        user: request.ext::<User>().map(|user| user.name.clone()),
+    };
+
+    let (link, expires) = request.state().arc.share.mint(&shared, valid_for);
+    let body = tide::Body::from_json(&Link {
+        url: format!("/share/{}", link),
+        expires,
+    })?;
+
+    let response = tide::Response::builder(201)
+        .body(body)
+        .content_type(mime::JSON)
+        .build();
+    Ok(response)
+}
+
+async fn tide_shared(request: Request<Web>)
+    -> tide::Result<tide::Response>
+{
+    let arc = &request.state().arc;
+    let link = request.param("link")
+        .map_err(|_| tide::Error::new(400, Error::AssetNotFound))?;
+    // Expired and forged links are indistinguishable from deleted projects.
+    let shared = arc.share.verify(link)
+        .ok_or_else(|| tide::Error::new(404, Error::NoSuchProject))?;
+
+    let sink = arc.app.sink.as_sink();
+    let sink = match shared.user {
+        None => sink,
+        Some(name) => User { name }.namespace(&sink)?,
+    };
+
+    let output = Project::load(&arc.app, &sink, shared.project_id)?
+        .and_then(|project| project.meta.output)
+        .ok_or_else(|| tide::Error::new(404, Error::NoSuchProject))?;
+
+    serve_file(&request, &output).await
+}
+
+/// Serve a file, or the part of it requested with a `Range` header so that players can seek.
+async fn serve_file(request: &Request<Web>, path: &path::Path)
+    -> tide::Result<tide::Response>
+{
+    use async_std::io::prelude::*;
+
+    let mut file = async_std::fs::File::open(path).await
+        .map_err(|_| tide::Error::new(404, Error::AssetNotFound))?;
+    let meta = fs::metadata(path)?;
+    let len = meta.len();
+
+    let validators = Validators::of_file(&meta);
+    if let Some(response) = not_modified(request, &validators) {
+        return Ok(response);
+    }
+
+    let mime = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(asset_mime)
+        .unwrap_or(mime::BYTE_STREAM);
+
+    let range = request
+        .header("Range")
+        .map_or(ByteRange::Full, |value| ByteRange::parse(value.as_str(), len));
+
+    let mut response = match range {
+        ByteRange::Full => {
+            let body = tide::Body::from_reader(async_std::io::BufReader::new(file), Some(len as usize));
+            tide::Response::builder(200)
+                .body(body)
+                .build()
+        }
+        ByteRange::Partial { start, end } => {
+            file.seek(io::SeekFrom::Start(start)).await?;
+            let count = end - start + 1;
+            let reader = async_std::io::BufReader::new(file.take(count));
+            let body = tide::Body::from_reader(reader, Some(count as usize));
+            tide::Response::builder(206)
+                .header("Content-Range", format!("bytes {}-{}/{}", start, end, len))
+                .body(body)
+                .build()
+        }
+        ByteRange::Unsatisfiable => {
+            return Ok(tide::Response::builder(416)
+                .header("Content-Range", format!("bytes */{}", len))
+                .build());
+        }
+    };
+
+    response.insert_header("Accept-Ranges", "bytes");
+    response.set_content_type(mime);
+    set_validators(&mut response, &validators);
+    Ok(response)
+}
+
+/// A `304 Not Modified` response, if the client already has the current version.
+fn not_modified(request: &Request<Web>, validators: &Validators) -> Option<tide::Response> {
+    let if_none_match = request.header("If-None-Match").map(|value| value.as_str());
+    let if_modified_since = request.header("If-Modified-Since").map(|value| value.as_str());
+
+    if validators.is_fresh(if_none_match, if_modified_since) {
+        let mut response = tide::Response::builder(304).build();
+        set_validators(&mut response, validators);
+        Some(response)
+    } else {
+        None
+    }
+}
+
+fn set_validators(response: &mut tide::Response, validators: &Validators) {
+    response.insert_header("ETag", validators.etag.as_str());
+    if let Some(date) = validators.last_modified_header() {
+        response.insert_header("Last-Modified", date);
+    }
+    // Cache, but always ask whether the copy is still current.
+    response.insert_header("Cache-Control", "no-cache");
+}
+
+/// The content type of the files we produce, which `Mime::from_extension` mostly does not know.
+fn asset_mime(extension: &str) -> Option<mime::Mime> {
+    let essence = match extension {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "mp4" => "video/mp4",
+        "wav" => "audio/wav",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "pdf" => "application/pdf",
+        other => return mime::Mime::from_extension(other),
+    };
+    essence.parse().ok()
+}
 
-    tide_project_state(&project)
+/// The part of a file requested by a `Range` header.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ByteRange {
+    Full,
+    /// Inclusive bounds, as in the header.
+    Partial { start: u64, end: u64 },
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// Interpret the header for a file of `len` bytes.
+    ///
+    /// We only support a single range. For anything else, as for malformed headers, the whole file
+    /// is served which is always permitted.
+    pub(crate) fn parse(header: &str, len: u64) -> Self {
+        let spec = match header.trim().strip_prefix("bytes=") {
+            Some(spec) if !spec.contains(',') => spec.trim(),
+            _ => return ByteRange::Full,
+        };
+
+        let dash = match spec.find('-') {
+            Some(dash) => dash,
+            None => return ByteRange::Full,
+        };
+
+        let (first, last) = (&spec[..dash], &spec[dash+1..]);
+        let (start, end) = match (first.parse::<u64>(), last.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+            (Ok(start), Err(_)) if last.is_empty() => (start, len.saturating_sub(1)),
+            // The final bytes of the file.
+            (Err(_), Ok(suffix)) if first.is_empty() => {
+                if suffix == 0 {
+                    return ByteRange::Unsatisfiable;
+                }
+                (len.saturating_sub(suffix), len.saturating_sub(1))
+            }
+            _ => return ByteRange::Full,
+        };
+
+        if start >= len {
+            ByteRange::Unsatisfiable
+        } else {
+            ByteRange::Partial { start, end }
+        }
+    }
+}
+
+async fn tide_health(request: Request<Web>)
+    -> tide::Result<tide::Response>
+{
+    let arc = &request.state().arc;
+    let health = arc.tools.health(&arc.app);
+
+    let ready = request.url().path() != "/readyz" || health.is_ready(&arc.app);
+    let body = tide::Body::from_json(&health)?;
+    let response = tide::Response::builder(if ready { 200 } else { 503 })
+        .body(body)
+        .content_type(mime::JSON)
+        .build();
+    Ok(response)
+}
+
+async fn tide_openapi(_: Request<Web>)
+    -> tide::Result<tide::Response>
+{
+    let body = tide::Body::from_json(&api::openapi())?;
+    let response = tide::Response::builder(200)
+        .body(body)
+        .content_type(mime::JSON)
+        .build();
+    Ok(response)
 }
 
 async fn tide_static(request: Request<Web>)
@@ -233,6 +638,12 @@ async fn tide_static(request: Request<Web>)
         .ok_or_else(|| tide::Error::new(400, Error::AssetNotFound))?;
     let cow = Asset::get(relative)
         .ok_or_else(|| tide::Error::new(400, Error::AssetNotFound))?;
+
+    let validators = Validators::of_content(&cow);
+    if let Some(response) = not_modified(&request, &validators) {
+        return Ok(response);
+    }
+
     let content = cow.into_owned();
 
     let extension = std::path::Path::new(relative)
@@ -245,54 +656,129 @@ async fn tide_static(request: Request<Web>)
     let mime = mime::Mime::from_extension(extension)
         .ok_or_else(|| tide::Error::new(500, Error::InternalServerError))?;
 
-    let response = tide::Response::builder(200)
+    let mut response = tide::Response::builder(200)
         .content_type(mime)
         .body(content)
         .build();
+    set_validators(&mut response, &validators);
     Ok(response)
 }
 
 async fn tide_create(mut request: Request<Web>)
     -> tide::Result<tide::Response>
 {
-    // TODO: constify.
-    let mime_pdf: mime::Mime = "application/pdf".parse().unwrap();
-
-    match request.content_type() {
-        Some(mime) if mime.essence() == mime_pdf.essence() => {},
-        _ => {
-            return Err(tide::Error::new(415, Error::OnlyPdfAccepted));
-        }
-    }
+    require_pdf(&request)?;
 
     match request.project()? {
         None => {},
         Some(project) => {
-            let sink = request.state().arc.app.sink.as_sink();
+            let sink = request.as_sink()?;
             request.session_mut().remove(Web::PROJECT_ID);
 
             let path = sink.path_of(project.project_id);
             drop(project);
             let _ = std::fs::remove_dir_all(path);
+            if let Some(store) = sink.content_store() {
+                let _ = store.collect_garbage();
+            }
         }
     }
 
-    let mut body = request
-        .body_bytes()
-        .await
-        .map(io::Cursor::new)?;
-
-    let mut sink = request.as_sink();
-
-    let mut project = Project::new(&mut sink, &mut body)?;
-    project.explode(&request.state().arc.app)?;
-    project.thumbnail()?;
+    let mut project = project_from_body(&mut request).await?;
     project.store()?;
 
     request
         .session_mut()
         .insert(Web::PROJECT_ID, &project.project_id)?;
-    tide_project_state(&project)
+    tide_project_state(&request, &project)
+}
+
+async fn api_create(mut request: Request<Web>)
+    -> tide::Result<tide::Response>
+{
+    #[derive(Serialize)]
+    struct Created<T> {
+        token: String,
+        #[serde(flatten)]
+        project: T,
+    }
+
+    require_pdf(&request)?;
+
+    let mut project = project_from_body(&mut request).await?;
+
+    let token: [u8; 32] = rand::thread_rng().gen();
+    let token = base64::encode_config(&token, base64::URL_SAFE);
+    project.meta.token = Some(token.clone());
+    project.store()?;
+
+    let base = api_project_base(&project);
+    let body = tide::Body::from_json(&Created {
+        token,
+        project: serialize_project(&project, &base),
+    })?;
+
+    let response = tide::Response::builder(201)
+        .body(body)
+        .content_type(mime::JSON)
+        .build();
+
+    Ok(response)
+}
+
+/// Stream the request body into a new project and explode it.
+async fn project_from_body(request: &mut Request<Web>) -> tide::Result<Project> {
+    let state = request.state().clone();
+    let _job = state.arc.app.jobs.start()
+        .ok_or_else(|| tide::Error::new(503, Error::ShuttingDown))?;
+
+    let mut sink = request.as_sink()?;
+    let (mut dir, project_id) = Project::create_dir(&mut sink)?;
+
+    let limits = &state.arc.app.limits;
+    let limit = limits.upload_size().min(limits.pdf_size());
+    // The pdf itself is validated by the explode.
+    let source = match store_body(request, &mut dir, limit).await {
+        Ok(source) => source,
+        Err(err) => {
+            let _ = fs::remove_dir_all(dir.work_dir());
+            return Err(err);
+        }
+    };
+
+    let mut project = Project::with_source(dir, project_id, source)?;
+    if let Err(err) = project.explode(&request.state().arc.app) {
+        let _ = fs::remove_dir_all(project.dir.work_dir());
+        return Err(err.into());
+    }
+
+    project.thumbnail()?;
+    Ok(project)
+}
+
+/// Stream the request body to a file, enforcing a size limit.
+async fn store_body(request: &mut Request<Web>, sink: &mut sink::Sink, limit: u64)
+    -> tide::Result<path::PathBuf>
+{
+    // Reject early if the client announced the size.
+    if request.len().map_or(false, |len| len as u64 > limit) {
+        return Err(tide::Error::new(413, Error::UploadTooLarge));
+    }
+
+    match sink.store_from_reader(request, limit).await? {
+        Some(path) => Ok(path),
+        None => Err(tide::Error::new(413, Error::UploadTooLarge)),
+    }
+}
+
+fn require_pdf(request: &Request<Web>) -> tide::Result<()> {
+    // TODO: constify.
+    let mime_pdf: mime::Mime = "application/pdf".parse().unwrap();
+
+    match request.content_type() {
+        Some(mime) if mime.essence() == mime_pdf.essence() => Ok(()),
+        _ => Err(tide::Error::new(415, Error::OnlyPdfAccepted)),
+    }
 }
 
 async fn tide_set_audio(mut request: Request<Web>)
@@ -311,22 +797,143 @@ async fn tide_set_audio(mut request: Request<Web>)
         Err(_) => return Err(tide::Error::new(404, Error::NoSuchProject)),
     };
 
-    let mut body = request
-        .body_bytes()
-        .await
-        .map(io::Cursor::new)?;
+    let mut project = request.require_project()?;
+    if idx >= project.meta.slides.len() {
+        return Err(tide::Error::new(404, Error::NoSuchPage));
+    }
+
+    let limit = request.state().arc.app.limits.upload_size();
+    let src = store_body(&mut request, &mut project.dir, limit).await?;
+    project.attach_audio(idx, src);
+    project.analyze_audio(&request.state().arc.app)?;
+    project.store()?;
+
+    Ok(tide_project_state(&request, &project)?)
+}
+
+async fn tide_audio_batch(mut request: Request<Web>)
+    -> tide::Result<tide::Response>
+{
+    let state = request.state().clone();
+    let _job = state.arc.app.jobs.start()
+        .ok_or_else(|| tide::Error::new(503, Error::ShuttingDown))?;
 
     let mut project = request.require_project()?;
-    let mut source = sink::BufSource::from(&mut body);
+    let limit = request.state().arc.app.limits.upload_size();
+
+    let archive = store_body(&mut request, &mut project.dir, limit).await?;
+    let attached = project.attach_audio_archive(&archive, limit);
+    let _ = fs::remove_file(&archive);
+    attached?;
+
+    project.analyze_audio(&state.arc.app)?;
+    project.store()?;
+    Ok(tide_project_state(&request, &project)?)
+}
+
+async fn tide_recording(mut request: Request<Web>)
+    -> tide::Result<tide::Response>
+{
+    #[derive(serde::Deserialize)]
+    struct Query {
+        markers: Option<String>,
+    }
+
+    let state = request.state().clone();
+    let _job = state.arc.app.jobs.start()
+        .ok_or_else(|| tide::Error::new(503, Error::ShuttingDown))?;
+
+    let query: Query = request.query()
+        .map_err(|_| tide::Error::new(400, Error::InvalidQuery))?;
+    let split = match query.markers {
+        Some(markers) => Split::from_markers(&markers)
+            .ok_or_else(|| tide::Error::new(400, Error::InvalidQuery))?,
+        None => Split::Silence,
+    };
+
+    let mut project = request.require_project()?;
+    let limit = request.state().arc.app.limits.upload_size();
+
+    let recording = store_body(&mut request, &mut project.dir, limit).await?;
+    let attached = project.attach_recording(&recording, &split, &state.arc.app);
+    let _ = fs::remove_file(&recording);
+    attached?;
+
+    project.analyze_audio(&state.arc.app)?;
+    project.store()?;
+    Ok(tide_project_state(&request, &project)?)
+}
+
+async fn tide_trim(request: Request<Web>)
+    -> tide::Result<tide::Response>
+{
+    #[derive(serde::Deserialize)]
+    struct Query {
+        start: Option<f32>,
+        end: Option<f32>,
+    }
+
+    let query: Query = request.query()
+        .map_err(|_| tide::Error::new(400, Error::InvalidQuery))?;
+    let idx: usize = request.param("num")
+        .ok()
+        .and_then(|num| num.parse().ok())
+        .ok_or_else(|| tide::Error::new(404, Error::NoSuchPage))?;
 
-    project.import_audio(idx, &mut source)?;
+    let trim = match query {
+        Query { start: None, end: None } => None,
+        Query { start, end } => Some(Trim::new(start.unwrap_or(0.0), end)
+            .ok_or_else(|| tide::Error::new(400, Error::InvalidQuery))?),
+    };
+
+    let mut project = request.require_project()?;
+    let slide = project.meta.slides.get_mut(idx)
+        .ok_or_else(|| tide::Error::new(404, Error::NoSuchPage))?;
+    slide.trim = trim;
     project.store()?;
 
-    Ok(tide_project_state(&project)?)
+    Ok(tide_project_state(&request, &project)?)
 }
 
-fn tide_project_state(project: &Project) -> tide::Result<tide::Response> {
-    let body = tide::Body::from_json(&serialize_project(project))?;
+async fn tide_thumbnail(request: Request<Web>)
+    -> tide::Result<tide::Response>
+{
+    let state = request.state().clone();
+    let _job = state.arc.app.jobs.start()
+        .ok_or_else(|| tide::Error::new(503, Error::ShuttingDown))?;
+
+    #[derive(serde::Deserialize)]
+    struct Query {
+        w: Option<u32>,
+    }
+
+    let query: Query = request.query()
+        .map_err(|_| tide::Error::new(400, Error::InvalidQuery))?;
+    let idx: usize = request.param("num")
+        .ok()
+        .and_then(|num| num.parse().ok())
+        .ok_or_else(|| tide::Error::new(404, Error::NoSuchPage))?;
+
+    let mut project = request.require_project()?;
+    if idx >= project.meta.slides.len() {
+        return Err(tide::Error::new(404, Error::NoSuchPage));
+    }
+
+    let width = query.w.unwrap_or(Web::THUMBNAIL_WIDTH);
+    let converted = project.meta.slides[idx].png.is_none();
+    let path = project.page_thumbnail(idx, width, &request.state().arc.app)?;
+    // The thumbnails of all pages are requested at once, each may have produced the png of its
+    // page. Merged, as the others store theirs as well.
+    if converted && project.meta.slides[idx].png.is_some() {
+        project.store_pixmaps()?;
+    }
+
+    serve_file(&request, &path).await
+}
+
+fn tide_project_state(request: &Request<Web>, project: &Project) -> tide::Result<tide::Response> {
+    let base = request.project_base(project);
+    let body = tide::Body::from_json(&serialize_project(project, &base))?;
 
     let response = tide::Response::builder(201)
         .body(body)
@@ -340,8 +947,75 @@ fn tide_project_state(project: &Project) -> tide::Result<tide::Response> {
 enum Error {
     AssetNotFound,
     InternalServerError,
+    InvalidQuery,
+    LoginRequired,
+    NoSuchPage,
     NoSuchProject,
+    NotRendered,
     OnlyPdfAccepted,
+    ShuttingDown,
+    Unauthorized,
+    UploadTooLarge,
+}
+
+/// The body of every failed request.
+#[derive(Clone, Debug, Serialize)]
+struct ApiError {
+    /// Stable identifier of the kind of error, for clients to match on.
+    code: &'static str,
+    /// Human readable description.
+    message: String,
+    /// Further information, such as the complaint of a tool about the input.
+    detail: Option<String>,
+}
+
+impl Error {
+    fn code(&self) -> &'static str {
+        match self {
+            Error::AssetNotFound => "asset_not_found",
+            Error::InternalServerError => "internal",
+            Error::InvalidQuery => "invalid_query",
+            Error::LoginRequired => "login_required",
+            Error::NoSuchPage => "no_such_page",
+            Error::NoSuchProject => "no_such_project",
+            Error::NotRendered => "not_rendered",
+            Error::OnlyPdfAccepted => "only_pdf_accepted",
+            Error::ShuttingDown => "shutting_down",
+            Error::Unauthorized => "unauthorized",
+            Error::UploadTooLarge => "upload_too_large",
+        }
+    }
+}
+
+impl ApiError {
+    fn from_tide(err: &tide::Error) -> Self {
+        if let Some(err) = err.downcast_ref::<ApiError>() {
+            return err.clone();
+        }
+
+        if let Some(err) = err.downcast_ref::<Error>() {
+            return ApiError {
+                code: err.code(),
+                message: err.to_string(),
+                detail: None,
+            };
+        }
+
+        // Errors of tide itself, such as a malformed body. Don't leak anything internal.
+        if err.status().is_server_error() {
+            ApiError {
+                code: Error::InternalServerError.code(),
+                message: Error::InternalServerError.to_string(),
+                detail: None,
+            }
+        } else {
+            ApiError {
+                code: "bad_request",
+                message: err.to_string(),
+                detail: None,
+            }
+        }
+    }
 }
 
 impl fmt::Display for Error {
@@ -349,32 +1023,172 @@ impl fmt::Display for Error {
         match self {
             Error::AssetNotFound => f.write_str("No such asset."),
             Error::InternalServerError => f.write_str("An internal server error occurred."),
+            Error::InvalidQuery => f.write_str("Invalid query parameters."),
+            Error::LoginRequired => f.write_str("Please log in."),
+            Error::NoSuchPage => f.write_str("The project has no such page."),
             Error::NoSuchProject => f.write_str("This project has been deleted."),
+            Error::NotRendered => f.write_str("The project has not been rendered yet."),
             Error::OnlyPdfAccepted => f.write_str("Only pdf is accepted."),
+            Error::ShuttingDown => f.write_str("The server is shutting down, please try again later."),
+            Error::Unauthorized => f.write_str("Missing or wrong project token."),
+            Error::UploadTooLarge => f.write_str("The uploaded file is too large."),
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
 impl From<FatalError> for tide::Error {
     fn from(err: FatalError) -> tide::Error {
-        eprintln!("{:?}", err);
-        tide::Error::new(500, Error::InternalServerError)
+        let code = match &err {
+            FatalError::Limit(_) => "limit_exceeded",
+            FatalError::BadPdf(_) => "bad_pdf",
+            FatalError::BadArchive(_) => "bad_archive",
+            FatalError::BadAudio { .. } => "bad_audio",
+            FatalError::Svg(_) => "bad_page",
+            FatalError::Tool { .. } => "tool_failed",
+            FatalError::Conflict => "conflict",
+            FatalError::Ffmpeg(err) if err.missing_codec().is_some() => "missing_codec",
+            FatalError::Ffmpeg(_) => "ffmpeg_failed",
+            FatalError::Io(_) => "unavailable",
+            FatalError::Corrupt(_)
+            | FatalError::Image(_)
+            | FatalError::UnrecognizedInputSlide => Error::InternalServerError.code(),
+        };
+
+        let status = match err.class() {
+            // Not a problem of the request itself, sending it again after a reload works.
+            _ if matches!(err, FatalError::Conflict) => 409,
+            ErrorClass::User => {
+                tracing::warn!(code, "{:?}", err);
+                422
+            }
+            // Not the fault of the client, but retrying later or elsewhere may work.
+            ErrorClass::Environment => {
+                tracing::error!(code, "{:?}", err);
+                503
+            }
+            ErrorClass::Bug => {
+                tracing::error!("{:?}", err);
+                return tide::Error::new(500, Error::InternalServerError);
+            }
+        };
+
+        // Details about the system only help an attacker, the log has them. The complaints of
+        // tools are about the input, or at least tell the user what to report.
+        let detail = match err {
+            FatalError::Io(_) => None,
+            _ => err.detail(),
+        };
+
+        tide::Error::new(status, ApiError {
+            code,
+            message: err.to_string(),
+            detail,
+        })
+    }
+}
+
+/// Require a login when the server is shared by several users.
+struct Authenticate;
+
+#[tide::utils::async_trait]
+impl tide::Middleware<Web> for Authenticate {
+    async fn handle(&self, mut request: Request<Web>, next: tide::Next<'_, Web>) -> tide::Result {
+        // Share links are signed and grant access by themselves. Supervisors need no login.
+        let path = request.url().path();
+        if path.starts_with("/share/") || path == "/healthz" || path == "/readyz" {
+            return Ok(next.run(request).await);
+        }
+
+        let user = match &request.state().arc.app.users {
+            None => None,
+            Some(users) => {
+                let user = request
+                    .header("Authorization")
+                    .and_then(|value| users.authenticate(value.as_str()));
+                match user {
+                    Some(user) => Some(user),
+                    None => {
+                        let mut response = tide::Response::builder(401)
+                            .header("WWW-Authenticate", "Basic realm=\"vid-from-pdf\"")
+                            .build();
+                        response.set_error(tide::Error::new(401, Error::LoginRequired));
+                        return Ok(response);
+                    }
+                }
+            }
+        };
+
+        if let Some(user) = user {
+            request.set_ext(user);
+        }
+
+        Ok(next.run(request).await)
     }
 }
 
 trait TideAppProject {
-    fn project(&self) -> Result<Option<Project>, FatalError>;
+    fn project(&self) -> tide::Result<Option<Project>>;
     fn require_project(&self) -> tide::Result<Project>;
-    fn as_sink(&self) -> sink::Sink;
+    /// The projects of the authenticated user, or all projects if there are no users.
+    fn as_sink(&self) -> Result<sink::Sink, FatalError>;
+    /// The url prefix under which this request reaches the resources of the project.
+    fn project_base(&self, project: &Project) -> String;
 }
 
 impl TideAppProject for Request<Web> {
-    fn project(&self) -> Result<Option<Project>, FatalError> {
+    fn project(&self) -> tide::Result<Option<Project>> {
+        let app = &self.state().arc.app;
+        let sink = self.as_sink()?;
+        // A render in another request reports its progress through this project.
+        let observe = |mut project: Project| {
+            if let Some(progress) = self.state().arc.progress(&project.project_id) {
+                project.progress = progress;
+            }
+            project
+        };
+
+        // Explicit project in the path, authorized by token instead of a session.
+        if let Ok(project_id) = self.param("project") {
+            let identifier = match decode_identifier(project_id) {
+                Some(identifier) => identifier,
+                None => return Ok(None),
+            };
+
+            let project = match Project::load(app, &sink, identifier)? {
+                Some(project) => project,
+                None => return Ok(None),
+            };
+
+            // A login of a shared instance takes the `Authorization` header.
+            let token = self
+                .header("X-Project-Token")
+                .map(|value| value.as_str())
+                .or_else(|| {
+                    self.header("Authorization")
+                        .and_then(|value| value.as_str().strip_prefix("Bearer "))
+                });
+
+            return match (&project.meta.token, token) {
+                (Some(expected), Some(token)) if constant_time_eq(expected, token) => {
+                    Ok(Some(observe(project)))
+                }
+                _ => Err(tide::Error::new(403, Error::Unauthorized)),
+            };
+        }
+
         match self.session().get(Web::PROJECT_ID) {
-            None => return Ok(None),
-            Some(identifier) => Project::load(&self.state().arc.app, identifier),
+            None => Ok(None),
+            Some(identifier) => Ok(Project::load(app, &sink, identifier)?.map(observe)),
         }
     }
     fn require_project(&self) -> tide::Result<Project> {
@@ -383,7 +1197,33 @@ impl TideAppProject for Request<Web> {
             Some(project) => Ok(project),
         }
     }
-    fn as_sink(&self) -> sink::Sink {
-        self.state().arc.app.sink.as_sink()
+    fn as_sink(&self) -> Result<sink::Sink, FatalError> {
+        let sink = self.state().arc.app.sink.as_sink();
+        match self.ext::<User>() {
+            None => Ok(sink),
+            Some(user) => user.namespace(&sink),
+        }
+    }
+    fn project_base(&self, project: &Project) -> String {
+        if self.param("project").is_ok() {
+            api_project_base(project)
+        } else {
+            "/project".into()
+        }
     }
 }
+
+fn api_project_base(project: &Project) -> String {
+    format!("/api/project/{}", base64::encode_config(&project.project_id, base64::URL_SAFE))
+}
+
+fn decode_identifier(encoded: &str) -> Option<sink::Identifier> {
+    let bytes = base64::decode_config(encoded, base64::URL_SAFE).ok()?;
+    let mut identifier = sink::Identifier::default();
+    if bytes.len() != identifier.len() {
+        return None;
+    }
+    identifier.copy_from_slice(&bytes);
+    Some(identifier)
+}
+
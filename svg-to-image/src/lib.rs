@@ -16,6 +16,10 @@ pub struct Error {
 #[derive(Clone)]
 pub struct MagickConvert {
     magick: which::CanonicalPath,
+    /// A program and its arguments that runs the conversion, such as a sandbox.
+    wrapper: Vec<std::ffi::OsString>,
+    /// An ICC profile the output is converted to, after converting to sRGB.
+    profile: Option<std::path::PathBuf>,
 }
 
 #[derive(Debug)]
@@ -63,6 +67,18 @@ impl Svg {
         }
     }
 
+    /// The command line of `magick` which `render` runs with the svg on stdin, if any.
+    pub fn command_line(&self) -> Option<Vec<std::ffi::OsString>> {
+        if cfg!(render_pathfinder) || cfg!(render_resvg) {
+            return None;
+        }
+
+        let mut line = self.magick.wrapper.clone();
+        line.push(self.magick.magick.as_os_str().to_owned());
+        line.extend(self.magick.convert_args());
+        Some(line)
+    }
+
     fn render_convert(&self, magick: &MagickConvert) -> Result<image::DynamicImage, Error> {
         let tree_data = match &self.data {
             Some(data) => data.clone(),
@@ -71,11 +87,8 @@ impl Svg {
             }
         };
 
-        let exec = subprocess::Exec::cmd(&magick.magick)
-            .arg("convert")
-            .arg("-verbose")
-            .arg("svg:-")
-            .arg("ppm:-")
+        let exec = magick.command()
+            .args(&magick.convert_args())
             .stdin(tree_data)
             .stdout(subprocess::Redirection::Pipe)
             .stderr(subprocess::Redirection::Pipe)
@@ -165,6 +178,12 @@ impl Svg {
 
 impl MagickConvert {
     pub const MAGICK: &'static str = "magick";
+    /// Arguments reading an svg from stdin.
+    const INPUT: &'static [&'static str] = &["convert", "-verbose", "svg:-"];
+    /// Convert CMYK and wide gamut images explicitly, the output has no room for a profile.
+    const COLORSPACE: &'static [&'static str] = &["-colorspace", "sRGB"];
+    /// Write a ppm to stdout.
+    const OUTPUT: &'static str = "ppm:-";
 
     pub fn new(magick: which::CanonicalPath) -> Result<Self, Error> {
         let formats = subprocess::Exec::cmd(&magick)
@@ -207,13 +226,46 @@ impl MagickConvert {
 
         Ok(MagickConvert {
             magick,
+            wrapper: vec![],
+            profile: None,
         })
     }
 
+    /// Run conversions through another program, which is passed the command line of `magick`.
+    pub fn with_wrapper(self, wrapper: Vec<std::ffi::OsString>) -> Self {
+        MagickConvert { wrapper, ..self }
+    }
+
+    /// Convert the colors with this ICC profile, such as one of sRGB, instead of only by name.
+    pub fn with_profile(self, profile: std::path::PathBuf) -> Self {
+        MagickConvert { profile: Some(profile), ..self }
+    }
+
     pub fn path(&self) -> &Path {
         self.magick.as_path()
     }
 
+    /// Arguments converting an svg on stdin to a ppm in sRGB on stdout.
+    fn convert_args(&self) -> Vec<std::ffi::OsString> {
+        let mut args: Vec<std::ffi::OsString> = Self::INPUT.iter().map(Into::into).collect();
+        args.extend(Self::COLORSPACE.iter().map(Into::into));
+        if let Some(profile) = &self.profile {
+            args.push("-profile".into());
+            args.push(profile.as_os_str().to_owned());
+        }
+        args.push(Self::OUTPUT.into());
+        args
+    }
+
+    fn command(&self) -> subprocess::Exec {
+        match self.wrapper.split_first() {
+            Some((program, args)) => subprocess::Exec::cmd(program)
+                .args(args)
+                .arg(self.magick.as_path()),
+            None => subprocess::Exec::cmd(&self.magick),
+        }
+    }
+
     pub fn open(&self, path: &Path) -> Result<Svg, Error> {
         let mut options = usvg::Options::default();
         options.fontdb.load_system_fonts();
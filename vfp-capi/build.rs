@@ -0,0 +1,17 @@
+/// Generate the header into `OUT_DIR`, the source directory may be read-only.
+///
+/// The checked-in `include/vfp.h` is regenerated when building with `VFP_UPDATE_HEADER=1`.
+fn main() {
+    let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    let config = cbindgen::Config::from_file("cbindgen.toml").unwrap();
+    let bindings = cbindgen::generate_with_config(&dir, config)
+        .expect("Generating the header");
+    bindings.write_to_file(out.join("vfp.h"));
+    if std::env::var_os("VFP_UPDATE_HEADER").is_some() {
+        bindings.write_to_file("include/vfp.h");
+    }
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed=VFP_UPDATE_HEADER");
+}
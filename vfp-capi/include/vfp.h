@@ -0,0 +1,108 @@
+#ifndef VFP_H
+#define VFP_H
+
+/* Generated by cbindgen from src/lib.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum VfpStage {
+  VFP_STAGE_IDLE,
+  VFP_STAGE_EXPLODE,
+  VFP_STAGE_RENDER,
+  VFP_STAGE_ENCODE,
+  /**
+   * Waiting for other projects, `total` is the position in the queue.
+   */
+  VFP_STAGE_QUEUED,
+} VfpStage;
+
+/**
+ * The tools and the directory of projects.
+ */
+typedef struct VfpPipeline VfpPipeline;
+
+/**
+ * Watches a project from another thread, while it is worked on.
+ */
+typedef struct VfpProgressHandle VfpProgressHandle;
+
+/**
+ * A pdf with its slides and their narration.
+ */
+typedef struct VfpProject VfpProject;
+
+typedef struct VfpProgress {
+  VfpStage stage;
+  size_t done;
+  /**
+   * The number of steps in the stage, `0` if not known.
+   */
+  size_t total;
+} VfpProgress;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Describe the last failure on this thread, `NULL` if there was none.
+ *
+ * The string stays valid until the next call on this thread.
+ */
+const char *vfp_last_error(void);
+
+/**
+ * Find the tools in `PATH` and keep projects in a temporary directory.
+ */
+VfpPipeline *vfp_pipeline_new(void);
+
+/**
+ * Release the pipeline, after all of its projects.
+ */
+void vfp_pipeline_free(VfpPipeline *pipeline);
+
+/**
+ * Create a project with a copy of the pdf, then `vfp_project_explode` makes its slides.
+ */
+VfpProject *vfp_project_create(const VfpPipeline *pipeline, const char *pdf);
+
+void vfp_project_free(VfpProject *project);
+
+/**
+ * Make one slide of each page. Returns the number of slides.
+ */
+int vfp_project_explode(const VfpPipeline *pipeline, VfpProject *project);
+
+/**
+ * Narrate the slide `idx`, counted from zero, with a copy of an audio file.
+ */
+int vfp_project_attach_audio(const VfpPipeline *pipeline,
+                             VfpProject *project,
+                             size_t idx,
+                             const char *audio);
+
+/**
+ * Generate the video. Returns its path, to be released with `vfp_string_free`.
+ */
+char *vfp_project_render(const VfpPipeline *pipeline, VfpProject *project);
+
+/**
+ * Get a handle to poll the progress of the project, while another thread explodes or renders it.
+ */
+VfpProgressHandle *vfp_project_progress(const VfpProject *project);
+
+VfpProgress vfp_progress_poll(const VfpProgressHandle *handle);
+
+void vfp_progress_free(VfpProgressHandle *handle);
+
+void vfp_string_free(char *string);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* VFP_H */
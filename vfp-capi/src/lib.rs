@@ -0,0 +1,246 @@
+//! C bindings of the conversion, see `include/vfp.h`.
+//!
+//! All functions that can fail return `NULL` or a negative number, then `vfp_last_error` describes
+//! the failure. Strings returned to the caller must be released with `vfp_string_free`.
+//!
+//! # Safety
+//!
+//! Pointers must be `NULL` or come from this library and not yet be released. A project must not
+//! be used by two threads at once, watch it with `vfp_project_progress` instead.
+#![allow(clippy::missing_safety_doc)]
+use std::{cell::RefCell, ptr, sync::Arc};
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+
+use vid_from_pdf::FatalError;
+use vid_from_pdf::pipeline::Pipeline;
+use vid_from_pdf::progress::{Progress, Stage};
+use vid_from_pdf::project::Project;
+
+/// The tools and the directory of projects.
+pub struct VfpPipeline(Pipeline);
+
+/// A pdf with its slides and their narration.
+pub struct VfpProject(Project);
+
+/// Watches a project from another thread, while it is worked on.
+pub struct VfpProgressHandle(Arc<Progress>);
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum VfpStage {
+    Idle,
+    Explode,
+    Render,
+    Encode,
+    /// Waiting for other projects, `total` is the position in the queue.
+    Queued,
+}
+
+#[repr(C)]
+pub struct VfpProgress {
+    pub stage: VfpStage,
+    pub done: usize,
+    /// The number of steps in the stage, `0` if not known.
+    pub total: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Describe the last failure on this thread, `NULL` if there was none.
+///
+/// The string stays valid until the next call on this thread.
+#[no_mangle]
+pub extern "C" fn vfp_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match &*last.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Find the tools in `PATH` and keep projects in a temporary directory.
+#[no_mangle]
+pub extern "C" fn vfp_pipeline_new() -> *mut VfpPipeline {
+    guard(ptr::null_mut(), || {
+        let pipeline = Pipeline::new()?;
+        Ok(Box::into_raw(Box::new(VfpPipeline(pipeline))))
+    })
+}
+
+/// Release the pipeline, after all of its projects.
+#[no_mangle]
+pub unsafe extern "C" fn vfp_pipeline_free(pipeline: *mut VfpPipeline) {
+    if !pipeline.is_null() {
+        drop(Box::from_raw(pipeline));
+    }
+}
+
+/// Create a project with a copy of the pdf, then `vfp_project_explode` makes its slides.
+#[no_mangle]
+pub unsafe extern "C" fn vfp_project_create(
+    pipeline: *const VfpPipeline,
+    pdf: *const c_char,
+) -> *mut VfpProject {
+    guard(ptr::null_mut(), || {
+        let pipeline = &require(pipeline)?.0;
+        let project = pipeline.create(&path(pdf)?)?;
+        Ok(Box::into_raw(Box::new(VfpProject(project))))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vfp_project_free(project: *mut VfpProject) {
+    if !project.is_null() {
+        drop(Box::from_raw(project));
+    }
+}
+
+/// Make one slide of each page. Returns the number of slides.
+#[no_mangle]
+pub unsafe extern "C" fn vfp_project_explode(
+    pipeline: *const VfpPipeline,
+    project: *mut VfpProject,
+) -> c_int {
+    guard(-1, || {
+        let pipeline = &require(pipeline)?.0;
+        let project = &mut require_mut(project)?.0;
+        pipeline.explode(project)?;
+        Ok(project.meta.slides.len() as c_int)
+    })
+}
+
+/// Narrate the slide `idx`, counted from zero, with a copy of an audio file.
+#[no_mangle]
+pub unsafe extern "C" fn vfp_project_attach_audio(
+    pipeline: *const VfpPipeline,
+    project: *mut VfpProject,
+    idx: usize,
+    audio: *const c_char,
+) -> c_int {
+    guard(-1, || {
+        let pipeline = &require(pipeline)?.0;
+        let project = &mut require_mut(project)?.0;
+        pipeline.attach_audio(project, idx, &path(audio)?)?;
+        Ok(0)
+    })
+}
+
+/// Generate the video. Returns its path, to be released with `vfp_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn vfp_project_render(
+    pipeline: *const VfpPipeline,
+    project: *mut VfpProject,
+) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let pipeline = &require(pipeline)?.0;
+        let project = &mut require_mut(project)?.0;
+        let video = pipeline.render(project)?;
+        Ok(string(video.to_string_lossy().into_owned())?.into_raw())
+    })
+}
+
+/// Get a handle to poll the progress of the project, while another thread explodes or renders it.
+#[no_mangle]
+pub unsafe extern "C" fn vfp_project_progress(project: *const VfpProject) -> *mut VfpProgressHandle {
+    guard(ptr::null_mut(), || {
+        let project = &require(project)?.0;
+        let handle = VfpProgressHandle(project.progress.clone());
+        Ok(Box::into_raw(Box::new(handle)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vfp_progress_poll(handle: *const VfpProgressHandle) -> VfpProgress {
+    let idle = VfpProgress { stage: VfpStage::Idle, done: 0, total: 0 };
+    let handle = match handle.as_ref() {
+        Some(handle) => handle,
+        None => return idle,
+    };
+
+    let snapshot = handle.0.snapshot();
+    VfpProgress {
+        stage: match snapshot.stage {
+            Stage::Idle => VfpStage::Idle,
+            Stage::Explode => VfpStage::Explode,
+            Stage::Render => VfpStage::Render,
+            Stage::Encode => VfpStage::Encode,
+            Stage::Queued => VfpStage::Queued,
+        },
+        done: snapshot.done,
+        total: snapshot.total,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vfp_progress_free(handle: *mut VfpProgressHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vfp_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Run `work`, keeping its error or panic for `vfp_last_error` and returning `failed` instead.
+fn guard<T>(failed: T, work: impl FnOnce() -> Result<T, FatalError>) -> T {
+    // Nothing is used after a panic but the error message.
+    let message = match panic::catch_unwind(AssertUnwindSafe(work)) {
+        Ok(Ok(value)) => {
+            LAST_ERROR.with(|last| *last.borrow_mut() = None);
+            return value;
+        }
+        Ok(Err(err)) => format!("{:?}", err),
+        Err(_) => String::from("The conversion panicked"),
+    };
+
+    let message = CString::new(message.replace('\0', " ")).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    failed
+}
+
+unsafe fn require<'a, T>(ptr: *const T) -> Result<&'a T, FatalError> {
+    ptr.as_ref().ok_or_else(null_argument)
+}
+
+unsafe fn require_mut<'a, T>(ptr: *mut T) -> Result<&'a mut T, FatalError> {
+    ptr.as_mut().ok_or_else(null_argument)
+}
+
+fn null_argument() -> FatalError {
+    invalid("An argument was `NULL`")
+}
+
+unsafe fn path(ptr: *const c_char) -> Result<PathBuf, FatalError> {
+    if ptr.is_null() {
+        return Err(null_argument());
+    }
+
+    let bytes = CStr::from_ptr(ptr).to_bytes();
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt as _;
+        Ok(std::ffi::OsStr::from_bytes(bytes).into())
+    }
+    #[cfg(not(unix))]
+    {
+        std::str::from_utf8(bytes)
+            .map(PathBuf::from)
+            .map_err(|_| invalid("A path is not valid utf-8"))
+    }
+}
+
+fn string(value: String) -> Result<CString, FatalError> {
+    CString::new(value).map_err(|_| invalid("A string contains a nul byte"))
+}
+
+fn invalid(message: &str) -> FatalError {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message).into()
+}